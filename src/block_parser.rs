@@ -12,6 +12,11 @@ pub trait BlocksParser: Send + Sync {
     ///
     /// The blocks are required to be sorted by the `starts_at` field in ascending order.
     fn parse(&mut self, contents: &str) -> anyhow::Result<Vec<Block>>;
+
+    /// Returns the tree-sitter syntax tree of the given `contents` string, if the language has one.
+    fn syntax_tree(&mut self, _contents: &str) -> Option<tree_sitter::Tree> {
+        None
+    }
 }
 
 pub struct BlocksFromCommentsParser<C: CommentsParser> {
@@ -28,6 +33,10 @@ impl<C: CommentsParser> BlocksParser for BlocksFromCommentsParser<C> {
     fn parse(&mut self, contents: &str) -> anyhow::Result<Vec<Block>> {
         parse_blocks_from_comments(self.comments_parser.parse(contents))
     }
+
+    fn syntax_tree(&mut self, contents: &str) -> Option<tree_sitter::Tree> {
+        self.comments_parser.syntax_tree(contents)
+    }
 }
 
 /// Parses blocks from comments iterator.
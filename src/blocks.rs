@@ -1,10 +1,11 @@
 use crate::Position;
-use crate::diff_parser::LineChange;
+use crate::diff_parser::{LineChange, PreImage};
 use crate::language_parsers::{LanguageParser, LanguageParsers};
 use anyhow::{Context, anyhow};
 use globset::GlobSet;
 use ignore::Walk;
 use serde_repr::Serialize_repr;
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::ffi::OsString;
@@ -269,7 +270,8 @@ pub struct BlockWithContext {
 /// - `should_scan_files` indicates whether all the files in filesystem should be scanned for blocks.
 /// - `file_system` provides access to file contents within a root path.
 /// - `parsers` maps file extensions to language-specific block parsers.
-/// - `extra_file_extensions` allows remapping unknown extensions to supported ones (e.g., "cxx" -> "cpp").
+/// - `extra_file_extensions` allows remapping unknown extensions to supported ones (e.g., "cxx" -> "cpp"),
+///   optionally only for some paths.
 ///
 /// Returns a map of file paths to the list of intersecting blocks found in that file.
 pub fn parse_blocks(
@@ -278,7 +280,7 @@ pub fn parse_blocks(
     file_system: &impl FileSystem,
     path_checker: &impl PathChecker,
     parsers: &LanguageParsers,
-    extra_file_extensions: HashMap<OsString, OsString>,
+    extra_file_extensions: ExtensionMappings,
 ) -> anyhow::Result<HashMap<PathBuf, FileBlocks>> {
     let mut blocks = HashMap::new();
     if should_scan_files {
@@ -336,6 +338,77 @@ pub fn parse_blocks(
     Ok(blocks)
 }
 
+/// Parses the pre-images of the files changed or removed by a diff and returns the blocks that no
+/// longer exist after the change, grouped by their paths before the change.
+///
+/// Blocks of the old and the new contents are matched by name first, then by unchanged content
+/// (e.g. a renamed block) and finally unnamed blocks by attributes; the unmatched old blocks are
+/// deleted. All the blocks of removed or renamed files are deleted. Files whose pre-image can't be
+/// reconstructed (see [`PreImage::contents`]) are skipped.
+pub fn parse_deleted_blocks(
+    pre_images: &HashMap<PathBuf, PreImage>,
+    file_system: &impl FileSystem,
+    path_checker: &impl PathChecker,
+    parsers: &LanguageParsers,
+    extra_file_extensions: &ExtensionMappings,
+) -> anyhow::Result<HashMap<PathBuf, Vec<Block>>> {
+    let mut deleted_blocks = HashMap::new();
+    for (file_path, pre_image) in pre_images {
+        if path_checker.should_ignore(file_path) {
+            continue;
+        }
+        if language_parser(file_path, parsers, extra_file_extensions).is_none() {
+            continue;
+        }
+        let new_contents = pre_image
+            .target_path
+            .as_ref()
+            .and_then(|target_path| file_system.read_to_string(target_path).ok());
+        let Some(old_contents) = pre_image.contents(new_contents.as_deref()) else {
+            continue;
+        };
+        let parse = |source: &str| {
+            parse_source(file_path, source, parsers, extra_file_extensions)
+                .map(Option::unwrap_or_default)
+        };
+        let new_contents = match new_contents {
+            Some(new_contents) if pre_image.target_path.as_ref() == Some(file_path) => new_contents,
+            _ => String::new(),
+        };
+        let mut remaining_blocks = parse(&new_contents)?;
+        let mut old_blocks = parse(&old_contents)?;
+        // Blocks keep their identity when renamed or retagged without changing their content.
+        type Matcher<'a> = &'a dyn Fn(&Block, &Block) -> bool;
+        let matchers: [Matcher; 3] = [
+            &|old, new| old.name().is_some() && old.name() == new.name(),
+            &|old, new| {
+                let content = old.content(&old_contents);
+                !content.trim().is_empty() && content == new.content(&new_contents)
+            },
+            &|old, new| old.name().is_none() && old.attributes == new.attributes,
+        ];
+        for is_same_block in matchers {
+            old_blocks.retain(|old_block| {
+                match remaining_blocks
+                    .iter()
+                    .position(|block| is_same_block(old_block, block))
+                {
+                    Some(index) => {
+                        remaining_blocks.swap_remove(index);
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+        let deleted = old_blocks;
+        if !deleted.is_empty() {
+            deleted_blocks.insert(file_path.clone(), deleted);
+        }
+    }
+    Ok(deleted_blocks)
+}
+
 enum BlocksFilter {
     All,
     ModifiedOnly,
@@ -347,9 +420,13 @@ fn parse_file(
     blocks_filter: BlocksFilter,
     file_reader: &impl FileSystem,
     parsers: &LanguageParsers,
-    extra_file_extensions: &HashMap<OsString, OsString>,
+    extra_file_extensions: &ExtensionMappings,
 ) -> anyhow::Result<Option<FileBlocks>> {
-    let parser = match parser_for_file_path(file_path, parsers, extra_file_extensions) {
+    let parser = match parser_for_file_path(
+        file_path,
+        parsers,
+        &extra_file_extensions.for_path(file_path),
+    ) {
         None => return Ok(None),
         Some(p) => p,
     };
@@ -387,6 +464,39 @@ fn parse_file(
     }))
 }
 
+/// Parses all the blocks of the `source` of the file at `file_path`.
+///
+/// Returns `None` if the language of the file is not supported.
+pub(crate) fn parse_source(
+    file_path: &Path,
+    source: &str,
+    parsers: &LanguageParsers,
+    extra_file_extensions: &ExtensionMappings,
+) -> anyhow::Result<Option<Vec<Block>>> {
+    let Some(parser) = language_parser(file_path, parsers, extra_file_extensions) else {
+        return Ok(None);
+    };
+    parser
+        .lock()
+        .expect("no active locks")
+        .parse(source)
+        .context(format!("Failed to parse file {file_path:?}"))
+        .map(Some)
+}
+
+/// Returns the parser of the language of the file at `file_path` (if supported).
+pub(crate) fn language_parser<'p>(
+    file_path: &Path,
+    parsers: &'p LanguageParsers,
+    extra_file_extensions: &ExtensionMappings,
+) -> Option<&'p LanguageParser> {
+    parser_for_file_path(
+        file_path,
+        parsers,
+        &extra_file_extensions.for_path(file_path),
+    )
+}
+
 fn parser_for_file_path<'p>(
     file_path: &Path,
     parsers: &'p LanguageParsers,
@@ -419,11 +529,61 @@ fn try_parser_for_extension<'p>(
     parsers.get(ext)
 }
 
+/// User-provided file extension remappings (e.g. "cxx" -> "cpp").
+///
+/// Global mappings apply to every file. Scoped mappings apply only to the files matching their
+/// glob set and take precedence over the global ones (later scopes win over earlier ones).
+#[derive(Debug, Default, Clone)]
+pub struct ExtensionMappings {
+    global: HashMap<OsString, OsString>,
+    scoped: Vec<(GlobSet, HashMap<OsString, OsString>)>,
+}
+
+impl ExtensionMappings {
+    /// Creates mappings that apply to every file.
+    pub fn new(global: HashMap<OsString, OsString>) -> Self {
+        Self {
+            global,
+            scoped: Vec::new(),
+        }
+    }
+
+    /// Adds `mappings` that only apply to the files matching `glob_set`.
+    pub fn with_scoped(mut self, glob_set: GlobSet, mappings: HashMap<OsString, OsString>) -> Self {
+        self.scoped.push((glob_set, mappings));
+        self
+    }
+
+    /// Returns the mappings in effect for `path`.
+    pub(crate) fn for_path(&self, path: &Path) -> Cow<'_, HashMap<OsString, OsString>> {
+        let mut matching_scopes = self
+            .scoped
+            .iter()
+            .filter(|(glob_set, _)| glob_set.is_match(path))
+            .peekable();
+        if matching_scopes.peek().is_none() {
+            return Cow::Borrowed(&self.global);
+        }
+        let mut mappings = self.global.clone();
+        for (_, scoped_mappings) in matching_scopes {
+            mappings.extend(
+                scoped_mappings
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone())),
+            );
+        }
+        Cow::Owned(mappings)
+    }
+}
+
 // `Send + Sync` so an `Arc<Fs>` can be shared into validator threads (std::thread and Tokio tasks).
 pub trait FileSystem: Send + Sync {
     /// Reads the entire contents of a file into a string.
     fn read_to_string(&self, path: &Path) -> anyhow::Result<String>;
 
+    /// Replaces the entire contents of an existing file with `contents`.
+    fn write(&self, path: &Path, contents: &str) -> anyhow::Result<()>;
+
     /// Walks the directory tree rooted at the file system's root path, returning an iterator over the paths of all files.
     fn walk(&self) -> impl Iterator<Item = anyhow::Result<PathBuf>>;
 }
@@ -488,6 +648,12 @@ impl FileSystem for FileSystemImpl {
             .with_context(|| format!("Failed to read file \"{}\"", path.display()))
     }
 
+    fn write(&self, path: &Path, contents: &str) -> anyhow::Result<()> {
+        let resolved = self.resolve_within_root(path)?;
+        std::fs::write(&resolved, contents)
+            .with_context(|| format!("Failed to write file \"{}\"", path.display()))
+    }
+
     fn walk(&self) -> impl Iterator<Item = anyhow::Result<PathBuf>> {
         // Clone root_path for the closure.
         let root_path = self.root_path.clone();
@@ -762,7 +928,7 @@ mod parse_blocks_tests {
             &file_system,
             &FakePathChecker::allow_all(),
             &parsers,
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         assert_eq!(blocks_by_file.len(), 2);
@@ -868,7 +1034,7 @@ mod parse_blocks_tests {
             &file_system,
             &FakePathChecker::allow_all(),
             &parsers,
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         assert_eq!(
@@ -930,7 +1096,7 @@ mod parse_blocks_tests {
             &file_system,
             &FakePathChecker::allow_all(),
             &parsers,
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         assert_eq!(
@@ -977,7 +1143,7 @@ mod parse_blocks_tests {
             &file_system,
             &FakePathChecker::allow_all(),
             &parsers,
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         let content_a = &blocks_by_file[&PathBuf::from("a.rs")].file_content;
@@ -1003,7 +1169,7 @@ mod parse_blocks_tests {
             &file_system,
             &FakePathChecker::allow_all(),
             &parsers,
-            HashMap::from([("rust".into(), "rs".into())]),
+            ExtensionMappings::new(HashMap::from([("rust".into(), "rs".into())])),
         )?;
 
         assert_eq!(blocks_by_file.len(), 1);
@@ -1016,6 +1182,36 @@ mod parse_blocks_tests {
         Ok(())
     }
 
+    #[test]
+    fn with_scoped_remapped_extension_returns_parsed_blocks_from_matching_paths_only()
+    -> anyhow::Result<()> {
+        let file_system = FakeFileSystem::new(HashMap::from([
+            (
+                "third_party/a.inc".to_string(),
+                "// <block name=\"first\">\nint a;\n// </block>".to_string(),
+            ),
+            (
+                "src/b.inc".to_string(),
+                "// <block name=\"second\">\nint b;\n// </block>".to_string(),
+            ),
+        ]));
+        let glob_set = globset::GlobSet::new([globset::Glob::new("third_party/**")?])?;
+
+        let blocks_by_file = parse_blocks(
+            HashMap::new(),
+            true,
+            &file_system,
+            &FakePathChecker::allow_all(),
+            &language_parsers()?,
+            ExtensionMappings::default()
+                .with_scoped(glob_set, HashMap::from([("inc".into(), "cpp".into())])),
+        )?;
+
+        assert_eq!(blocks_by_file.len(), 1);
+        assert!(blocks_by_file.contains_key(&PathBuf::from("third_party/a.inc")));
+        Ok(())
+    }
+
     #[test]
     fn with_unknown_extension_returns_empty_result() -> anyhow::Result<()> {
         let files = HashMap::from([("test.unknown".to_string(), "test content".to_string())]);
@@ -1026,7 +1222,7 @@ mod parse_blocks_tests {
             &FakeFileSystem::new(files),
             &FakePathChecker::allow_all(),
             &HashMap::new(),
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         assert_eq!(blocks.len(), 0);
@@ -1065,7 +1261,7 @@ mod parse_blocks_tests {
             &file_system,
             &path_checker,
             &language_parsers()?,
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         assert_eq!(blocks.len(), 1);
@@ -1083,7 +1279,7 @@ mod parse_blocks_tests {
             &FakeFileSystem::new(HashMap::default()),
             &FakePathChecker::allow_all(),
             &HashMap::new(),
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         assert_eq!(blocks.len(), 0);
@@ -1091,6 +1287,195 @@ mod parse_blocks_tests {
     }
 }
 
+#[cfg(test)]
+mod parse_deleted_blocks_tests {
+    use crate::blocks::*;
+    use crate::diff_parser::pre_images_from_diff;
+    use crate::language_parsers::language_parsers;
+    use crate::test_utils::{FakeFileSystem, FakePathChecker};
+    use std::collections::HashSet;
+
+    fn deleted_block_names(
+        diff: &str,
+        files: &[(&str, &str)],
+        path_checker: &FakePathChecker,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<String>>> {
+        let file_system = FakeFileSystem::new(
+            files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect(),
+        );
+        let deleted_blocks = parse_deleted_blocks(
+            &pre_images_from_diff(diff)?,
+            &file_system,
+            path_checker,
+            &language_parsers()?,
+            &ExtensionMappings::default(),
+        )?;
+        Ok(deleted_blocks
+            .into_iter()
+            .map(|(path, blocks)| {
+                (
+                    path,
+                    blocks
+                        .iter()
+                        .map(|block| block.name_display().to_string())
+                        .collect(),
+                )
+            })
+            .collect())
+    }
+
+    #[test]
+    fn blocks_removed_from_modified_file_are_returned() -> anyhow::Result<()> {
+        let diff = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -4,3 +3,0 @@
+-# <block name="bar">
+-bar
+-# </block>
+"#;
+        let new_contents = "# <block name=\"foo\">\nfoo\n# </block>\n";
+
+        let deleted = deleted_block_names(
+            diff,
+            &[("a.py", new_contents)],
+            &FakePathChecker::allow_all(),
+        )?;
+
+        assert_eq!(
+            deleted,
+            HashMap::from([(PathBuf::from("a.py"), vec!["bar".to_string()])])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn edited_blocks_are_not_deleted() -> anyhow::Result<()> {
+        let diff = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -1,2 +1,2 @@
+-# <block name="foo">
+-foo
++# <block name="foo" keep-sorted>
++new foo
+"#;
+
+        let deleted = deleted_block_names(
+            diff,
+            &[(
+                "a.py",
+                "# <block name=\"foo\" keep-sorted>\nnew foo\n# </block>\n",
+            )],
+            &FakePathChecker::allow_all(),
+        )?;
+
+        assert!(deleted.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn retagged_blocks_with_unchanged_content_are_not_deleted() -> anyhow::Result<()> {
+        let diff = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -1,1 +1,1 @@
+-# <block name="foo" affects=":bar">
++# <block affects=":bar">
+"#;
+
+        let deleted = deleted_block_names(
+            diff,
+            &[("a.py", "# <block affects=\":bar\">\nfoo\n# </block>\n")],
+            &FakePathChecker::allow_all(),
+        )?;
+
+        assert!(deleted.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn all_blocks_of_removed_file_are_returned() -> anyhow::Result<()> {
+        let diff = r#"diff --git a/a.py b/a.py
+deleted file mode 100644
+--- a/a.py
++++ /dev/null
+@@ -1,4 +0,0 @@
+-# <block name="foo">
+-# </block>
+-# <block affects=":foo">
+-# </block>
+"#;
+
+        let deleted = deleted_block_names(diff, &[], &FakePathChecker::allow_all())?;
+
+        assert_eq!(
+            deleted,
+            HashMap::from([(
+                PathBuf::from("a.py"),
+                vec!["foo".to_string(), "(unnamed)".to_string()]
+            )])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn blocks_of_renamed_file_are_deleted_from_old_path() -> anyhow::Result<()> {
+        let diff = r#"diff --git a/a.py b/b.py
+similarity index 90%
+rename from a.py
+rename to b.py
+--- a/a.py
++++ b/b.py
+@@ -2,1 +2,1 @@
+-foo
++bar
+"#;
+
+        let deleted = deleted_block_names(
+            diff,
+            &[("b.py", "# <block name=\"foo\">\nbar\n# </block>\n")],
+            &FakePathChecker::allow_all(),
+        )?;
+
+        assert_eq!(
+            deleted,
+            HashMap::from([(PathBuf::from("a.py"), vec!["foo".to_string()])])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ignored_files_and_mismatching_contents_are_skipped() -> anyhow::Result<()> {
+        let diff = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -1,2 +0,0 @@
+-# <block name="foo">
+-# </block>
+diff --git a/b.py b/b.py
+--- a/b.py
++++ b/b.py
+@@ -1,2 +1,1 @@
+-# <block name="foo">
+-# </block>
++x
+"#;
+
+        let deleted = deleted_block_names(
+            diff,
+            &[("a.py", ""), ("b.py", "y\n")],
+            &FakePathChecker::with_ignored_paths(HashSet::from(["a.py".to_string()])),
+        )?;
+
+        assert!(deleted.is_empty());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod file_system_impl_tests {
     use super::{FileSystem, FileSystemImpl};
@@ -1490,7 +1875,7 @@ mod supported_languages_tests {
             &file_system,
             &FakePathChecker::allow_all(),
             &parsers,
-            HashMap::new(),
+            ExtensionMappings::default(),
         )?;
 
         for file_name in files.keys() {
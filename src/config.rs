@@ -0,0 +1,305 @@
+use crate::blocks::ExtensionMappings;
+use crate::flags;
+use anyhow::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Name of the project configuration file looked up at the repository root.
+pub const CONFIG_FILE_NAME: &str = "blockwatch.toml";
+
+/// Project configuration read from [`CONFIG_FILE_NAME`].
+///
+/// The top-level settings mirror the command-line flags; `overrides` adjust them for the files
+/// matching a set of glob patterns.
+// <block affects="README.md:config-file">
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Glob patterns to filter files (same as the positional `GLOBS` arguments).
+    #[serde(default)]
+    globs: Vec<String>,
+    /// Glob patterns to ignore files (same as `--ignore`).
+    #[serde(default)]
+    ignore: Vec<String>,
+    /// Extension mappings, e.g. `cxx = "cpp"` (same as `-E cxx=cpp`).
+    #[serde(default)]
+    extensions: HashMap<String, String>,
+    /// Validators to enable (same as `-e`).
+    #[serde(default)]
+    enable: Vec<String>,
+    /// Validators to disable (same as `-d`).
+    #[serde(default)]
+    disable: Vec<String>,
+    /// Per-path overrides.
+    #[serde(default)]
+    overrides: Vec<Override>,
+}
+
+/// Settings that only apply to the files matching `paths`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Override {
+    /// Glob patterns selecting the files this override applies to.
+    paths: Vec<String>,
+    /// Validators to disable for the matching files.
+    #[serde(default)]
+    disable: Vec<String>,
+    /// Extension mappings for the matching files.
+    #[serde(default)]
+    extensions: HashMap<String, String>,
+    /// `paths` compiled into a glob set.
+    #[serde(skip)]
+    glob_set: GlobSet,
+}
+// </block>
+
+impl Config {
+    /// Loads [`CONFIG_FILE_NAME`] from `root_path`, returning the default configuration when the
+    /// file does not exist.
+    pub fn load(root_path: &Path) -> anyhow::Result<Self> {
+        let config_path = root_path.join(CONFIG_FILE_NAME);
+        if !config_path.is_file() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        Self::parse(&contents).with_context(|| format!("Invalid {CONFIG_FILE_NAME}"))
+    }
+
+    /// Parses the configuration from its TOML `contents`.
+    ///
+    /// Unknown keys, unknown validator names and invalid glob patterns are rejected.
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let mut config: Self = toml::from_str(contents)?;
+        for validator in config
+            .enable
+            .iter()
+            .chain(&config.disable)
+            .chain(config.overrides.iter().flat_map(|o| &o.disable))
+        {
+            flags::parse_validator(validator)?;
+        }
+        if !config.enable.is_empty() && !config.disable.is_empty() {
+            anyhow::bail!("\"enable\" and \"disable\" must not be set at the same time");
+        }
+        for glob in config.globs.iter().chain(&config.ignore) {
+            Glob::new(glob).with_context(|| format!("Invalid glob pattern: {glob}"))?;
+        }
+        for config_override in &mut config.overrides {
+            if config_override.paths.is_empty() {
+                anyhow::bail!("Every entry in \"overrides\" must have non-empty \"paths\"");
+            }
+            let mut builder = GlobSetBuilder::new();
+            for glob in &config_override.paths {
+                builder
+                    .add(Glob::new(glob).with_context(|| format!("Invalid glob pattern: {glob}"))?);
+            }
+            config_override.glob_set = builder.build().context("Failed to build glob set")?;
+        }
+        Ok(config)
+    }
+
+    /// Validates the extension mappings against the `supported_extensions`.
+    pub fn validate(&self, supported_extensions: &HashSet<&OsString>) -> anyhow::Result<()> {
+        for (key, val) in self
+            .extensions
+            .iter()
+            .chain(self.overrides.iter().flat_map(|o| &o.extensions))
+        {
+            if !supported_extensions.contains(&OsString::from(val)) {
+                anyhow::bail!("Unsupported extension mapping in {CONFIG_FILE_NAME}: {key}={val}");
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn globs(&self) -> &[String] {
+        &self.globs
+    }
+
+    pub(crate) fn ignore(&self) -> &[String] {
+        &self.ignore
+    }
+
+    pub(crate) fn enable(&self) -> &[String] {
+        &self.enable
+    }
+
+    pub(crate) fn disable(&self) -> &[String] {
+        &self.disable
+    }
+
+    /// Combines the configured extension mappings with the `cli_extensions` given via `-E`.
+    ///
+    /// The command-line mappings win over both the top-level and the per-path mappings.
+    pub fn extension_mappings(
+        &self,
+        cli_extensions: HashMap<OsString, OsString>,
+    ) -> ExtensionMappings {
+        let mut global: HashMap<OsString, OsString> = self
+            .extensions
+            .iter()
+            .map(|(key, val)| (OsString::from(key), OsString::from(val)))
+            .collect();
+        global.extend(cli_extensions.clone());
+        let mut mappings = ExtensionMappings::new(global);
+        for config_override in &self.overrides {
+            let scoped: HashMap<OsString, OsString> = config_override
+                .extensions
+                .iter()
+                .map(|(key, val)| (OsString::from(key), OsString::from(val)))
+                .filter(|(key, _)| !cli_extensions.contains_key(key))
+                .collect();
+            if !scoped.is_empty() {
+                mappings = mappings.with_scoped(config_override.glob_set.clone(), scoped);
+            }
+        }
+        mappings
+    }
+
+    /// Returns the validators disabled by the overrides matching `path`.
+    pub fn disabled_validators_for(&self, path: &Path) -> HashSet<&str> {
+        self.overrides
+            .iter()
+            .filter(|config_override| config_override.glob_set.is_match(path))
+            .flat_map(|config_override| config_override.disable.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_returns_defaults() -> anyhow::Result<()> {
+        let config = Config::parse("")?;
+
+        assert!(config.globs().is_empty());
+        assert!(config.ignore().is_empty());
+        assert!(config.enable().is_empty());
+        assert!(config.disable().is_empty());
+        assert!(config.disabled_validators_for(Path::new("a.py")).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn full_config_is_parsed() -> anyhow::Result<()> {
+        let config = Config::parse(
+            r#"
+globs = ["src/**"]
+ignore = ["**/generated/**"]
+disable = ["line-count"]
+
+[extensions]
+cxx = "cpp"
+
+[[overrides]]
+paths = ["vendor/**"]
+disable = ["check-ai", "check-lua"]
+
+[[overrides]]
+paths = ["third_party/**"]
+extensions = { inc = "cpp" }
+"#,
+        )?;
+
+        assert_eq!(config.globs(), ["src/**"]);
+        assert_eq!(config.ignore(), ["**/generated/**"]);
+        assert_eq!(config.disable(), ["line-count"]);
+        assert_eq!(
+            config.disabled_validators_for(Path::new("vendor/lib/a.py")),
+            HashSet::from(["check-ai", "check-lua"])
+        );
+        assert!(
+            config
+                .disabled_validators_for(Path::new("src/a.py"))
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_key_returns_error_with_location() {
+        let err = Config::parse("globs = []\nunknown = 1\n").unwrap_err();
+
+        let message = format!("{err:#}");
+        assert!(message.contains("unknown field `unknown`"), "{message}");
+        assert!(message.contains("line 2"), "{message}");
+    }
+
+    #[test]
+    fn unknown_override_key_returns_error() {
+        let err = Config::parse("[[overrides]]\npaths = [\"a/**\"]\nenable = [\"affects\"]\n")
+            .unwrap_err();
+
+        assert!(format!("{err:#}").contains("unknown field `enable`"));
+    }
+
+    #[test]
+    fn unknown_validator_returns_error() {
+        let err = Config::parse("disable = [\"no-such-validator\"]").unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("Unknown validator: no-such-validator")
+        );
+    }
+
+    #[test]
+    fn enable_and_disable_together_returns_error() {
+        assert!(Config::parse("enable = [\"affects\"]\ndisable = [\"check-ai\"]").is_err());
+    }
+
+    #[test]
+    fn override_without_paths_returns_error() {
+        assert!(Config::parse("[[overrides]]\npaths = []\ndisable = [\"affects\"]").is_err());
+    }
+
+    #[test]
+    fn unsupported_extension_mapping_returns_error() -> anyhow::Result<()> {
+        let config =
+            Config::parse("[[overrides]]\npaths = [\"a/**\"]\nextensions = { x = \"nope\" }")?;
+        let rs = OsString::from("rs");
+
+        let err = config.validate(&HashSet::from([&rs])).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Unsupported extension mapping in blockwatch.toml: x=nope"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cli_extensions_win_over_configured_ones() -> anyhow::Result<()> {
+        let config = Config::parse(
+            r#"
+extensions = { inc = "c", tpl = "html" }
+
+[[overrides]]
+paths = ["third_party/**"]
+extensions = { inc = "cpp", tpl = "xml" }
+"#,
+        )?;
+
+        let mappings = config.extension_mappings(HashMap::from([("tpl".into(), "md".into())]));
+
+        assert_eq!(
+            mappings.for_path(Path::new("src/a.inc"))[&OsString::from("inc")],
+            "c"
+        );
+        assert_eq!(
+            mappings.for_path(Path::new("third_party/a.inc"))[&OsString::from("inc")],
+            "cpp"
+        );
+        assert_eq!(
+            mappings.for_path(Path::new("third_party/a.tpl"))[&OsString::from("tpl")],
+            "md"
+        );
+        Ok(())
+    }
+}
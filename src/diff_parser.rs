@@ -50,6 +50,106 @@ pub fn line_changes_from_diff(
     Ok(result)
 }
 
+/// Old contents of a file changed or removed by a diff, reconstructed from its new contents.
+#[derive(Debug, PartialEq)]
+pub struct PreImage {
+    /// Path of the file after the change (`None` if the file was removed).
+    pub target_path: Option<PathBuf>,
+    hunks: Vec<PreImageHunk>,
+}
+
+#[derive(Debug, PartialEq)]
+struct PreImageHunk {
+    // 0-based index of the first line of the new contents replaced by the hunk.
+    target_start: usize,
+    // Lines of the new contents covered by the hunk (context and added lines).
+    target_lines: Vec<String>,
+    // Lines of the old contents covered by the hunk (context and removed lines).
+    source_lines: Vec<String>,
+}
+
+impl PreImage {
+    /// Returns the old contents of the file given its `new_contents` (`None` for a removed file).
+    ///
+    /// Returns `None` if the `new_contents` do not match the diff, e.g. when a diff of the staged
+    /// changes is validated against a working tree with more changes.
+    pub fn contents(&self, new_contents: Option<&str>) -> Option<String> {
+        let new_lines: Vec<&str> = new_contents.map_or_else(Vec::new, |c| c.lines().collect());
+        let mut old_lines: Vec<&str> = Vec::new();
+        let mut cursor = 0;
+        for hunk in &self.hunks {
+            let target_end = hunk.target_start + hunk.target_lines.len();
+            if hunk.target_start < cursor
+                || new_lines.get(hunk.target_start..target_end)? != hunk.target_lines.as_slice()
+            {
+                return None;
+            }
+            old_lines.extend(&new_lines[cursor..hunk.target_start]);
+            old_lines.extend(hunk.source_lines.iter().map(String::as_str));
+            cursor = target_end;
+        }
+        old_lines.extend(&new_lines[cursor..]);
+        Some(old_lines.iter().map(|line| format!("{line}\n")).collect())
+    }
+}
+
+/// Extracts the pre-images of the files changed or removed by a unified diff patch string, keyed
+/// by their paths before the change.
+///
+/// Added files have no pre-image and are not included in the result.
+pub fn pre_images_from_diff(patch_diff: &str) -> anyhow::Result<HashMap<PathBuf, PreImage>> {
+    let patch_set = PatchSet::from_str(patch_diff)?;
+    let mut result = HashMap::new();
+    for patched_file in patch_set {
+        if patched_file.is_added_file() {
+            continue;
+        }
+        let source_path: PathBuf = patched_file.source_file.trim_start_matches("a/").into();
+        if !is_within_repo_root(&source_path) {
+            anyhow::bail!(
+                "diff source path \"{}\" escapes the repository root folder",
+                source_path.display()
+            );
+        }
+        let target_path = (!patched_file.is_removed_file())
+            .then(|| PathBuf::from(patched_file.target_file.trim_start_matches("b/")));
+        let hunks = patched_file
+            .hunks()
+            .iter()
+            .map(|hunk| PreImageHunk {
+                // A zero-length target starts after the line before the removed lines.
+                target_start: if hunk.target_length == 0 {
+                    hunk.target_start
+                } else {
+                    hunk.target_start.saturating_sub(1)
+                },
+                target_lines: hunk.target_lines().into_iter().map(|l| l.value).collect(),
+                source_lines: hunk.source_lines().into_iter().map(|l| l.value).collect(),
+            })
+            .collect();
+        result.insert(source_path, PreImage { target_path, hunks });
+    }
+    Ok(result)
+}
+
+/// Extracts the line changes between the `old` and the `new` contents of the file at `path`.
+///
+/// Line numbers refer to the `new` contents, exactly like for a `git diff` of the file.
+pub fn line_changes_between(path: &Path, old: &str, new: &str) -> anyhow::Result<Vec<LineChange>> {
+    if old == new {
+        return Ok(Vec::new());
+    }
+    let path = path.to_string_lossy();
+    let patch_diff = similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string();
+    Ok(line_changes_from_diff(&patch_diff)?
+        .into_values()
+        .next()
+        .unwrap_or_default())
+}
+
 /// Whether `path` stays within the repository root.
 ///
 /// I.e. it is a relative path with no `..` component and no absolute/root prefix.
@@ -1079,4 +1179,150 @@ index 0000000..710d1d9
             "unexpected error: {err}"
         );
     }
+
+    #[test]
+    fn line_changes_between_equal_contents_returns_no_changes() -> anyhow::Result<()> {
+        let changes = line_changes_between(Path::new("a.py"), "a\nb\n", "a\nb\n")?;
+
+        assert!(changes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn line_changes_between_returns_changes_in_new_contents() -> anyhow::Result<()> {
+        let changes =
+            line_changes_between(Path::new("src/a.py"), "a\nb\nc\nd\n", "a\nbox\nc\nd\ne\n")?;
+
+        assert_eq!(
+            changes,
+            vec![
+                LineChange {
+                    line: 2,
+                    ranges: Some(vec![1..3]),
+                },
+                line_change(5),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_changes_between_empty_old_contents_returns_all_lines() -> anyhow::Result<()> {
+        let changes = line_changes_between(Path::new("a.py"), "", "a\nb\n")?;
+
+        assert_eq!(changes, vec![line_change(1), line_change(2)]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod pre_image_tests {
+    use super::*;
+
+    const DIFF: &str = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -2,2 +2,1 @@
+-removed
+-changed
++new
+@@ -5,0 +5,1 @@
++added
+diff --git a/removed.py b/removed.py
+deleted file mode 100644
+--- a/removed.py
++++ /dev/null
+@@ -1,2 +0,0 @@
+-first
+-second
+diff --git a/added.py b/added.py
+new file mode 100644
+--- /dev/null
++++ b/added.py
+@@ -0,0 +1,1 @@
++added
+"#;
+
+    #[test]
+    fn modified_file_contents_are_reconstructed_from_new_contents() -> anyhow::Result<()> {
+        let pre_images = pre_images_from_diff(DIFF)?;
+
+        let pre_image = &pre_images[Path::new("a.py")];
+        assert_eq!(pre_image.target_path, Some(PathBuf::from("a.py")));
+        assert_eq!(
+            pre_image.contents(Some("one\nnew\nfour\nfive\nadded\nsix\n")),
+            Some("one\nremoved\nchanged\nfour\nfive\nsix\n".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn removed_file_contents_are_taken_from_diff() -> anyhow::Result<()> {
+        let pre_images = pre_images_from_diff(DIFF)?;
+
+        let pre_image = &pre_images[Path::new("removed.py")];
+        assert_eq!(pre_image.target_path, None);
+        assert_eq!(
+            pre_image.contents(None),
+            Some("first\nsecond\n".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn added_file_has_no_pre_image() -> anyhow::Result<()> {
+        let pre_images = pre_images_from_diff(DIFF)?;
+
+        assert_eq!(pre_images.len(), 2);
+        assert!(!pre_images.contains_key(Path::new("added.py")));
+        Ok(())
+    }
+
+    #[test]
+    fn mismatching_new_contents_return_none() -> anyhow::Result<()> {
+        let pre_images = pre_images_from_diff(DIFF)?;
+
+        let pre_image = &pre_images[Path::new("a.py")];
+        assert_eq!(pre_image.contents(Some("one\nother\nfour\n")), None);
+        assert_eq!(pre_image.contents(Some("one\n")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_file_pre_image_is_keyed_by_source_path() -> anyhow::Result<()> {
+        let pre_images = pre_images_from_diff(
+            r#"diff --git a/old.py b/new.py
+--- a/old.py
++++ b/new.py
+@@ -1,1 +1,1 @@
+-old
++new
+"#,
+        )?;
+
+        assert_eq!(
+            pre_images[Path::new("old.py")].target_path,
+            Some(PathBuf::from("new.py"))
+        );
+        assert_eq!(
+            pre_images[Path::new("old.py")].contents(Some("new\n")),
+            Some("old\n".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn source_path_escaping_repository_root_returns_error() {
+        let result = pre_images_from_diff(
+            r#"diff --git a/../x.py b/x.py
+--- a/../x.py
++++ b/x.py
+@@ -1,1 +1,1 @@
+-old
++new
+"#,
+        );
+
+        assert!(result.is_err());
+    }
 }
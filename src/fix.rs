@@ -0,0 +1,393 @@
+use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::validators::{Fixer, ValidationContext};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Fixed contents of a single file.
+#[derive(Debug, PartialEq)]
+pub struct FileFix {
+    path: PathBuf,
+    original: String,
+    fixed: String,
+}
+
+impl FileFix {
+    /// Path of the fixed file relative to the repository root.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the fix as a unified diff between the original and the fixed contents.
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.display();
+        similar::TextDiff::from_lines(&self.original, &self.fixed)
+            .unified_diff()
+            .header(&format!("a/{path}"), &format!("b/{path}"))
+            .to_string()
+    }
+}
+
+/// Computes the fixes for all the blocks in the `context`, sorted by file path.
+///
+/// Only the fixers of the enabled validators are applied (see
+/// [`crate::validators::detect_validators`] for how `disabled_validators` and `enabled_validators`
+/// are interpreted). Files without anything to fix are not returned.
+pub fn fix_blocks(
+    context: &ValidationContext,
+    fixers: &[(&str, Box<dyn Fixer>)],
+    disabled_validators: &HashSet<&str>,
+    enabled_validators: &HashSet<&str>,
+) -> anyhow::Result<Vec<FileFix>> {
+    let fixers: Vec<&dyn Fixer> = fixers
+        .iter()
+        .filter(|(validator_name, _)| {
+            if !enabled_validators.is_empty() {
+                enabled_validators.contains(validator_name)
+            } else {
+                !disabled_validators.contains(validator_name)
+            }
+        })
+        .map(|(_, fixer)| fixer.as_ref())
+        .collect();
+    let mut fixes = Vec::new();
+    for (file_path, file_blocks) in &context.blocks {
+        let mut replacements = Vec::new();
+        for block_with_context in &file_blocks.blocks_with_context {
+            let block = &block_with_context.block;
+            if has_nested_blocks(block, &file_blocks.blocks_with_context) {
+                // Moving or removing lines could break the tags of the nested blocks.
+                continue;
+            }
+            let original = block.content(&file_blocks.file_content);
+            let mut content = original.to_string();
+            for fixer in &fixers {
+                if let Some(fixed) = fixer.fix(context, file_path, block, &content)? {
+                    content = fixed;
+                }
+            }
+            if content != original {
+                replacements.push((block.content_bytes_range.clone(), content));
+            }
+        }
+        if !replacements.is_empty() {
+            fixes.push(FileFix {
+                path: file_path.clone(),
+                fixed: apply_replacements(&file_blocks.file_content, replacements),
+                original: file_blocks.file_content.clone(),
+            });
+        }
+    }
+    fixes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(fixes)
+}
+
+/// Writes the fixed contents of every file in `fixes`.
+pub fn write_fixes(fixes: &[FileFix], file_system: &impl FileSystem) -> anyhow::Result<()> {
+    for fix in fixes {
+        file_system.write(&fix.path, &fix.fixed)?;
+    }
+    Ok(())
+}
+
+/// Whether any of the `blocks` is defined within the content of the `block`.
+fn has_nested_blocks(block: &Block, blocks: &[BlockWithContext]) -> bool {
+    blocks.iter().any(|other| {
+        block
+            .content_position_range
+            .contains(other.block.start_tag_position_range.start())
+    })
+}
+
+/// Replaces the non-overlapping byte ranges of `source` with their new contents.
+fn apply_replacements(source: &str, mut replacements: Vec<(Range<usize>, String)>) -> String {
+    replacements.sort_by_key(|(range, _)| range.start);
+    let mut result = String::with_capacity(source.len());
+    let mut cursor = 0;
+    for (range, replacement) in replacements {
+        result.push_str(&source[cursor..range.start]);
+        result.push_str(&replacement);
+        cursor = range.end;
+    }
+    result.push_str(&source[cursor..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeFileSystem, validation_context};
+    use crate::validators::fixers;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn fix(file_name: &str, contents: &str) -> anyhow::Result<Vec<FileFix>> {
+        fix_blocks(
+            &validation_context(file_name, contents),
+            &fixers(&Arc::new(FakeFileSystem::new(HashMap::new()))),
+            &HashSet::new(),
+            &HashSet::new(),
+        )
+    }
+
+    fn fixed(file_name: &str, contents: &str) -> anyhow::Result<String> {
+        let mut fixes = fix(file_name, contents)?;
+        assert_eq!(fixes.len(), 1);
+        Ok(fixes.remove(0).fixed)
+    }
+
+    #[test]
+    fn valid_blocks_return_no_fixes() -> anyhow::Result<()> {
+        let fixes = fix(
+            "example.py",
+            r#"# <block keep-sorted keep-unique>
+a
+b
+# </block>
+"#,
+        )?;
+
+        assert!(fixes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn keep_sorted_asc_sorts_lines_keeping_blank_lines_and_trailing_commas() -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            r#"items = [
+    # <block keep-sorted>
+    "c",
+
+    "a",
+    "b"
+    # </block>
+]
+"#,
+        )?;
+
+        assert_eq!(
+            fixed,
+            r#"items = [
+    # <block keep-sorted>
+    "a",
+
+    "b",
+    "c"
+    # </block>
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_sorted_desc_sorts_lines_in_descending_order() -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            "# <block keep-sorted=\"desc\">\na\nc\nb\n# </block>\n",
+        )?;
+
+        assert_eq!(
+            fixed,
+            "# <block keep-sorted=\"desc\">\nc\nb\na\n# </block>\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_sorted_pattern_sorts_by_matches_and_keeps_non_matching_lines_in_place()
+    -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            r#"# <block keep-sorted keep-sorted-pattern="id: (?P<value>\w+)">
+id: c
+-- separator
+id: a
+id: b
+# </block>
+"#,
+        )?;
+
+        assert_eq!(
+            fixed,
+            r#"# <block keep-sorted keep-sorted-pattern="id: (?P<value>\w+)">
+id: a
+-- separator
+id: b
+id: c
+# </block>
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_sorted_numeric_format_sorts_numerically() -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            "# <block keep-sorted keep-sorted-format=\"numeric\">\n10\n9\n-1.5\n# </block>\n",
+        )?;
+
+        assert_eq!(
+            fixed,
+            "# <block keep-sorted keep-sorted-format=\"numeric\">\n-1.5\n9\n10\n# </block>\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_sorted_numeric_format_with_non_numeric_value_returns_error() {
+        let result = fix(
+            "example.py",
+            "# <block keep-sorted keep-sorted-format=\"numeric\">\n10\nten\n# </block>\n",
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn keep_unique_removes_duplicated_last_item_keeping_it_without_trailing_comma()
+    -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            r#"items = [
+    # <block keep-unique="\w+">
+    "a",
+    "b",
+
+    "a"
+    # </block>
+]
+"#,
+        )?;
+
+        assert_eq!(
+            fixed,
+            r#"items = [
+    # <block keep-unique="\w+">
+    "a",
+    "b"
+
+    # </block>
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_unique_pattern_removes_lines_with_duplicated_matches() -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            r#"# <block keep-unique="id: (?P<value>\d+)">
+id: 1 first
+id: 2
+id: 1 second
+# </block>
+"#,
+        )?;
+
+        assert_eq!(
+            fixed,
+            r#"# <block keep-unique="id: (?P<value>\d+)">
+id: 1 first
+id: 2
+# </block>
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_unique_and_keep_sorted_are_both_applied() -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            "# <block keep-sorted keep-unique>\nb\na\nb\n# </block>\n",
+        )?;
+
+        assert_eq!(
+            fixed,
+            "# <block keep-sorted keep-unique>\na\nb\n# </block>\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn disabled_validators_are_not_fixed() -> anyhow::Result<()> {
+        let fixes = fix_blocks(
+            &validation_context(
+                "example.py",
+                "# <block keep-sorted keep-unique>\nb\na\nb\n# </block>\n",
+            ),
+            &fixers(&Arc::new(FakeFileSystem::new(HashMap::new()))),
+            &HashSet::from(["keep-sorted"]),
+            &HashSet::new(),
+        )?;
+
+        assert_eq!(
+            fixes[0].fixed,
+            "# <block keep-sorted keep-unique>\nb\na\n# </block>\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_with_nested_blocks_is_not_fixed() -> anyhow::Result<()> {
+        let fixed = fixed(
+            "example.py",
+            r#"# <block keep-sorted>
+b
+# <block keep-sorted>
+d
+c
+# </block>
+a
+# </block>
+"#,
+        )?;
+
+        assert_eq!(
+            fixed,
+            r#"# <block keep-sorted>
+b
+# <block keep-sorted>
+c
+d
+# </block>
+a
+# </block>
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unified_diff_shows_changed_lines() -> anyhow::Result<()> {
+        let fixes = fix("example.py", "# <block keep-sorted>\nb\na\n# </block>\n")?;
+
+        assert_eq!(
+            fixes[0].unified_diff(),
+            "--- a/example.py\n+++ b/example.py\n@@ -1,4 +1,4 @@\n # <block keep-sorted>\n+a\n b\n-a\n # </block>\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_fixes_writes_fixed_contents() -> anyhow::Result<()> {
+        let contents = "# <block keep-sorted>\nb\na\n# </block>\n";
+        let file_system = FakeFileSystem::new(HashMap::from([(
+            "example.py".to_string(),
+            contents.to_string(),
+        )]));
+        let fixes = fix("example.py", contents)?;
+
+        write_fixes(&fixes, &file_system)?;
+
+        assert_eq!(
+            file_system.read_to_string(Path::new("example.py"))?,
+            "# <block keep-sorted>\na\nb\n# </block>\n"
+        );
+        Ok(())
+    }
+}
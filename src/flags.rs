@@ -1,3 +1,6 @@
+use crate::config::Config;
+use crate::git::DiffSource;
+use crate::reporters::OutputFormat;
 use crate::validators;
 use anyhow::Context;
 use clap::{Parser, builder::ValueParser, crate_version};
@@ -30,6 +33,13 @@ use std::ffi::OsString;
     # With zero context for tighter diffs (recommended for hooks)
     git diff --patch --unified=0 | blockwatch
 
+    # Validate staged changes without piping a diff (reads the files from the index)
+    blockwatch --staged
+
+    # Validate the changes since a revision or between two revisions
+    blockwatch --since main
+    blockwatch --range main..HEAD
+
     # Provide extra extension mappings (map unknown extensions to supported grammars)
     blockwatch -E cxx=cpp -E c++=cpp
 
@@ -39,11 +49,29 @@ use std::ffi::OsString;
     # Enable specific validators only
     blockwatch -e keep-sorted -e line-count
 
+    # Report violations as JSON even in a terminal
+    blockwatch --format json
+
+    # Report violations as a SARIF log (e.g. for GitHub code scanning)
+    blockwatch --format sarif 2> blockwatch.sarif
+
     # List all found blocks
     blockwatch list 'src/**/*.rs'
 
     # List blocks and mark those touched by a diff (reads stdin)
-    git diff --patch | blockwatch list --diff",
+    git diff --patch | blockwatch list --diff
+
+    # Fix keep-sorted and keep-unique violations in place
+    blockwatch fix 'src/**/*.rs'
+
+    # Preview the fixes of the blocks touched by a diff as a unified diff (reads stdin)
+    git diff --patch | blockwatch fix --diff --dry-run
+
+    # Check that all affects references point to exactly one existing block
+    blockwatch check-refs
+
+    # Run the language server (for editors)
+    blockwatch lsp",
 )]
 pub struct Args {
     // <block affects="README.md:cli-docs">
@@ -89,6 +117,33 @@ pub struct Args {
     )]
     pub ignore: Vec<String>,
 
+    /// Output format of the violations report.
+    /// Defaults to text when stderr is a terminal and to json otherwise.
+    #[arg(long = "format", value_enum, global = true)]
+    pub format: Option<OutputFormat>,
+
+    /// Validate the changes staged for commit instead of reading a diff from stdin.
+    /// The files are read from the git index, i.e. exactly as they will be committed.
+    #[arg(long, conflicts_with_all = ["since", "range"])]
+    staged: bool,
+
+    /// Validate the changes of the working tree since a git revision, e.g. --since main
+    #[arg(long, value_name = "REV", conflicts_with = "range")]
+    since: Option<String>,
+
+    /// Validate the changes between two git revisions, e.g. --range main..HEAD
+    /// The files are read from the second revision.
+    #[arg(long, value_name = "A..B", value_parser = ValueParser::new(parse_range))]
+    range: Option<String>,
+
+    /// Don't read or write the cache of the check-ai responses (.blockwatch/cache/check-ai).
+    #[arg(long, global = true)]
+    pub no_ai_cache: bool,
+
+    /// Report details of the run to stderr, e.g. the check-ai cache hits.
+    #[arg(short = 'v', long, global = true)]
+    pub verbose: bool,
+
     /// Glob patterns to filter files.
     #[arg(value_name = "GLOBS")]
     pub globs: Vec<String>,
@@ -110,6 +165,30 @@ pub enum SubCommand {
         #[arg(value_name = "GLOBS")]
         globs: Vec<String>,
     },
+    /// Fix the violations of the validators that support it (keep-sorted, keep-unique,
+    /// generate-cmd, check-lua) in place.
+    Fix {
+        /// Read a unified diff from stdin and fix only the blocks it touched.
+        /// Without this flag, `fix` never reads stdin.
+        #[arg(long)]
+        diff: bool,
+
+        /// Print the fixes as a unified diff instead of writing them.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(value_name = "GLOBS")]
+        globs: Vec<String>,
+    },
+    /// Check the `affects` references of all blocks: report references to missing files, missing
+    /// or duplicated block names and blocks that affect themselves.
+    CheckRefs {
+        #[arg(value_name = "GLOBS")]
+        globs: Vec<String>,
+    },
+    /// Run a Language Server Protocol server over stdio, publishing the violations of the open
+    /// documents as diagnostics.
+    Lsp,
 }
 
 impl Args {
@@ -131,16 +210,34 @@ impl Args {
         self.enabled_validators.iter().map(AsRef::as_ref).collect()
     }
 
+    /// Changes to validate as set by `--staged`, `--since` or `--range` (if any).
+    pub fn diff_source(&self) -> Option<DiffSource> {
+        if self.staged {
+            Some(DiffSource::Staged)
+        } else if let Some(revision) = &self.since {
+            Some(DiffSource::Since(revision.clone()))
+        } else {
+            self.range.clone().map(DiffSource::Range)
+        }
+    }
+
+    /// Glob patterns given after the subcommand (if any).
+    fn subcommand_globs(&self) -> &[String] {
+        match &self.command {
+            Some(
+                SubCommand::List { globs, .. }
+                | SubCommand::Fix { globs, .. }
+                | SubCommand::CheckRefs { globs },
+            ) => globs,
+            Some(SubCommand::Lsp) | None => &[],
+        }
+    }
+
     /// Returns a compiled GlobSet from the provided glob patterns.
     pub fn globs(&self) -> anyhow::Result<GlobSet> {
         let mut builder = GlobSetBuilder::new();
         let mut globs = self.globs.clone();
-        if let Some(SubCommand::List {
-            globs: list_globs, ..
-        }) = &self.command
-        {
-            globs.extend(list_globs.clone());
-        }
+        globs.extend(self.subcommand_globs().iter().cloned());
 
         for glob_str in &globs {
             let glob = Glob::new(glob_str)
@@ -161,6 +258,22 @@ impl Args {
         builder.build().context("Failed to build ignore glob set")
     }
 
+    /// Fills in the settings that were not given on the command line from the project `config`.
+    ///
+    /// Command-line flags win: the globs and the `--enable`/`--disable` lists are taken from the
+    /// configuration only when none were passed, while the ignore globs are combined.
+    pub fn merge_config(&mut self, config: &Config) {
+        let has_cli_globs = !self.globs.is_empty() || !self.subcommand_globs().is_empty();
+        if !has_cli_globs {
+            self.globs = config.globs().to_vec();
+        }
+        self.ignore.extend(config.ignore().iter().cloned());
+        if self.enabled_validators.is_empty() && self.disabled_validators.is_empty() {
+            self.enabled_validators = config.enable().to_vec();
+            self.disabled_validators = config.disable().to_vec();
+        }
+    }
+
     /// Validates all arguments.
     pub fn validate(&self, supported_extensions: &HashSet<&OsString>) -> anyhow::Result<()> {
         // Check custom extensions.
@@ -173,6 +286,10 @@ impl Args {
         if !self.disabled_validators.is_empty() && !self.enabled_validators.is_empty() {
             anyhow::bail!("--enable and --disable flags must not be set at the same time");
         }
+        // Check that the git diff flags are not used with a subcommand.
+        if self.command.is_some() && self.diff_source().is_some() {
+            anyhow::bail!("--staged, --since and --range flags can't be used with a subcommand");
+        }
 
         Ok(())
     }
@@ -184,7 +301,15 @@ fn parse_extensions(s: &str) -> anyhow::Result<(String, String)> {
         .with_context(|| format!("Invalid KEY=VALUE format: {s}"))
 }
 
-fn parse_validator(value: &str) -> anyhow::Result<String> {
+/// Parses a `--range` of revisions, which needs both ends: without `..` git would compare the
+/// revision with the working tree, while the files are read from a revision.
+fn parse_range(s: &str) -> anyhow::Result<String> {
+    s.contains("..").then(|| s.to_string()).with_context(|| {
+        format!("Invalid range: {s}. Expected A..B or A...B, use --since {s} for the working tree")
+    })
+}
+
+pub(crate) fn parse_validator(value: &str) -> anyhow::Result<String> {
     let validators: Vec<&str> = validators::detector_factories::<crate::blocks::FileSystemImpl>()
         .iter()
         .map(|(validator_name, _)| *validator_name)
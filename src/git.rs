@@ -0,0 +1,269 @@
+use crate::blocks::FileSystem;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Which changes to validate, as an alternative to piping a `git diff` to stdin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffSource {
+    /// Changes staged for the next commit (`git diff --cached`).
+    Staged,
+    /// Changes of the working tree since the revision (`git diff <rev>`).
+    Since(String),
+    /// Changes between two revisions (`git diff A..B`, or `A...B` for the changes since the merge
+    /// base).
+    Range(String),
+}
+
+impl DiffSource {
+    /// Returns the revision whose files the changes refer to: `None` for the working tree and
+    /// `Some("")` for the index.
+    fn target_revision(&self) -> Option<String> {
+        match self {
+            DiffSource::Staged => Some(String::new()),
+            DiffSource::Since(_) => None,
+            DiffSource::Range(range) => {
+                let target = range
+                    .split_once("..")
+                    .map_or("", |(_, target)| target.trim_start_matches('.'));
+                Some(if target.is_empty() { "HEAD" } else { target }.to_string())
+            }
+        }
+    }
+}
+
+/// Runs `git diff` in the repository at `root_path` and returns the unified diff of the changes,
+/// in the same format as a diff read from stdin.
+pub fn diff(root_path: &Path, source: &DiffSource) -> anyhow::Result<String> {
+    let mut args = vec![
+        "diff",
+        "--patch",
+        "--no-color",
+        "--no-ext-diff",
+        // Like the recommended `git diff --patch --unified=0 | blockwatch` for hooks.
+        "--unified=0",
+        // User settings like `diff.noprefix` must not change the paths in the output.
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+    ];
+    match source {
+        DiffSource::Staged => args.push("--cached"),
+        DiffSource::Since(revision) | DiffSource::Range(revision) => {
+            args.extend([revision.as_str(), "--"])
+        }
+    }
+    git(root_path, &args)
+}
+
+/// Returns the file system the changes of the `source` refer to, if it is not the working tree.
+pub fn file_system(root_path: &Path, source: &DiffSource) -> Option<GitFileSystem> {
+    source.target_revision().map(|revision| GitFileSystem {
+        root_path: root_path.to_path_buf(),
+        revision,
+    })
+}
+
+/// Reads the files from the git index or from a revision instead of the working tree.
+///
+/// Used to validate the files exactly as they are staged or committed, e.g. when only a part of
+/// the changes of a file is staged.
+pub struct GitFileSystem {
+    root_path: PathBuf,
+    // Empty for the index.
+    revision: String,
+}
+
+impl FileSystem for GitFileSystem {
+    fn read_to_string(&self, path: &Path) -> anyhow::Result<String> {
+        let object = format!("{}:{}", self.revision, path.display());
+        git(&self.root_path, &["show", &object])
+            .with_context(|| format!("Failed to read file \"{}\"", path.display()))
+    }
+
+    fn write(&self, path: &Path, _contents: &str) -> anyhow::Result<()> {
+        anyhow::bail!(
+            "Writing \"{}\" is not supported for files read from git",
+            path.display()
+        )
+    }
+
+    fn walk(&self) -> impl Iterator<Item = anyhow::Result<PathBuf>> {
+        let files = if self.revision.is_empty() {
+            git(&self.root_path, &["ls-files", "-z"])
+        } else {
+            git(
+                &self.root_path,
+                &["ls-tree", "-r", "--name-only", "-z", &self.revision],
+            )
+        };
+        let paths: Vec<anyhow::Result<PathBuf>> = match files {
+            Ok(files) => files
+                .split('\0')
+                .filter(|path| !path.is_empty())
+                .map(|path| Ok(PathBuf::from(path)))
+                .collect(),
+            Err(e) => vec![Err(e)],
+        };
+        paths.into_iter()
+    }
+}
+
+/// Runs git with the `args` in `root_path` and returns its stdout.
+fn git(root_path: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root_path)
+        // Keep non-ASCII paths unquoted in the diff headers.
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "\"git {}\" failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("\"git {}\" returned non-UTF-8 output", args.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff_parser::{LineChange, line_changes_from_diff};
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn line_changes(
+        root_path: &Path,
+        source: &DiffSource,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<LineChange>>> {
+        line_changes_from_diff(&diff(root_path, source)?)
+    }
+
+    /// Creates a git repository with `a.py` committed as "a\nb\n".
+    fn repository() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        run(dir.path(), &["init", "-q"]);
+        fs::write(dir.path().join("a.py"), "a\nb\n").unwrap();
+        commit(dir.path(), "first");
+        dir
+    }
+
+    fn run(root_path: &Path, args: &[&str]) {
+        let mut all_args = vec!["-c", "user.name=test", "-c", "user.email=test@example.com"];
+        all_args.extend(args);
+        git(root_path, &all_args).unwrap();
+    }
+
+    fn commit(root_path: &Path, message: &str) {
+        run(root_path, &["add", "-A"]);
+        run(root_path, &["commit", "-q", "-m", message]);
+    }
+
+    fn line(line: usize) -> LineChange {
+        LineChange { line, ranges: None }
+    }
+
+    #[test]
+    fn staged_returns_staged_changes_only() -> anyhow::Result<()> {
+        let dir = repository();
+        fs::write(dir.path().join("a.py"), "a\nb\nc\n")?;
+        run(dir.path(), &["add", "a.py"]);
+        fs::write(dir.path().join("a.py"), "a\nb\nc\nd\n")?;
+
+        let changes = line_changes(dir.path(), &DiffSource::Staged)?;
+
+        assert_eq!(
+            changes,
+            HashMap::from([(PathBuf::from("a.py"), vec![line(3)])])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn since_returns_working_tree_changes_since_revision() -> anyhow::Result<()> {
+        let dir = repository();
+        fs::write(dir.path().join("a.py"), "a\nb\nc\n")?;
+        commit(dir.path(), "second");
+        fs::write(dir.path().join("b.py"), "x\n")?;
+        run(dir.path(), &["add", "b.py"]);
+
+        let changes = line_changes(dir.path(), &DiffSource::Since("HEAD~1".to_string()))?;
+
+        assert_eq!(
+            changes,
+            HashMap::from([
+                (PathBuf::from("a.py"), vec![line(3)]),
+                (PathBuf::from("b.py"), vec![line(1)]),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn range_returns_changes_between_revisions() -> anyhow::Result<()> {
+        let dir = repository();
+        fs::write(dir.path().join("a.py"), "a\nb\nc\n")?;
+        commit(dir.path(), "second");
+        fs::write(dir.path().join("a.py"), "a\nb\nc\nd\n")?;
+
+        let changes = line_changes(dir.path(), &DiffSource::Range("HEAD~1..HEAD".to_string()))?;
+
+        assert_eq!(
+            changes,
+            HashMap::from([(PathBuf::from("a.py"), vec![line(3)])])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_revision_returns_error() {
+        let dir = repository();
+
+        let result = line_changes(dir.path(), &DiffSource::Since("nope".to_string()));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn staged_file_system_reads_and_walks_index() -> anyhow::Result<()> {
+        let dir = repository();
+        fs::write(dir.path().join("a.py"), "staged\n")?;
+        run(dir.path(), &["add", "a.py"]);
+        fs::write(dir.path().join("a.py"), "unstaged\n")?;
+        fs::write(dir.path().join("untracked.py"), "untracked\n")?;
+
+        let file_system = file_system(dir.path(), &DiffSource::Staged).unwrap();
+
+        assert_eq!(file_system.read_to_string(Path::new("a.py"))?, "staged\n");
+        assert_eq!(
+            file_system.walk().collect::<anyhow::Result<Vec<_>>>()?,
+            vec![PathBuf::from("a.py")]
+        );
+        assert!(file_system.write(Path::new("a.py"), "x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn range_file_system_reads_target_revision() -> anyhow::Result<()> {
+        let dir = repository();
+        fs::write(dir.path().join("a.py"), "second\n")?;
+        commit(dir.path(), "second");
+        fs::write(dir.path().join("a.py"), "unstaged\n")?;
+
+        for range in ["HEAD~1..HEAD", "HEAD~1...HEAD", "HEAD~1.."] {
+            let file_system =
+                file_system(dir.path(), &DiffSource::Range(range.to_string())).unwrap();
+            assert_eq!(file_system.read_to_string(Path::new("a.py"))?, "second\n");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn since_reads_working_tree() {
+        assert!(file_system(Path::new("."), &DiffSource::Since("HEAD".to_string())).is_none());
+    }
+}
@@ -148,6 +148,11 @@ impl CommentsParser for MdCommentsParser {
         comments.sort_by_key(|comment| comment.source_range.start);
         comments.into_iter()
     }
+
+    /// Returns the tree of the block structure of the document (inline content is not parsed).
+    fn syntax_tree(&mut self, contents: &str) -> Option<tree_sitter::Tree> {
+        self.md_tree_sitter_parser.parse(contents, None)
+    }
 }
 
 /// Extracts the content of a Markdown `[//]:` comment (a link reference definition used as a
@@ -170,6 +170,11 @@ pub(crate) trait CommentsParser: Send + Sync {
         &'source mut self,
         source_code: &'source str,
     ) -> impl Iterator<Item = Comment> + 'source;
+
+    /// Returns the tree-sitter syntax tree of the source code, if the language has one.
+    fn syntax_tree(&mut self, _source_code: &str) -> Option<Tree> {
+        None
+    }
 }
 
 type NodeVisitor = Box<dyn Fn(&Node, &str) -> Option<String> + Send + Sync>;
@@ -201,6 +206,10 @@ impl CommentsParser for TreeSitterCommentsParser {
         // It is safe to unwrap here because we just set self.tree
         CommentsIterator::new(self.tree.as_ref().unwrap(), &self.node_visitor, source_code)
     }
+
+    fn syntax_tree(&mut self, source_code: &str) -> Option<Tree> {
+        self.parser.parse(source_code, None)
+    }
 }
 
 struct CommentsIterator<'source> {
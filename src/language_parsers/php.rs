@@ -100,6 +100,10 @@ impl CommentsParser for PhpCommentsParser {
         comments.sort_by_key(|comment| comment.source_range.start);
         comments.into_iter()
     }
+
+    fn syntax_tree(&mut self, source_code: &str) -> Option<tree_sitter::Tree> {
+        self.php_tree_sitter_parser.parse(source_code, None)
+    }
 }
 
 #[cfg(test)]
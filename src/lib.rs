@@ -2,13 +2,18 @@ use serde::Serialize;
 
 mod block_parser;
 pub mod blocks;
+pub mod config;
 pub mod diff_parser;
+pub mod fix;
 pub mod flags;
+pub mod git;
 pub mod language_parsers;
+pub mod lsp;
+pub mod reporters;
 mod tag_parser;
 pub mod validators;
 
-#[derive(Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct Position {
     // 1-based line number.
     line: usize,
@@ -24,14 +29,14 @@ impl Position {
 
 #[cfg(test)]
 mod test_utils {
-    use crate::blocks::{FileBlocks, FileSystem, PathChecker, parse_blocks};
+    use crate::blocks::{ExtensionMappings, FileBlocks, FileSystem, PathChecker, parse_blocks};
     use crate::diff_parser::LineChange;
     use crate::language_parsers;
-    use crate::validators::ValidationContext;
+    use crate::validators::{ValidationContext, Violation};
     use std::collections::{HashMap, HashSet};
     use std::ops::Range;
     use std::path::{Path, PathBuf};
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
 
     /// Finds the byte range of the first occurrence of a substring within a string.
     ///
@@ -44,12 +49,14 @@ mod test_utils {
     }
 
     pub(crate) struct FakeFileSystem {
-        files: HashMap<String, String>,
+        files: Mutex<HashMap<String, String>>,
     }
 
     impl FakeFileSystem {
         pub(crate) fn new(files: HashMap<String, String>) -> Self {
-            Self { files }
+            Self {
+                files: Mutex::new(files),
+            }
         }
     }
 
@@ -58,13 +65,31 @@ mod test_utils {
             // Mirror a real filesystem: a missing file is an error, not a panic. This lets
             // validators' read-failure paths be exercised with the fake.
             self.files
+                .lock()
+                .unwrap()
                 .get(&path.display().to_string())
                 .cloned()
                 .ok_or_else(|| anyhow::anyhow!("File {} not found", path.display()))
         }
 
+        fn write(&self, path: &Path, contents: &str) -> anyhow::Result<()> {
+            match self
+                .files
+                .lock()
+                .unwrap()
+                .get_mut(&path.display().to_string())
+            {
+                Some(file) => {
+                    *file = contents.to_string();
+                    Ok(())
+                }
+                None => Err(anyhow::anyhow!("File {} not found", path.display())),
+            }
+        }
+
         fn walk(&self) -> impl Iterator<Item = anyhow::Result<PathBuf>> {
-            self.files.keys().map(|p| Ok(PathBuf::from(p)))
+            let paths: Vec<_> = self.files.lock().unwrap().keys().cloned().collect();
+            paths.into_iter().map(|p| Ok(PathBuf::from(p)))
         }
     }
 
@@ -134,13 +159,96 @@ mod test_utils {
                 &file_system,
                 &FakePathChecker::allow_all(),
                 &parsers,
-                HashMap::new(),
+                ExtensionMappings::default(),
             )
             .unwrap(),
             parsers,
         ))
     }
 
+    /// Creates a [`ValidationContext`] for the changes from the `old_files` to the `new_files`.
+    ///
+    /// The changed blocks of the new files are modified, the blocks missing from the new files are
+    /// deleted, the files which differ are changed and the whole tree consists of all the blocks of
+    /// the new files.
+    pub(crate) fn validation_context_from_changes(
+        old_files: &[(&str, &str)],
+        new_files: &[(&str, &str)],
+    ) -> Arc<ValidationContext> {
+        let old_files: HashMap<&str, &str> = old_files.iter().copied().collect();
+        let new_files: HashMap<&str, &str> = new_files.iter().copied().collect();
+        let mut paths: Vec<&str> = old_files.keys().chain(new_files.keys()).copied().collect();
+        paths.sort();
+        paths.dedup();
+        let mut diff = String::new();
+        for path in paths {
+            let old = old_files.get(path).copied().unwrap_or_default();
+            let new = new_files.get(path).copied().unwrap_or_default();
+            if old == new {
+                continue;
+            }
+            let old_header = if old_files.contains_key(path) {
+                format!("a/{path}")
+            } else {
+                "/dev/null".to_string()
+            };
+            let new_header = if new_files.contains_key(path) {
+                format!("b/{path}")
+            } else {
+                "/dev/null".to_string()
+            };
+            diff.push_str(&format!("diff --git a/{path} b/{path}\n"));
+            diff.push_str(
+                &similar::TextDiff::from_lines(old, new)
+                    .unified_diff()
+                    .header(&old_header, &new_header)
+                    .to_string(),
+            );
+        }
+        let file_system = Arc::new(FakeFileSystem::new(
+            new_files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect(),
+        ));
+        let parsers = language_parsers::language_parsers().unwrap();
+        let line_changes_by_file = crate::diff_parser::line_changes_from_diff(&diff).unwrap();
+        let changed_files = line_changes_by_file.keys().cloned().collect();
+        let blocks = parse_blocks(
+            line_changes_by_file,
+            false,
+            file_system.as_ref(),
+            &FakePathChecker::allow_all(),
+            &parsers,
+            ExtensionMappings::default(),
+        )
+        .unwrap();
+        let deleted_blocks = crate::blocks::parse_deleted_blocks(
+            &crate::diff_parser::pre_images_from_diff(&diff).unwrap(),
+            file_system.as_ref(),
+            &FakePathChecker::allow_all(),
+            &parsers,
+            &ExtensionMappings::default(),
+        )
+        .unwrap();
+        let tree_parsers = parsers.clone();
+        Arc::new(
+            ValidationContext::new(blocks, parsers)
+                .with_deleted_blocks(deleted_blocks)
+                .with_changed_files(changed_files)
+                .with_tree_loader(move || {
+                    parse_blocks(
+                        HashMap::new(),
+                        true,
+                        file_system.as_ref(),
+                        &FakePathChecker::allow_all(),
+                        &tree_parsers,
+                        ExtensionMappings::default(),
+                    )
+                }),
+        )
+    }
+
     pub(crate) fn merge_validation_contexts(
         contexts: Vec<Arc<ValidationContext>>,
     ) -> Arc<ValidationContext> {
@@ -163,4 +271,16 @@ mod test_utils {
         }
         Arc::new(ValidationContext::new(merged_modified_blocks, parsers))
     }
+
+    /// Returns the messages of the `violations` reported in `file_path`, in the order they were
+    /// reported.
+    pub(crate) fn messages<'a>(
+        violations: &'a HashMap<PathBuf, Vec<Violation>>,
+        file_path: &str,
+    ) -> Vec<&'a str> {
+        violations
+            .get(Path::new(file_path))
+            .map(|violations| violations.iter().map(Violation::message).collect())
+            .unwrap_or_default()
+    }
 }
@@ -0,0 +1,817 @@
+use crate::blocks::{
+    self, Block, BlockSeverity, ExtensionMappings, FileBlocks, FileSystem, PathChecker,
+};
+use crate::config::Config;
+use crate::language_parsers::LanguageParsers;
+use crate::validators::{self, ValidationContext, Violation};
+use crate::{Position, diff_parser};
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    LogMessage, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, Location, LogMessageParams, MarkupContent,
+    MarkupKind, MessageType, NumberOrString, OneOf, PublishDiagnosticsParams, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Reads the open documents from memory and every other file from the wrapped file system.
+///
+/// Walking only returns `walk_paths`, so that [`blocks::parse_blocks`] parses just the documents
+/// of interest instead of the whole tree.
+struct OverlayFileSystem<Fs: FileSystem> {
+    inner: Arc<Fs>,
+    documents: HashMap<PathBuf, String>,
+    walk_paths: Vec<PathBuf>,
+}
+
+impl<Fs: FileSystem> FileSystem for OverlayFileSystem<Fs> {
+    fn read_to_string(&self, path: &Path) -> anyhow::Result<String> {
+        match self.documents.get(path) {
+            Some(text) => Ok(text.clone()),
+            None => self.inner.read_to_string(path),
+        }
+    }
+
+    fn write(&self, path: &Path, _contents: &str) -> anyhow::Result<()> {
+        anyhow::bail!(
+            "Writing \"{}\" is not supported by the language server",
+            path.display()
+        )
+    }
+
+    fn walk(&self) -> impl Iterator<Item = anyhow::Result<PathBuf>> {
+        self.walk_paths.clone().into_iter().map(Ok)
+    }
+}
+
+/// Allows every path, e.g. for resolving the blocks referenced by `affects` regardless of the globs.
+struct AnyPath;
+
+impl PathChecker for AnyPath {
+    fn should_allow(&self, _path: &Path) -> bool {
+        true
+    }
+
+    fn should_ignore(&self, _path: &Path) -> bool {
+        false
+    }
+}
+
+/// Settings the language server shares with the command-line run.
+pub struct ServerSettings<'a> {
+    pub config: &'a Config,
+    pub extensions: ExtensionMappings,
+    pub path_checker: blocks::PathCheckerImpl,
+    pub disabled_validators: HashSet<String>,
+    pub enabled_validators: HashSet<String>,
+}
+
+/// Protocol-independent state of the language server: the open documents and how to check them.
+///
+/// Paths are relative to the repository root and positions are 1-based, like everywhere else in
+/// the crate. Unsaved changes of the open documents (compared to the files on disk) are treated as
+/// the modified lines, e.g. for the `affects` validator.
+pub(crate) struct Server<'a, Fs: FileSystem> {
+    file_system: Arc<Fs>,
+    parsers: LanguageParsers,
+    settings: ServerSettings<'a>,
+    documents: HashMap<PathBuf, String>,
+}
+
+impl<'a, Fs: FileSystem + 'static> Server<'a, Fs> {
+    pub(crate) fn new(
+        file_system: Arc<Fs>,
+        parsers: LanguageParsers,
+        settings: ServerSettings<'a>,
+    ) -> Self {
+        Self {
+            file_system,
+            parsers,
+            settings,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Opens or replaces the document at `path` with its current `text`.
+    pub(crate) fn update(&mut self, path: PathBuf, text: String) {
+        self.documents.insert(path, text);
+    }
+
+    pub(crate) fn close(&mut self, path: &Path) {
+        self.documents.remove(path);
+    }
+
+    fn overlay(&self, walk_paths: Vec<PathBuf>) -> OverlayFileSystem<Fs> {
+        OverlayFileSystem {
+            inner: Arc::clone(&self.file_system),
+            documents: self.documents.clone(),
+            walk_paths,
+        }
+    }
+
+    /// Runs the sync validators on all the open documents.
+    ///
+    /// Every open document is present in the result (possibly without violations), so that the
+    /// diagnostics of the fixed documents can be cleared.
+    pub(crate) fn check(&self) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut line_changes_by_file = HashMap::new();
+        for (path, text) in &self.documents {
+            // A document which is not saved yet is entirely new.
+            let saved = self.file_system.read_to_string(path).unwrap_or_default();
+            line_changes_by_file.insert(
+                path.clone(),
+                diff_parser::line_changes_between(path, &saved, text)?,
+            );
+        }
+        let file_system = Arc::new(self.overlay(self.documents.keys().cloned().collect()));
+        let blocks = blocks::parse_blocks(
+            line_changes_by_file,
+            true,
+            file_system.as_ref(),
+            &self.settings.path_checker,
+            &self.parsers,
+            self.settings.extensions.clone(),
+        )?;
+        let mut context = ValidationContext::new(blocks, self.parsers.clone())
+            .with_extension_mappings(self.settings.extensions.clone());
+        context.disable_validators(|path| self.settings.config.disabled_validators_for(path));
+        let disabled_validators = self
+            .settings
+            .disabled_validators
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let enabled_validators = self
+            .settings
+            .enabled_validators
+            .iter()
+            .map(String::as_str)
+            .collect();
+        // Async validators (e.g. check-ai) are too slow and too expensive to run on every edit.
+        let (sync_validators, _) = validators::detect_validators(
+            &context,
+            &validators::detector_factories::<OverlayFileSystem<Fs>>(),
+            &disabled_validators,
+            &enabled_validators,
+            &file_system,
+        )?;
+        let mut violations = validators::run(Arc::new(context), sync_validators, Vec::new())?;
+        for path in self.documents.keys() {
+            violations.entry(path.clone()).or_default();
+        }
+        Ok(violations)
+    }
+
+    /// Parses the blocks of the file at `path`, preferring the open document over the saved file.
+    fn file_blocks(&self, path: &Path) -> anyhow::Result<Option<FileBlocks>> {
+        let file_system = self.overlay(vec![path.to_path_buf()]);
+        let mut blocks = blocks::parse_blocks(
+            HashMap::new(),
+            true,
+            &file_system,
+            &AnyPath,
+            &self.parsers,
+            self.settings.extensions.clone(),
+        )?;
+        Ok(blocks.remove(path))
+    }
+
+    /// Returns the block whose start tag contains the `position` in the document at `path`.
+    fn block_at(
+        &self,
+        path: &Path,
+        position: &Position,
+    ) -> anyhow::Result<Option<(Block, String)>> {
+        let Some(file_blocks) = self.file_blocks(path)? else {
+            return Ok(None);
+        };
+        Ok(file_blocks
+            .blocks_with_context
+            .into_iter()
+            .map(|block_with_context| block_with_context.block)
+            .find(|block| block.start_tag_position_range.contains(position))
+            .map(|block| (block, file_blocks.file_content)))
+    }
+
+    /// Resolves the `affects` references of the block tag at `position` in the document at `path`
+    /// to the start tags of the referenced blocks.
+    ///
+    /// If the `position` is on a single reference only that reference is resolved, otherwise all
+    /// of them are. References to missing blocks are skipped.
+    pub(crate) fn definition(
+        &self,
+        path: &Path,
+        position: &Position,
+    ) -> anyhow::Result<Vec<(PathBuf, RangeInclusive<Position>)>> {
+        let Some((block, source)) = self.block_at(path, position)? else {
+            return Ok(Vec::new());
+        };
+        let Some(affects) = block.attributes.get("affects") else {
+            return Ok(Vec::new());
+        };
+        let references = match reference_at(&source, &block, position) {
+            Some(reference) => validators::parse_affects_attribute(&reference)?,
+            None => validators::parse_affects_attribute(affects)?,
+        };
+        let mut locations = Vec::new();
+        for (file_path, name) in references {
+            let file_path = file_path.unwrap_or_else(|| path.to_path_buf());
+            // A reference to a missing or unsupported file can't be resolved.
+            let Ok(Some(file_blocks)) = self.file_blocks(&file_path) else {
+                continue;
+            };
+            locations.extend(
+                file_blocks
+                    .blocks_with_context
+                    .iter()
+                    .filter(|block_with_context| block_with_context.block.name() == Some(&name))
+                    .map(|block_with_context| {
+                        (
+                            file_path.clone(),
+                            block_with_context.block.start_tag_position_range.clone(),
+                        )
+                    }),
+            );
+        }
+        Ok(locations)
+    }
+
+    /// Returns a Markdown list of the attributes of the block tag at `position` in the document at
+    /// `path`.
+    pub(crate) fn hover(
+        &self,
+        path: &Path,
+        position: &Position,
+    ) -> anyhow::Result<Option<(String, RangeInclusive<Position>)>> {
+        let Some((block, _)) = self.block_at(path, position)? else {
+            return Ok(None);
+        };
+        let mut attributes: Vec<_> = block.attributes.iter().collect();
+        attributes.sort();
+        let mut markdown = format!("**block** `{}`\n", block.name_display());
+        for (key, value) in attributes {
+            markdown.push_str(&format!("\n- `{key}`: `{value}`"));
+        }
+        Ok(Some((markdown, block.start_tag_position_range)))
+    }
+}
+
+/// Returns the single `affects` reference at `position` within the start tag of the `block`.
+fn reference_at(source: &str, block: &Block, position: &Position) -> Option<String> {
+    let tag_start = byte_offset(source, block.start_tag_position_range.start())?;
+    let tag_end = byte_offset(source, block.start_tag_position_range.end())?;
+    let cursor = byte_offset(source, position)?;
+    let tag = source.get(tag_start..=tag_end)?;
+    let value_start = tag_start + tag.find("affects=")? + "affects=".len();
+    let quote = source[value_start..].chars().next()?;
+    let value_start = value_start + quote.len_utf8();
+    let value_end = value_start + source[value_start..].find(quote)?;
+    if !(value_start..value_end).contains(&cursor) {
+        return None;
+    }
+    let mut reference_start = value_start;
+    for reference in source[value_start..value_end].split(',') {
+        let reference_end = reference_start + reference.len();
+        if (reference_start..=reference_end).contains(&cursor) {
+            return Some(reference.to_string());
+        }
+        reference_start = reference_end + 1;
+    }
+    None
+}
+
+/// Returns the byte offset of the 1-based `position` in `source`.
+fn byte_offset(source: &str, position: &Position) -> Option<usize> {
+    let line_start = if position.line <= 1 {
+        0
+    } else {
+        source
+            .match_indices('\n')
+            .nth(position.line - 2)
+            .map(|(index, _)| index + 1)?
+    };
+    Some(line_start + position.character.saturating_sub(1))
+}
+
+/// Returns the line with the 1-based `line_number` in `source` (empty if there is no such line).
+fn source_line(source: &str, line_number: usize) -> &str {
+    source
+        .split('\n')
+        .nth(line_number.saturating_sub(1))
+        .unwrap_or_default()
+}
+
+/// Converts a 0-based `byte_column` in the line with the 1-based `line_number` into an LSP
+/// position, whose character is counted in UTF-16 code units.
+fn lsp_position(source: &str, line_number: usize, byte_column: usize) -> lsp_types::Position {
+    let line = source_line(source, line_number);
+    let mut byte_column = byte_column.min(line.len());
+    while !line.is_char_boundary(byte_column) {
+        byte_column -= 1;
+    }
+    lsp_types::Position::new(
+        line_number.saturating_sub(1) as u32,
+        line[..byte_column].encode_utf16().count() as u32,
+    )
+}
+
+/// Converts an inclusive range of 1-based positions into an LSP range.
+fn lsp_range(source: &str, start: &Position, end: &Position) -> lsp_types::Range {
+    lsp_types::Range::new(
+        lsp_position(source, start.line, start.character.saturating_sub(1)),
+        // The LSP range end is exclusive.
+        lsp_position(source, end.line, end.character),
+    )
+}
+
+/// Converts an LSP position into a 1-based position in `source`.
+fn from_lsp_position(source: &str, position: lsp_types::Position) -> Position {
+    let line_number = position.line as usize + 1;
+    let line = source_line(source, line_number);
+    let mut utf16_units = 0;
+    let mut byte_column = line.len();
+    for (index, c) in line.char_indices() {
+        if utf16_units >= position.character as usize {
+            byte_column = index;
+            break;
+        }
+        utf16_units += c.len_utf16();
+    }
+    Position::new(line_number, byte_column + 1)
+}
+
+fn lsp_severity(severity: BlockSeverity) -> DiagnosticSeverity {
+    match severity {
+        BlockSeverity::Error => DiagnosticSeverity::ERROR,
+        BlockSeverity::Warning => DiagnosticSeverity::WARNING,
+        BlockSeverity::Info => DiagnosticSeverity::INFORMATION,
+        BlockSeverity::Hint => DiagnosticSeverity::HINT,
+    }
+}
+
+fn lsp_diagnostic(source: &str, violation: &Violation) -> Diagnostic {
+    Diagnostic {
+        range: lsp_range(source, violation.range().start(), violation.range().end()),
+        severity: Some(lsp_severity(violation.severity())),
+        code: Some(NumberOrString::String(violation.code().to_string())),
+        source: Some("blockwatch".to_string()),
+        message: violation.message().to_string(),
+        data: violation.data().cloned(),
+        ..Default::default()
+    }
+}
+
+/// Translates between the LSP messages and the [`Server`].
+struct Session<'a, Fs: FileSystem> {
+    connection: Connection,
+    root_path: PathBuf,
+    server: Server<'a, Fs>,
+    // Documents with published diagnostics, so that they can be cleared once fixed or closed.
+    published: HashSet<PathBuf>,
+}
+
+impl<Fs: FileSystem + 'static> Session<'_, Fs> {
+    fn path(&self, uri: &Uri) -> Option<PathBuf> {
+        let path = url::Url::parse(uri.as_str()).ok()?.to_file_path().ok()?;
+        path.strip_prefix(&self.root_path)
+            .ok()
+            .map(Path::to_path_buf)
+    }
+
+    fn uri(&self, path: &Path) -> anyhow::Result<Uri> {
+        let url = url::Url::from_file_path(self.root_path.join(path))
+            .map_err(|()| anyhow::anyhow!("Failed to convert {} into a URI", path.display()))?;
+        Uri::from_str(url.as_str()).map_err(|e| anyhow::anyhow!("Invalid URI {url}: {e}"))
+    }
+
+    fn source(&self, path: &Path) -> String {
+        self.server
+            .documents
+            .get(path)
+            .cloned()
+            .or_else(|| self.server.file_system.read_to_string(path).ok())
+            .unwrap_or_default()
+    }
+
+    fn send_notification<N: lsp_types::notification::Notification>(
+        &self,
+        params: N::Params,
+    ) -> anyhow::Result<()> {
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                N::METHOD.to_string(),
+                params,
+            )))?;
+        Ok(())
+    }
+
+    fn log_error(&self, error: &anyhow::Error) -> anyhow::Result<()> {
+        self.send_notification::<LogMessage>(LogMessageParams {
+            typ: MessageType::ERROR,
+            message: format!("{error:#}"),
+        })
+    }
+
+    /// Checks the open documents and publishes their diagnostics.
+    ///
+    /// Errors (e.g. an incomplete block tag while typing) are logged and keep the previously
+    /// published diagnostics.
+    fn publish_diagnostics(&mut self) -> anyhow::Result<()> {
+        let violations = match self.server.check() {
+            Ok(violations) => violations,
+            Err(e) => return self.log_error(&e),
+        };
+        let stale: Vec<PathBuf> = self
+            .published
+            .iter()
+            .filter(|path| !violations.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in stale {
+            self.send_notification::<PublishDiagnostics>(PublishDiagnosticsParams::new(
+                self.uri(&path)?,
+                Vec::new(),
+                None,
+            ))?;
+            self.published.remove(&path);
+        }
+        for (path, file_violations) in &violations {
+            let source = self.source(path);
+            let diagnostics = file_violations
+                .iter()
+                .map(|violation| lsp_diagnostic(&source, violation))
+                .collect();
+            self.send_notification::<PublishDiagnostics>(PublishDiagnosticsParams::new(
+                self.uri(path)?,
+                diagnostics,
+                None,
+            ))?;
+            self.published.insert(path.clone());
+        }
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> anyhow::Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Some(path) = self.path(&params.text_document.uri) {
+                    self.server.update(path, params.text_document.text);
+                    self.publish_diagnostics()?;
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // The server only supports full document sync, so the last change is the text.
+                if let (Some(path), Some(change)) = (
+                    self.path(&params.text_document.uri),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    self.server.update(path, change.text);
+                    self.publish_diagnostics()?;
+                }
+            }
+            DidSaveTextDocument::METHOD => self.publish_diagnostics()?,
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Some(path) = self.path(&params.text_document.uri) {
+                    self.server.close(&path);
+                    self.publish_diagnostics()?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => serde_json::from_value(request.params)
+                .map_err(anyhow::Error::from)
+                .and_then(|params| self.definition(params))
+                .and_then(|response| Ok(serde_json::to_value(response)?)),
+            HoverRequest::METHOD => serde_json::from_value(request.params)
+                .map_err(anyhow::Error::from)
+                .and_then(|params| self.hover(params))
+                .and_then(|response| Ok(serde_json::to_value(response)?)),
+            _ => {
+                return Response::new_err(
+                    request.id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request: {}", request.method),
+                );
+            }
+        };
+        match result {
+            Ok(value) => Response::new_ok(request.id, value),
+            Err(e) => Response::new_err(
+                request.id,
+                lsp_server::ErrorCode::InternalError as i32,
+                format!("{e:#}"),
+            ),
+        }
+    }
+
+    fn definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> anyhow::Result<Option<GotoDefinitionResponse>> {
+        let text_document_position = params.text_document_position_params;
+        let Some(path) = self.path(&text_document_position.text_document.uri) else {
+            return Ok(None);
+        };
+        let source = self.source(&path);
+        let position = from_lsp_position(&source, text_document_position.position);
+        let mut locations = Vec::new();
+        for (target_path, range) in self.server.definition(&path, &position)? {
+            let target_source = self.source(&target_path);
+            locations.push(Location::new(
+                self.uri(&target_path)?,
+                lsp_range(&target_source, range.start(), range.end()),
+            ));
+        }
+        Ok((!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations)))
+    }
+
+    fn hover(&self, params: HoverParams) -> anyhow::Result<Option<Hover>> {
+        let text_document_position = params.text_document_position_params;
+        let Some(path) = self.path(&text_document_position.text_document.uri) else {
+            return Ok(None);
+        };
+        let source = self.source(&path);
+        let position = from_lsp_position(&source, text_document_position.position);
+        Ok(self
+            .server
+            .hover(&path, &position)?
+            .map(|(markdown, range)| Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: markdown,
+                }),
+                range: Some(lsp_range(&source, range.start(), range.end())),
+            }))
+    }
+
+    fn run(mut self) -> anyhow::Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => {
+                    if let Err(e) = self.handle_notification(notification) {
+                        self.log_error(&e)?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the language server over stdio until the client shuts it down.
+///
+/// Diagnostics are published for the open documents whenever they are opened, changed or saved.
+pub fn run(
+    root_path: PathBuf,
+    parsers: LanguageParsers,
+    settings: ServerSettings,
+) -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..Default::default()
+    })?;
+    connection.initialize(capabilities)?;
+    let file_system = Arc::new(blocks::FileSystemImpl::new(root_path.clone()));
+    Session {
+        connection,
+        root_path,
+        server: Server::new(file_system, parsers, settings),
+        published: HashSet::new(),
+    }
+    .run()?;
+    io_threads.join()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language_parsers;
+    use crate::test_utils::FakeFileSystem;
+    use globset::{Glob, GlobSet};
+
+    fn server<'a>(config: &'a Config, files: &[(&str, &str)]) -> Server<'a, FakeFileSystem> {
+        let file_system = FakeFileSystem::new(
+            files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect(),
+        );
+        Server::new(
+            Arc::new(file_system),
+            language_parsers::language_parsers().unwrap(),
+            ServerSettings {
+                config,
+                extensions: ExtensionMappings::default(),
+                path_checker: blocks::PathCheckerImpl::new(
+                    GlobSet::new([Glob::new("**").unwrap()]).unwrap(),
+                    GlobSet::empty(),
+                ),
+                disabled_validators: HashSet::new(),
+                enabled_validators: HashSet::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn check_returns_violations_of_open_documents_only() -> anyhow::Result<()> {
+        let config = Config::default();
+        let unsorted = "# <block keep-sorted>\nb\na\n# </block>\n";
+        let mut server = server(&config, &[("a.py", unsorted), ("b.py", unsorted)]);
+        server.update("a.py".into(), unsorted.to_string());
+
+        let violations = server.check()?;
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[Path::new("a.py")][0].code(), "keep-sorted");
+        Ok(())
+    }
+
+    #[test]
+    fn check_uses_unsaved_document_text() -> anyhow::Result<()> {
+        let config = Config::default();
+        let mut server = server(
+            &config,
+            &[("a.py", "# <block keep-sorted>\nb\na\n# </block>\n")],
+        );
+        server.update(
+            "a.py".into(),
+            "# <block keep-sorted>\na\nb\n# </block>\n".to_string(),
+        );
+
+        let violations = server.check()?;
+
+        assert_eq!(violations.len(), 1);
+        assert!(violations[Path::new("a.py")].is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn check_treats_unsaved_changes_as_modified_lines() -> anyhow::Result<()> {
+        let config = Config::default();
+        let saved =
+            "# <block affects=\":b\">\na\n# </block>\n# <block name=\"b\">\nb\n# </block>\n";
+        let mut server = server(&config, &[("a.py", saved)]);
+        server.update("a.py".into(), saved.to_string());
+
+        assert!(server.check()?[Path::new("a.py")].is_empty());
+
+        server.update("a.py".into(), saved.replace("\na\n", "\nchanged\n"));
+        let violations = server.check()?;
+
+        assert_eq!(violations[Path::new("a.py")].len(), 1);
+        assert_eq!(violations[Path::new("a.py")][0].code(), "affects");
+        Ok(())
+    }
+
+    #[test]
+    fn closed_document_is_not_checked() -> anyhow::Result<()> {
+        let config = Config::default();
+        let unsorted = "# <block keep-sorted>\nb\na\n# </block>\n";
+        let mut server = server(&config, &[("a.py", unsorted)]);
+        server.update("a.py".into(), unsorted.to_string());
+        server.close(Path::new("a.py"));
+
+        assert!(server.check()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn definition_on_reference_returns_referenced_block() -> anyhow::Result<()> {
+        let config = Config::default();
+        let source = "# <block affects=\"b.py:foo, :bar\">\n# </block>\n# <block name=\"bar\">\n# </block>\n";
+        let server = server(
+            &config,
+            &[
+                ("a.py", source),
+                ("b.py", "x = 1\n# <block name=\"foo\">\n# </block>\n"),
+            ],
+        );
+
+        let on_foo = server.definition(Path::new("a.py"), &Position::new(1, 22))?;
+        let on_bar = server.definition(Path::new("a.py"), &Position::new(1, 31))?;
+        let on_tag = server.definition(Path::new("a.py"), &Position::new(1, 4))?;
+
+        assert_eq!(
+            on_foo,
+            vec![(
+                PathBuf::from("b.py"),
+                Position::new(2, 3)..=Position::new(2, 20)
+            )]
+        );
+        assert_eq!(
+            on_bar,
+            vec![(
+                PathBuf::from("a.py"),
+                Position::new(3, 3)..=Position::new(3, 20)
+            )]
+        );
+        assert_eq!(on_tag.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn definition_outside_block_tag_returns_nothing() -> anyhow::Result<()> {
+        let config = Config::default();
+        let server = server(
+            &config,
+            &[("a.py", "# <block affects=\":b\">\nx\n# </block>\n")],
+        );
+
+        assert!(
+            server
+                .definition(Path::new("a.py"), &Position::new(2, 1))?
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hover_on_block_tag_lists_attributes() -> anyhow::Result<()> {
+        let config = Config::default();
+        let server = server(
+            &config,
+            &[(
+                "a.py",
+                "# <block name=\"foo\" keep-sorted=\"desc\">\n# </block>\n",
+            )],
+        );
+
+        let hover = server.hover(Path::new("a.py"), &Position::new(1, 10))?;
+
+        assert_eq!(
+            hover,
+            Some((
+                "**block** `foo`\n\n- `keep-sorted`: `desc`\n- `name`: `foo`".to_string(),
+                Position::new(1, 3)..=Position::new(1, 39)
+            ))
+        );
+        assert!(
+            server
+                .hover(Path::new("a.py"), &Position::new(2, 1))?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lsp_range_converts_inclusive_byte_columns_into_utf16_positions() {
+        let source = "a\n\u{1F600}bc\n";
+
+        let range = lsp_range(source, &Position::new(2, 5), &Position::new(2, 6));
+
+        assert_eq!(
+            range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(1, 2),
+                lsp_types::Position::new(1, 4)
+            )
+        );
+    }
+
+    #[test]
+    fn from_lsp_position_converts_utf16_positions_into_byte_columns() {
+        let source = "a\n\u{1F600}bc\n";
+
+        assert_eq!(
+            from_lsp_position(source, lsp_types::Position::new(1, 2)),
+            Position::new(2, 5)
+        );
+        assert_eq!(
+            from_lsp_position(source, lsp_types::Position::new(1, 10)),
+            Position::new(2, 7)
+        );
+    }
+}
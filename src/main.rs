@@ -1,25 +1,38 @@
 use anyhow::Context;
 use blockwatch::blocks;
-use blockwatch::blocks::BlockSeverity;
+use blockwatch::blocks::{BlockSeverity, FileSystem, PathChecker};
+use blockwatch::config::Config;
 use blockwatch::diff_parser;
+use blockwatch::fix;
 use blockwatch::flags;
+use blockwatch::git;
 use blockwatch::language_parsers;
+use blockwatch::lsp;
+use blockwatch::reporters;
 use blockwatch::validators;
 
 use blockwatch::validators::Violation;
 use clap::Parser;
 use globset::GlobSet;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{IsTerminal, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{env, fs, process};
 
 fn main() -> anyhow::Result<()> {
-    let args = flags::Args::parse();
+    let mut args = flags::Args::parse();
+    let root_path = repository_root()?;
+    let config = Arc::new(Config::load(&root_path)?);
+    args.merge_config(&config);
     match &args.command {
-        Some(flags::SubCommand::List { diff, .. }) => run_list(&args, *diff),
-        None => run_validators(&args),
+        Some(flags::SubCommand::List { diff, .. }) => run_list(&args, &config, root_path, *diff),
+        Some(flags::SubCommand::Fix { diff, dry_run, .. }) => {
+            run_fix(&args, &config, root_path, *diff, *dry_run)
+        }
+        Some(flags::SubCommand::CheckRefs { .. }) => run_check_refs(&args, &config, root_path),
+        Some(flags::SubCommand::Lsp) => run_lsp(&args, &config, root_path),
+        None => run_validators(&args, &config, root_path),
     }
 }
 
@@ -28,72 +41,261 @@ fn main() -> anyhow::Result<()> {
 /// A diff is read from stdin only when `--diff` is set (and stdin is not a terminal), to
 /// populate `is_content_modified`. Otherwise `list` never touches stdin, so it is safe to run
 /// non-interactively — piped to `jq`, in CI, or when spawned by another program such as an AI agent.
-fn run_list(args: &flags::Args, read_diff_flag: bool) -> anyhow::Result<()> {
-    let read_diff = read_diff_flag && !stdin_is_terminal();
-    let file_system = blocks::FileSystemImpl::new(repository_root()?);
-    let context = build_context(args, read_diff, &file_system)?;
+fn run_list(
+    args: &flags::Args,
+    config: &Arc<Config>,
+    root_path: PathBuf,
+    read_diff_flag: bool,
+) -> anyhow::Result<()> {
+    let diff = diff_from_stdin(read_diff_flag)?;
+    let file_system = Arc::new(blocks::FileSystemImpl::new(root_path));
+    let context = build_context(args, config, diff.as_deref(), &file_system)?;
     let report = context.to_serializable_report();
     serde_json::to_writer_pretty(std::io::stdout(), &report).context("Failed to list blocks")
 }
 
+/// Runs `fix`: rewrites the blocks whose violations can be fixed automatically.
+///
+/// Like `list`, a diff is read from stdin only when `--diff` is set, to limit the fixes to the
+/// blocks it touched. With `--dry-run` the fixes are printed as a unified diff instead of written.
+fn run_fix(
+    args: &flags::Args,
+    config: &Arc<Config>,
+    root_path: PathBuf,
+    read_diff_flag: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let diff = diff_from_stdin(read_diff_flag)?;
+    let file_system = Arc::new(blocks::FileSystemImpl::new(root_path.clone()));
+    let mut context =
+        build_context(args, config, diff.as_deref(), &file_system)?.with_root_path(root_path);
+    context.disable_validators(|path| config.disabled_validators_for(path));
+    let fixes = fix::fix_blocks(
+        &context,
+        &validators::fixers(&file_system),
+        &args.disabled_validators(),
+        &args.enabled_validators(),
+    )?;
+    if dry_run {
+        let mut stdout = std::io::stdout().lock();
+        for file_fix in &fixes {
+            write!(&mut stdout, "{}", file_fix.unified_diff())?;
+        }
+        return Ok(());
+    }
+    fix::write_fixes(&fixes, file_system.as_ref())?;
+    for file_fix in &fixes {
+        eprintln!("Fixed {}", file_fix.path().display());
+    }
+    Ok(())
+}
+
+/// Runs `check-refs`: reports the `affects` references which don't resolve to exactly one other
+/// block.
+///
+/// The references are resolved against the whole tree, while the globs and `--ignore` limit the
+/// blocks whose references are checked.
+fn run_check_refs(args: &flags::Args, config: &Config, root_path: PathBuf) -> anyhow::Result<()> {
+    let language_parsers = language_parsers::language_parsers()?;
+    let supported_extensions = language_parsers.keys().collect();
+    args.validate(&supported_extensions)?;
+    config.validate(&supported_extensions)?;
+
+    let file_system = blocks::FileSystemImpl::new(root_path);
+    let existing_files = file_system.walk().collect::<anyhow::Result<HashSet<_>>>()?;
+    let tree = blocks::parse_blocks(
+        HashMap::new(),
+        true,
+        &file_system,
+        &blocks::PathCheckerImpl::new(GlobSet::new([globset::Glob::new("**")?])?, GlobSet::empty()),
+        &language_parsers,
+        config.extension_mappings(args.extensions()),
+    )?;
+    let mut violations = validators::check_references(&tree, &existing_files)?;
+
+    let mut glob_set = args.globs()?;
+    if glob_set.is_empty() {
+        glob_set = GlobSet::new([globset::Glob::new("**")?])?;
+    }
+    let path_checker = blocks::PathCheckerImpl::new(glob_set, args.ignored_globs()?);
+    violations
+        .retain(|path, _| path_checker.should_allow(path) && !path_checker.should_ignore(path));
+    process_violations(violations, args.format, &file_system)
+}
+
+/// Runs `lsp`: serves diagnostics for the open documents over stdio until the editor exits.
+///
+/// Only the sync validators are run, on every change of an open document. Globs limit the
+/// documents that are checked, as does `--ignore`.
+fn run_lsp(args: &flags::Args, config: &Config, root_path: PathBuf) -> anyhow::Result<()> {
+    let language_parsers = language_parsers::language_parsers()?;
+    let supported_extensions = language_parsers.keys().collect();
+    args.validate(&supported_extensions)?;
+    config.validate(&supported_extensions)?;
+
+    let mut glob_set = args.globs()?;
+    if glob_set.is_empty() {
+        glob_set = GlobSet::new([globset::Glob::new("**")?])?;
+    }
+    let settings = lsp::ServerSettings {
+        config,
+        extensions: config.extension_mappings(args.extensions()),
+        path_checker: blocks::PathCheckerImpl::new(glob_set, args.ignored_globs()?),
+        disabled_validators: args
+            .disabled_validators()
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        enabled_validators: args
+            .enabled_validators()
+            .into_iter()
+            .map(String::from)
+            .collect(),
+    };
+    lsp::run(root_path, language_parsers, settings)
+}
+
 /// Runs the default command: validates every block in scope and reports any violations.
 ///
-/// The diff to validate is read from stdin whenever stdin is not a terminal (i.e. when a
-/// `git diff` is piped in); otherwise the whole working tree is checked.
-fn run_validators(args: &flags::Args) -> anyhow::Result<()> {
-    let file_system = Arc::new(blocks::FileSystemImpl::new(repository_root()?));
-    let context = build_context(args, !stdin_is_terminal(), file_system.as_ref())?;
+/// The changes to validate come from `git diff` when `--staged`, `--since` or `--range` is set
+/// and are otherwise read from stdin whenever stdin is not a terminal (i.e. when a `git diff` is
+/// piped in); with no changes at all the whole working tree is checked. Validators disabled for a
+/// path by the project configuration are not run on the blocks in that path.
+fn run_validators(
+    args: &flags::Args,
+    config: &Arc<Config>,
+    root_path: PathBuf,
+) -> anyhow::Result<()> {
+    let diff_source = args.diff_source();
+    let diff = match &diff_source {
+        Some(source) => Some(git::diff(&root_path, source)?),
+        None if !stdin_is_terminal() => Some(read_diff_from_stdin()?),
+        None => None,
+    };
+    match diff_source.and_then(|source| git::file_system(&root_path, &source)) {
+        Some(file_system) => validate(
+            args,
+            config,
+            diff.as_deref(),
+            Arc::new(file_system),
+            &root_path,
+        ),
+        None => validate(
+            args,
+            config,
+            diff.as_deref(),
+            Arc::new(blocks::FileSystemImpl::new(root_path.clone())),
+            &root_path,
+        ),
+    }
+}
+
+/// Validates the blocks in scope of the `file_system` and reports any violations.
+///
+/// The `check-ai` cache and fixtures are kept in the repository at `root_path`, where the commands
+/// of the blocks run.
+fn validate<Fs: blocks::FileSystem + 'static>(
+    args: &flags::Args,
+    config: &Arc<Config>,
+    diff: Option<&str>,
+    file_system: Arc<Fs>,
+    root_path: &Path,
+) -> anyhow::Result<()> {
+    let mut context = build_context(args, config, diff, &file_system)?
+        .with_ai_cache_dir((!args.no_ai_cache).then(|| root_path.join(validators::AI_CACHE_DIR)))
+        .with_ai_fixtures_path(root_path.join(validators::AI_FIXTURES_FILE))
+        .with_verbose(args.verbose)
+        .with_root_path(root_path.to_path_buf());
+    context.disable_validators(|path| config.disabled_validators_for(path));
     let (sync_validators, async_validators) = validators::detect_validators(
         &context,
-        &validators::detector_factories::<blocks::FileSystemImpl>(),
+        &validators::detector_factories::<Fs>(),
         &args.disabled_validators(),
         &args.enabled_validators(),
         &file_system,
     )?;
     let violations = validators::run(Arc::new(context), sync_validators, async_validators)?;
-    if !violations.is_empty() {
-        process_violations(violations)?;
-    }
-    Ok(())
+    process_violations(violations, args.format, file_system.as_ref())
 }
 
 /// Parses every block the run should consider into a `ValidationContext`.
 ///
-/// When `read_diff` is set, a unified diff is read from stdin and used to mark which blocks
-/// changed. With neither globs nor a diff to scope the run, the whole tree is scanned.
-fn build_context(
+/// The unified `diff` (if any) marks which blocks changed and which were deleted. With neither
+/// globs nor a diff to scope the run, the whole tree is scanned. The whole tree can also be loaded
+/// lazily by the validators that need it, e.g. to find the references to the deleted blocks.
+fn build_context<Fs: blocks::FileSystem + 'static>(
     args: &flags::Args,
-    read_diff: bool,
-    file_system: &impl blocks::FileSystem,
+    config: &Arc<Config>,
+    diff: Option<&str>,
+    file_system: &Arc<Fs>,
 ) -> anyhow::Result<validators::ValidationContext> {
     let language_parsers = language_parsers::language_parsers()?;
     let supported_extensions = language_parsers.keys().collect();
     args.validate(&supported_extensions)?;
+    config.validate(&supported_extensions)?;
 
-    let modified_lines_by_file = if read_diff {
-        read_diff_from_stdin()?
-    } else {
-        HashMap::new()
+    let modified_lines_by_file = match diff {
+        Some(diff) => diff_parser::line_changes_from_diff(diff)?,
+        None => HashMap::new(),
     };
+    let changed_files = modified_lines_by_file.keys().cloned().collect();
 
     let mut glob_set = args.globs()?;
-    if glob_set.is_empty() && !read_diff {
+    if glob_set.is_empty() && diff.is_none() {
         // Nothing scopes the run, so match every file.
         glob_set = GlobSet::new([globset::Glob::new("**")?])?;
     }
     let should_scan_files = !glob_set.is_empty();
 
     let path_checker = blocks::PathCheckerImpl::new(glob_set, args.ignored_globs()?);
+    let extension_mappings = config.extension_mappings(args.extensions());
 
     let blocks = blocks::parse_blocks(
         modified_lines_by_file,
         should_scan_files,
-        file_system,
+        file_system.as_ref(),
         &path_checker,
         &language_parsers,
-        args.extensions(),
+        extension_mappings.clone(),
     )?;
-    Ok(validators::ValidationContext::new(blocks, language_parsers))
+    let deleted_blocks = match diff {
+        Some(diff) => blocks::parse_deleted_blocks(
+            &diff_parser::pre_images_from_diff(diff)?,
+            file_system.as_ref(),
+            &path_checker,
+            &language_parsers,
+            &extension_mappings,
+        )?,
+        None => HashMap::new(),
+    };
+
+    let tree_loader = {
+        let extension_mappings = extension_mappings.clone();
+        let file_system = Arc::clone(file_system);
+        let config = Arc::clone(config);
+        let path_checker = blocks::PathCheckerImpl::new(
+            GlobSet::new([globset::Glob::new("**")?])?,
+            args.ignored_globs()?,
+        );
+        let language_parsers = language_parsers.clone();
+        move || {
+            let mut tree = blocks::parse_blocks(
+                HashMap::new(),
+                true,
+                file_system.as_ref(),
+                &path_checker,
+                &language_parsers,
+                extension_mappings.clone(),
+            )?;
+            validators::disable_validators(&mut tree, |path| config.disabled_validators_for(path));
+            Ok(tree)
+        }
+    };
+    Ok(validators::ValidationContext::new(blocks, language_parsers)
+        .with_deleted_blocks(deleted_blocks)
+        .with_changed_files(changed_files)
+        .with_extension_mappings(extension_mappings)
+        .with_tree_loader(tree_loader))
 }
 
 /// Whether stdin is connected to an interactive terminal, i.e. no diff is piped in.
@@ -101,32 +303,50 @@ fn stdin_is_terminal() -> bool {
     std::io::stdin().is_terminal()
 }
 
-/// Reads a unified diff from stdin and parses it into per-file line changes.
-fn read_diff_from_stdin() -> anyhow::Result<HashMap<PathBuf, Vec<diff_parser::LineChange>>> {
+/// Reads the diff from stdin if `read_diff_flag` is set and stdin is not a terminal.
+fn diff_from_stdin(read_diff_flag: bool) -> anyhow::Result<Option<String>> {
+    if read_diff_flag && !stdin_is_terminal() {
+        read_diff_from_stdin().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads a unified diff from stdin.
+fn read_diff_from_stdin() -> anyhow::Result<String> {
     let mut diff = String::new();
     std::io::stdin().read_to_string(&mut diff)?;
-    diff_parser::line_changes_from_diff(&diff)
+    Ok(diff)
 }
 
-fn process_violations(violations: HashMap<PathBuf, Vec<Violation>>) -> anyhow::Result<()> {
-    let mut has_error_severity = false;
-    let mut diagnostics: HashMap<PathBuf, Vec<serde_json::Value>> =
-        HashMap::with_capacity(violations.len());
-    for (file_path, file_violations) in violations {
-        let mut file_diagnostics = Vec::with_capacity(file_violations.len());
-        for violation in file_violations {
-            let diagnostic = violation.as_simple_diagnostic();
-            if diagnostic.severity() == BlockSeverity::Error {
-                has_error_severity = true;
-            }
-            file_diagnostics.push(serde_json::to_value(diagnostic)?);
-        }
-        diagnostics.insert(file_path, file_diagnostics);
+/// Writes the `violations` report to stderr and exits with code 1 if any of them has the error
+/// severity. Nothing is written without violations, except for a SARIF log.
+///
+/// Without an explicit `format`, a colored text report is written when stderr is a terminal and
+/// JSON otherwise. Colors are disabled by a non-empty `NO_COLOR` environment variable.
+fn process_violations(
+    violations: HashMap<PathBuf, Vec<Violation>>,
+    format: Option<reporters::OutputFormat>,
+    file_system: &impl blocks::FileSystem,
+) -> anyhow::Result<()> {
+    // Consumers of SARIF (e.g. code scanning uploads) expect a log even without results.
+    if violations.is_empty() && format != Some(reporters::OutputFormat::Sarif) {
+        return Ok(());
     }
+    let has_error_severity = violations
+        .values()
+        .flatten()
+        .any(|violation| violation.as_simple_diagnostic().severity() == BlockSeverity::Error);
 
+    let stderr_is_terminal = std::io::stderr().is_terminal();
+    let format = format.unwrap_or(if stderr_is_terminal {
+        reporters::OutputFormat::Text
+    } else {
+        reporters::OutputFormat::Json
+    });
+    let color = stderr_is_terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
     let mut stderr = std::io::stderr().lock();
-    serde_json::to_writer_pretty(&mut stderr, &diagnostics)?;
-    writeln!(&mut stderr)?;
+    reporters::write_report(format, &violations, file_system, color, &mut stderr)?;
     if has_error_severity {
         process::exit(1);
     }
@@ -0,0 +1,68 @@
+use crate::validators::Violation;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Writes the simple diagnostics of the `violations` as a pretty-printed JSON object keyed by file
+/// path.
+pub(super) fn write_report(
+    violations: &HashMap<PathBuf, Vec<Violation>>,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let mut diagnostics: HashMap<&PathBuf, Vec<serde_json::Value>> =
+        HashMap::with_capacity(violations.len());
+    for (file_path, file_violations) in violations {
+        let mut file_diagnostics = Vec::with_capacity(file_violations.len());
+        for violation in file_violations {
+            file_diagnostics.push(serde_json::to_value(violation.as_simple_diagnostic())?);
+        }
+        diagnostics.insert(file_path, file_diagnostics);
+    }
+    serde_json::to_writer_pretty(&mut *writer, &diagnostics)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Position;
+    use crate::blocks::BlockSeverity;
+    use crate::validators::ViolationRange;
+    use serde_json::json;
+
+    #[test]
+    fn violations_are_written_as_simple_diagnostics_keyed_by_file_path() -> anyhow::Result<()> {
+        let violations = HashMap::from([(
+            PathBuf::from("a.py"),
+            vec![Violation::new(
+                ViolationRange::new(Position::new(2, 1), Position::new(2, 3)),
+                "keep-sorted".to_string(),
+                "out of order".to_string(),
+                BlockSeverity::Warning,
+                Some(json!({"order_by": "asc"})),
+            )],
+        )]);
+        let mut output = Vec::new();
+
+        write_report(&violations, &mut output)?;
+
+        let actual: serde_json::Value = serde_json::from_slice(&output)?;
+        assert_eq!(
+            actual,
+            json!({
+                "a.py": [{
+                    "range": {
+                        "start": {"line": 2, "character": 1},
+                        "end": {"line": 2, "character": 3}
+                    },
+                    "code": "keep-sorted",
+                    "message": "out of order",
+                    "severity": 2,
+                    "data": {"order_by": "asc"}
+                }]
+            })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+mod json;
+mod sarif;
+mod text;
+
+use crate::blocks::FileSystem;
+use crate::validators::Violation;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Format of the violations report.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable diagnostics with source snippets.
+    Text,
+    /// Pretty-printed JSON object with the diagnostics grouped by file path.
+    Json,
+    /// [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html) log, e.g.
+    /// for GitHub code scanning.
+    Sarif,
+}
+
+/// Writes the `violations` grouped by file path to `writer` in the given `format`.
+///
+/// The text format reads the source snippets from the `file_system` and uses ANSI colors if
+/// `color` is set; the other formats ignore both.
+pub fn write_report(
+    format: OutputFormat,
+    violations: &HashMap<PathBuf, Vec<Violation>>,
+    file_system: &impl FileSystem,
+    color: bool,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => text::write_report(violations, file_system, color, writer),
+        OutputFormat::Json => json::write_report(violations, writer),
+        OutputFormat::Sarif => sarif::write_report(violations, writer),
+    }
+}
+
+/// Returns the `violations` sorted by file path, so that reports are deterministic.
+fn sorted_by_path(
+    violations: &HashMap<PathBuf, Vec<Violation>>,
+) -> Vec<(&PathBuf, &Vec<Violation>)> {
+    let mut sorted: Vec<_> = violations.iter().collect();
+    sorted.sort_by_key(|(path, _)| *path);
+    sorted
+}
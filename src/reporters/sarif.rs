@@ -0,0 +1,248 @@
+use crate::blocks::{BlockSeverity, FileSystemImpl};
+use crate::validators::{self, Violation};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const INFORMATION_URI: &str = "https://github.com/mennanov/blockwatch";
+
+/// Returns a short description of the rule reported by the validator `code`.
+fn rule_description(code: &str) -> &'static str {
+    match code {
+        // <block name="sarif-rules">
+        "affects" => "Blocks affected by a modified block must be updated too.",
+        "keep-sorted" => "Block lines must be sorted.",
+        "keep-unique" => "Block lines must be unique.",
+        "line-pattern" => "Block lines must match a regex pattern.",
+        "line-count" => "Block must satisfy a line count constraint.",
+        "check-ai" => "Block must satisfy a natural language condition checked by an AI model.",
+        "check-lua" => "Block must pass a custom Lua script check.",
+        "sync-group" => "All the blocks of a sync group must be updated together.",
+        "keep-in-sync" => "Block content must match the content of another block.",
+        "keep-same-values" => "Block values must match the values of another block.",
+        "unique-names" => "Block names must be unique within a file.",
+        "mirrors-file" => "Block content must match the content of the mirrored file.",
+        "generate-cmd" => "Block content must match the output of its command.",
+        // </block>
+        _ => "BlockWatch rule.",
+    }
+}
+
+/// Writes the `violations` as a SARIF 2.1.0 log with a single run.
+///
+/// Every registered validator becomes a rule (so that the rule indices are stable across runs) and
+/// every violation becomes a result pointing at its range. The violation `data` is kept in the
+/// result's `properties`.
+pub(super) fn write_report(
+    violations: &HashMap<PathBuf, Vec<Violation>>,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let mut rule_ids: Vec<&str> = validators::detector_factories::<FileSystemImpl>()
+        .iter()
+        .map(|(validator_name, _)| *validator_name)
+        .collect();
+    let mut results = Vec::new();
+    for (file_path, file_violations) in super::sorted_by_path(violations) {
+        for violation in file_violations {
+            let rule_index = match rule_ids.iter().position(|id| *id == violation.code()) {
+                Some(index) => index,
+                None => {
+                    rule_ids.push(violation.code());
+                    rule_ids.len() - 1
+                }
+            };
+            results.push(result(file_path, violation, rule_index));
+        }
+    }
+    let rules: Vec<Value> = rule_ids
+        .iter()
+        .map(|id| {
+            json!({
+                "id": id,
+                "name": id,
+                "shortDescription": {"text": rule_description(id)},
+                "helpUri": INFORMATION_URI,
+            })
+        })
+        .collect();
+    let log = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "blockwatch",
+                    "informationUri": INFORMATION_URI,
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_writer_pretty(&mut *writer, &log)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Converts the `violation` found in `file_path` into a SARIF result.
+fn result(file_path: &Path, violation: &Violation, rule_index: usize) -> Value {
+    let range = violation.range();
+    let mut result = json!({
+        "ruleId": violation.code(),
+        "ruleIndex": rule_index,
+        "level": level(violation.severity()),
+        "message": {"text": violation.message()},
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": {
+                    // SARIF URIs always use forward slashes.
+                    "uri": file_path.to_string_lossy().replace('\\', "/"),
+                    "uriBaseId": "%SRCROOT%",
+                },
+                "region": {
+                    "startLine": range.start().line,
+                    "startColumn": range.start().character,
+                    "endLine": range.end().line,
+                    // SARIF end columns are exclusive, violation ranges are inclusive.
+                    "endColumn": range.end().character + 1,
+                },
+            }
+        }],
+    });
+    if let Some(data) = violation.data() {
+        result["properties"] = json!({ "data": data });
+    }
+    result
+}
+
+/// Maps the block severity to the SARIF result level.
+fn level(severity: BlockSeverity) -> &'static str {
+    match severity {
+        BlockSeverity::Error => "error",
+        BlockSeverity::Warning => "warning",
+        BlockSeverity::Info | BlockSeverity::Hint => "note",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Position;
+    use crate::validators::ViolationRange;
+
+    fn violation(code: &str, severity: BlockSeverity, data: Option<Value>) -> Violation {
+        Violation::new(
+            ViolationRange::new(Position::new(3, 5), Position::new(4, 7)),
+            code.to_string(),
+            format!("{code} violation"),
+            severity,
+            data,
+        )
+    }
+
+    fn report(violations: HashMap<PathBuf, Vec<Violation>>) -> anyhow::Result<Value> {
+        let mut output = Vec::new();
+        write_report(&violations, &mut output)?;
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    #[test]
+    fn no_violations_returns_log_with_all_rules_and_no_results() -> anyhow::Result<()> {
+        let log = report(HashMap::new())?;
+
+        assert_eq!(log["version"], "2.1.0");
+        assert_eq!(log["$schema"], SARIF_SCHEMA);
+        let driver = &log["runs"][0]["tool"]["driver"];
+        assert_eq!(driver["name"], "blockwatch");
+        let rule_ids: Vec<&str> = driver["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|rule| rule["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            rule_ids,
+            validators::detector_factories::<FileSystemImpl>()
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(log["runs"][0]["results"], json!([]));
+        Ok(())
+    }
+
+    #[test]
+    fn violations_are_converted_to_results() -> anyhow::Result<()> {
+        let log = report(HashMap::from([
+            (
+                PathBuf::from("src/b.py"),
+                vec![violation("keep-unique", BlockSeverity::Info, None)],
+            ),
+            (
+                PathBuf::from("a.py"),
+                vec![violation(
+                    "keep-sorted",
+                    BlockSeverity::Warning,
+                    Some(json!({"order_by": "asc"})),
+                )],
+            ),
+        ]))?;
+
+        let rules = &log["runs"][0]["tool"]["driver"]["rules"];
+        assert_eq!(
+            log["runs"][0]["results"],
+            json!([
+                {
+                    "ruleId": "keep-sorted",
+                    "ruleIndex": 1,
+                    "level": "warning",
+                    "message": {"text": "keep-sorted violation"},
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": {"uri": "a.py", "uriBaseId": "%SRCROOT%"},
+                            "region": {"startLine": 3, "startColumn": 5, "endLine": 4, "endColumn": 8}
+                        }
+                    }],
+                    "properties": {"data": {"order_by": "asc"}}
+                },
+                {
+                    "ruleId": "keep-unique",
+                    "ruleIndex": 2,
+                    "level": "note",
+                    "message": {"text": "keep-unique violation"},
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": {"uri": "src/b.py", "uriBaseId": "%SRCROOT%"},
+                            "region": {"startLine": 3, "startColumn": 5, "endLine": 4, "endColumn": 8}
+                        }
+                    }]
+                }
+            ])
+        );
+        assert_eq!(rules[1]["id"], "keep-sorted");
+        assert_eq!(rules[2]["id"], "keep-unique");
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_code_is_added_as_rule() -> anyhow::Result<()> {
+        let log = report(HashMap::from([(
+            PathBuf::from("a.py"),
+            vec![violation("custom", BlockSeverity::Error, None)],
+        )]))?;
+
+        let rules = log["runs"][0]["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap();
+        let result = &log["runs"][0]["results"][0];
+        assert_eq!(result["level"], "error");
+        assert_eq!(
+            rules[result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+            "custom"
+        );
+        Ok(())
+    }
+}
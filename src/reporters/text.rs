@@ -0,0 +1,398 @@
+use crate::Position;
+use crate::blocks::{BlockSeverity, FileSystem};
+use crate::validators::Violation;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of source lines shown for a single violation.
+const MAX_SNIPPET_LINES: usize = 5;
+/// Number of columns a tab is expanded to in the source snippets.
+const TAB_WIDTH: usize = 4;
+
+/// Applies ANSI styles to the report when colors are enabled.
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, ansi_code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{ansi_code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn severity(&self, severity: BlockSeverity, text: &str) -> String {
+        let ansi_code = match severity {
+            BlockSeverity::Error => "1;31",
+            BlockSeverity::Warning => "1;33",
+            BlockSeverity::Info => "1;34",
+            BlockSeverity::Hint => "1;36",
+        };
+        self.paint(ansi_code, text)
+    }
+
+    fn gutter(&self, text: &str) -> String {
+        self.paint("1;34", text)
+    }
+
+    fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+}
+
+/// Writes the `violations` in a human-readable form similar to the compiler diagnostics: the
+/// location of every violation followed by the offending source lines (read from `file_system`)
+/// with carets under the violation range.
+pub(super) fn write_report(
+    violations: &HashMap<PathBuf, Vec<Violation>>,
+    file_system: &impl FileSystem,
+    color: bool,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let style = Style { color };
+    let (mut errors, mut warnings, mut notes) = (0, 0, 0);
+    for (file_path, file_violations) in super::sorted_by_path(violations) {
+        // The snippets are best-effort: the location is still reported if the file can't be read.
+        let source = file_system.read_to_string(file_path).ok();
+        let mut file_violations: Vec<&Violation> = file_violations.iter().collect();
+        file_violations.sort_by(|a, b| a.range().start().cmp(b.range().start()));
+        for violation in file_violations {
+            write_violation(writer, &style, file_path, source.as_deref(), violation)?;
+            match violation.severity() {
+                BlockSeverity::Error => errors += 1,
+                BlockSeverity::Warning => warnings += 1,
+                BlockSeverity::Info | BlockSeverity::Hint => notes += 1,
+            }
+        }
+    }
+    let summary: Vec<String> = [(errors, "error"), (warnings, "warning"), (notes, "note")]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, noun)| format!("{count} {noun}{}", if count == 1 { "" } else { "s" }))
+        .collect();
+    if !summary.is_empty() {
+        writeln!(
+            writer,
+            "{}",
+            style.bold(&format!("blockwatch: {}", summary.join(", ")))
+        )?;
+    }
+    Ok(())
+}
+
+fn severity_label(severity: BlockSeverity) -> &'static str {
+    match severity {
+        BlockSeverity::Error => "error",
+        BlockSeverity::Warning => "warning",
+        BlockSeverity::Info => "info",
+        BlockSeverity::Hint => "hint",
+    }
+}
+
+fn write_violation(
+    writer: &mut impl Write,
+    style: &Style,
+    file_path: &Path,
+    source: Option<&str>,
+    violation: &Violation,
+) -> anyhow::Result<()> {
+    let start = violation.range().start();
+    let end = violation.range().end();
+    let severity = violation.severity();
+    writeln!(
+        writer,
+        "{}{}",
+        style.severity(
+            severity,
+            &format!("{}[{}]", severity_label(severity), violation.code())
+        ),
+        style.bold(&format!(": {}", violation.message()))
+    )?;
+    let last_shown_line = end.line.min(start.line + MAX_SNIPPET_LINES - 1);
+    let gutter_width = last_shown_line.to_string().len();
+    let padding = " ".repeat(gutter_width);
+    writeln!(
+        writer,
+        "{padding}{} {}:{}:{}",
+        style.gutter("-->"),
+        file_path.display(),
+        start.line,
+        start.character
+    )?;
+    let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+    if start.line == 0 || start.line > source_lines.len() {
+        writeln!(writer)?;
+        return Ok(());
+    }
+    writeln!(writer, "{padding} {}", style.gutter("|"))?;
+    for line_number in start.line..=last_shown_line.min(source_lines.len()) {
+        let line = source_lines[line_number - 1];
+        let (underline_start, underline_end) = underline_range(line, line_number, start, end);
+        writeln!(
+            writer,
+            "{} {}",
+            style.gutter(&format!("{line_number:>gutter_width$} |")),
+            expand_tabs(line)
+        )?;
+        writeln!(
+            writer,
+            "{padding} {} {}{}",
+            style.gutter("|"),
+            " ".repeat(display_width(&line[..underline_start])),
+            style.severity(
+                severity,
+                &"^".repeat(display_width(&line[underline_start..underline_end]).max(1))
+            )
+        )?;
+    }
+    if last_shown_line < end.line {
+        writeln!(writer, "{}", style.gutter("..."))?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Returns the byte range of the `line` to underline for a violation spanning `start..=end`.
+///
+/// The first line is underlined from the start character, the last line up to the end
+/// character and the lines in between from their first non-whitespace character.
+fn underline_range(
+    line: &str,
+    line_number: usize,
+    start: &Position,
+    end: &Position,
+) -> (usize, usize) {
+    let first = if line_number == start.line {
+        start.character.saturating_sub(1)
+    } else {
+        line.len() - line.trim_start().len()
+    };
+    let last = if line_number == end.line {
+        end.character
+    } else {
+        line.len()
+    };
+    let first = floor_char_boundary(line, first.min(line.len()));
+    let last = floor_char_boundary(line, last.clamp(first, line.len()));
+    (first, last)
+}
+
+fn floor_char_boundary(line: &str, mut index: usize) -> usize {
+    while !line.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::FakeFileSystem;
+    use crate::validators::ViolationRange;
+
+    fn violation(
+        start: (usize, usize),
+        end: (usize, usize),
+        code: &str,
+        severity: BlockSeverity,
+    ) -> Violation {
+        Violation::new(
+            ViolationRange::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            code.to_string(),
+            format!("{code} violation"),
+            severity,
+            None,
+        )
+    }
+
+    fn report(
+        violations: HashMap<PathBuf, Vec<Violation>>,
+        files: &[(&str, &str)],
+        color: bool,
+    ) -> anyhow::Result<String> {
+        let file_system = FakeFileSystem::new(
+            files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.to_string()))
+                .collect(),
+        );
+        let mut output = Vec::new();
+        write_report(&violations, &file_system, color, &mut output)?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn single_line_violation_shows_source_line_with_carets() -> anyhow::Result<()> {
+        let output = report(
+            HashMap::from([(
+                PathBuf::from("a.py"),
+                vec![violation(
+                    (3, 5),
+                    (3, 9),
+                    "keep-sorted",
+                    BlockSeverity::Error,
+                )],
+            )]),
+            &[(
+                "a.py",
+                "# <block keep-sorted>\nx = [b,\n    a = 1\n# </block>\n",
+            )],
+            false,
+        )?;
+
+        assert_eq!(
+            output,
+            "error[keep-sorted]: keep-sorted violation
+ --> a.py:3:5
+  |
+3 |     a = 1
+  |     ^^^^^
+
+blockwatch: 1 error
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn multi_line_violation_underlines_every_line() -> anyhow::Result<()> {
+        let output = report(
+            HashMap::from([(
+                PathBuf::from("a.py"),
+                vec![violation(
+                    (9, 3),
+                    (10, 2),
+                    "affects",
+                    BlockSeverity::Warning,
+                )],
+            )]),
+            &[("a.py", "\n\n\n\n\n\n\n\n# abc\n\tdef\n")],
+            false,
+        )?;
+
+        assert_eq!(
+            output,
+            "warning[affects]: affects violation
+  --> a.py:9:3
+   |
+ 9 | # abc
+   |   ^^^
+10 |     def
+   |     ^
+
+blockwatch: 1 warning
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn long_violation_is_truncated() -> anyhow::Result<()> {
+        let output = report(
+            HashMap::from([(
+                PathBuf::from("a.py"),
+                vec![violation((1, 1), (7, 1), "line-count", BlockSeverity::Info)],
+            )]),
+            &[("a.py", "1\n2\n3\n4\n5\n6\n7\n")],
+            false,
+        )?;
+
+        assert!(output.contains("5 | 5\n  | ^\n...\n"), "{output}");
+        assert!(!output.contains("6 | 6"), "{output}");
+        assert!(output.ends_with("blockwatch: 1 note\n"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_file_shows_location_only() -> anyhow::Result<()> {
+        let output = report(
+            HashMap::from([(
+                PathBuf::from("missing.py"),
+                vec![violation((2, 1), (2, 1), "check-ai", BlockSeverity::Error)],
+            )]),
+            &[],
+            false,
+        )?;
+
+        assert_eq!(
+            output,
+            "error[check-ai]: check-ai violation
+ --> missing.py:2:1
+
+blockwatch: 1 error
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn violations_are_sorted_by_path_and_position_and_counted() -> anyhow::Result<()> {
+        let output = report(
+            HashMap::from([
+                (
+                    PathBuf::from("b.py"),
+                    vec![
+                        violation((2, 1), (2, 1), "keep-unique", BlockSeverity::Warning),
+                        violation((1, 1), (1, 1), "keep-sorted", BlockSeverity::Error),
+                    ],
+                ),
+                (
+                    PathBuf::from("a.py"),
+                    vec![violation((1, 1), (1, 1), "affects", BlockSeverity::Error)],
+                ),
+            ]),
+            &[("a.py", "a\n"), ("b.py", "a\nb\n")],
+            false,
+        )?;
+
+        let affects = output.find("error[affects]").unwrap();
+        let keep_sorted = output.find("error[keep-sorted]").unwrap();
+        let keep_unique = output.find("warning[keep-unique]").unwrap();
+        assert!(
+            affects < keep_sorted && keep_sorted < keep_unique,
+            "{output}"
+        );
+        assert!(
+            output.ends_with("blockwatch: 2 errors, 1 warning\n"),
+            "{output}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn colors_are_applied_by_severity() -> anyhow::Result<()> {
+        let output = report(
+            HashMap::from([(
+                PathBuf::from("a.py"),
+                vec![violation(
+                    (1, 1),
+                    (1, 1),
+                    "keep-sorted",
+                    BlockSeverity::Warning,
+                )],
+            )]),
+            &[("a.py", "a\n")],
+            true,
+        )?;
+
+        assert!(
+            output.starts_with("\x1b[1;33mwarning[keep-sorted]\x1b[0m"),
+            "{output}"
+        );
+        assert!(output.contains("\x1b[1;33m^\x1b[0m"), "{output}");
+        Ok(())
+    }
+}
@@ -1,9 +1,9 @@
-use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::blocks::{Block, BlockWithContext, FileBlocks, FileSystem};
 use crate::validators;
 use crate::validators::{ValidatorType, Violation, ViolationRange};
 use anyhow::Context;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -21,6 +21,36 @@ struct AffectsViolation<'a> {
     affected_block_name: &'a str,
 }
 
+#[derive(Serialize)]
+struct DanglingReferenceViolation<'a> {
+    deleted_block_file_path: &'a Path,
+    deleted_block_name: &'a str,
+}
+
+#[derive(Serialize)]
+struct DeletedBlockViolation<'a> {
+    deleted_block_file_path: &'a Path,
+    deleted_block_name: &'a str,
+    deleted_block_line: usize,
+}
+
+#[derive(Serialize)]
+struct ReferenceViolation<'a> {
+    reference: &'a str,
+    problem: ReferenceProblem,
+}
+
+/// Why an `affects` reference can't be resolved to exactly one other block.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum ReferenceProblem {
+    Malformed,
+    MissingFile,
+    MissingBlock,
+    AmbiguousBlock,
+    SelfReference,
+}
+
 impl validators::ValidatorSync for AffectsValidator {
     fn validate(
         &self,
@@ -41,6 +71,15 @@ impl validators::ValidatorSync for AffectsValidator {
                 }
             }
         }
+        let deleted_block_names: HashSet<(PathBuf, String)> = context
+            .deleted_blocks
+            .iter()
+            .flat_map(|(file_path, blocks)| {
+                blocks
+                    .iter()
+                    .filter_map(|block| Some((file_path.clone(), block.name()?.to_string())))
+            })
+            .collect();
         let mut violations = HashMap::new();
         for (modified_block_file_path, file_blocks) in &context.blocks {
             for block_with_context in &file_blocks.blocks_with_context {
@@ -53,10 +92,12 @@ impl validators::ValidatorSync for AffectsValidator {
                     for (affected_file_path, affected_block_name) in affected_blocks {
                         let affected_file_path =
                             affected_file_path.unwrap_or_else(|| modified_block_file_path.clone());
-                        if !named_modified_blocks.contains_key(&(
-                            affected_file_path.clone(),
-                            affected_block_name.clone(),
-                        )) {
+                        let affected_block =
+                            (affected_file_path.clone(), affected_block_name.clone());
+                        if !named_modified_blocks.contains_key(&affected_block)
+                            // References to the deleted blocks are reported as dangling below.
+                            && !deleted_block_names.contains(&affected_block)
+                        {
                             violations
                                 .entry(modified_block_file_path.clone())
                                 .or_insert_with(Vec::new)
@@ -71,10 +112,92 @@ impl validators::ValidatorSync for AffectsValidator {
                 }
             }
         }
+        if !context.deleted_blocks.is_empty() {
+            validate_deleted_blocks(
+                &context,
+                &named_modified_blocks,
+                &deleted_block_names,
+                &mut violations,
+            )?;
+        }
         Ok(violations)
     }
 }
 
+/// Reports the blocks in the tree which still affect a deleted block (dangling references) and
+/// the blocks affected by a deleted block which were not modified in the same change.
+fn validate_deleted_blocks(
+    context: &validators::ValidationContext,
+    named_modified_blocks: &HashMap<(PathBuf, String), Vec<&BlockWithContext>>,
+    deleted_block_names: &HashSet<(PathBuf, String)>,
+    violations: &mut HashMap<PathBuf, Vec<Violation>>,
+) -> anyhow::Result<()> {
+    let tree = context.tree()?;
+    for (file_path, file_blocks) in tree {
+        for block_with_context in &file_blocks.blocks_with_context {
+            let Some(affects) = block_with_context.block.attributes.get("affects") else {
+                continue;
+            };
+            for (affected_file_path, affected_block_name) in
+                validators::parse_affects_attribute(affects)?
+            {
+                let affected_file_path = affected_file_path.unwrap_or_else(|| file_path.clone());
+                if deleted_block_names
+                    .contains(&(affected_file_path.clone(), affected_block_name.clone()))
+                {
+                    violations.entry(file_path.clone()).or_default().push(
+                        create_dangling_reference_violation(
+                            file_path,
+                            &block_with_context.block,
+                            &affected_file_path,
+                            &affected_block_name,
+                        )?,
+                    );
+                }
+            }
+        }
+    }
+    for (deleted_block_file_path, deleted_blocks) in &context.deleted_blocks {
+        for deleted_block in deleted_blocks {
+            let Some(affects) = deleted_block.attributes.get("affects") else {
+                continue;
+            };
+            for (affected_file_path, affected_block_name) in
+                validators::parse_affects_attribute(affects)?
+            {
+                let affected_file_path =
+                    affected_file_path.unwrap_or_else(|| deleted_block_file_path.clone());
+                if named_modified_blocks
+                    .contains_key(&(affected_file_path.clone(), affected_block_name.clone()))
+                {
+                    continue;
+                }
+                // Blocks which don't exist (anymore) have nothing left to update.
+                let Some(affected_block) = tree.get(&affected_file_path).and_then(|file_blocks| {
+                    file_blocks
+                        .blocks_with_context
+                        .iter()
+                        .find(|block_with_context| {
+                            block_with_context.block.name() == Some(affected_block_name.as_str())
+                        })
+                }) else {
+                    continue;
+                };
+                violations
+                    .entry(affected_file_path.clone())
+                    .or_default()
+                    .push(create_deleted_block_violation(
+                        deleted_block_file_path,
+                        deleted_block,
+                        &affected_file_path,
+                        &affected_block.block,
+                    )?);
+            }
+        }
+    }
+    Ok(())
+}
+
 pub(crate) struct AffectsValidatorDetector();
 
 impl AffectsValidatorDetector {
@@ -97,6 +220,122 @@ impl<Fs: FileSystem> validators::ValidatorDetector<Fs> for AffectsValidatorDetec
             Ok(None)
         }
     }
+
+    fn detect_deleted(
+        &self,
+        deleted_blocks: &HashMap<PathBuf, Vec<Block>>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        // Deleted named blocks may still be referenced and deleted blocks with `affects` may
+        // affect blocks which were not modified.
+        if deleted_blocks
+            .values()
+            .flatten()
+            .any(|block| block.name().is_some() || block.attributes.contains_key("affects"))
+        {
+            Ok(Some(ValidatorType::Sync(Box::new(AffectsValidator::new()))))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Checks the `affects` references of all the blocks in the `tree`, whether they are modified or
+/// not.
+///
+/// Reports the malformed `affects` attributes, the references to files which are neither in the
+/// `tree` nor among the `existing_files`, to block names which are missing or duplicated in the
+/// referenced file and the blocks which affect themselves.
+pub fn check_references(
+    tree: &HashMap<PathBuf, FileBlocks>,
+    existing_files: &HashSet<PathBuf>,
+) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+    let mut name_counts: HashMap<(&Path, &str), usize> = HashMap::new();
+    for (file_path, file_blocks) in tree {
+        for block_with_context in &file_blocks.blocks_with_context {
+            if let Some(name) = block_with_context.block.name() {
+                *name_counts.entry((file_path, name)).or_default() += 1;
+            }
+        }
+    }
+    let mut violations: HashMap<PathBuf, Vec<Violation>> = HashMap::new();
+    for (file_path, file_blocks) in tree {
+        for block_with_context in &file_blocks.blocks_with_context {
+            let block = &block_with_context.block;
+            let Some(affects) = block.attributes.get("affects") else {
+                continue;
+            };
+            let block_display = format!(
+                "Block {}:{} at line {}",
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line
+            );
+            let references = match validators::parse_affects_attribute(affects) {
+                Ok(references) => references,
+                Err(err) => {
+                    violations.entry(file_path.clone()).or_default().push(
+                        create_reference_violation(
+                            block,
+                            affects,
+                            ReferenceProblem::Malformed,
+                            format!("{block_display} has a malformed \"affects\" attribute: {err}"),
+                        )?,
+                    );
+                    continue;
+                }
+            };
+            for (affected_file_path, affected_block_name) in references {
+                let affected_file_path = affected_file_path.unwrap_or_else(|| file_path.clone());
+                let reference = format!("{}:{}", affected_file_path.display(), affected_block_name);
+                let (problem, message) = if affected_file_path == *file_path
+                    && block.name() == Some(affected_block_name.as_str())
+                {
+                    (
+                        ReferenceProblem::SelfReference,
+                        format!("{block_display} affects itself"),
+                    )
+                } else {
+                    match name_counts
+                        .get(&(affected_file_path.as_path(), affected_block_name.as_str()))
+                    {
+                        Some(1) => continue,
+                        Some(count) => (
+                            ReferenceProblem::AmbiguousBlock,
+                            format!(
+                                "{block_display} affects {reference}, but there are {count} blocks named \"{affected_block_name}\" in {}",
+                                affected_file_path.display()
+                            ),
+                        ),
+                        None if tree.contains_key(&affected_file_path)
+                            || existing_files.contains(&affected_file_path) =>
+                        {
+                            (
+                                ReferenceProblem::MissingBlock,
+                                format!(
+                                    "{block_display} affects {reference}, but there is no block named \"{affected_block_name}\" in {}",
+                                    affected_file_path.display()
+                                ),
+                            )
+                        }
+                        None => (
+                            ReferenceProblem::MissingFile,
+                            format!(
+                                "{block_display} affects {reference}, but {} does not exist",
+                                affected_file_path.display()
+                            ),
+                        ),
+                    }
+                };
+                violations
+                    .entry(file_path.clone())
+                    .or_default()
+                    .push(create_reference_violation(
+                        block, &reference, problem, message,
+                    )?);
+            }
+        }
+    }
+    Ok(violations)
 }
 
 fn create_violation(
@@ -130,6 +369,90 @@ fn create_violation(
     ))
 }
 
+fn create_dangling_reference_violation(
+    block_file_path: &Path,
+    block: &Block,
+    deleted_block_file_path: &Path,
+    deleted_block_name: &str,
+) -> anyhow::Result<Violation> {
+    let message = format!(
+        "Block {}:{} at line {} affects {}:{} which is deleted",
+        block_file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+        deleted_block_file_path.display(),
+        deleted_block_name
+    );
+    let details = serde_json::to_value(DanglingReferenceViolation {
+        deleted_block_file_path,
+        deleted_block_name,
+    })
+    .context("failed to serialize DanglingReferenceViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "affects".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+fn create_deleted_block_violation(
+    deleted_block_file_path: &Path,
+    deleted_block: &Block,
+    affected_block_file_path: &Path,
+    affected_block: &Block,
+) -> anyhow::Result<Violation> {
+    let deleted_block_line = deleted_block.start_tag_position_range.start().line;
+    let message = format!(
+        "Block {}:{} at line {} is deleted, but {}:{} is not modified",
+        deleted_block_file_path.display(),
+        deleted_block.name_display(),
+        deleted_block_line,
+        affected_block_file_path.display(),
+        affected_block.name_display()
+    );
+    let details = serde_json::to_value(DeletedBlockViolation {
+        deleted_block_file_path,
+        deleted_block_name: deleted_block.name_display(),
+        deleted_block_line,
+    })
+    .context("failed to serialize DeletedBlockViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            affected_block.start_tag_position_range.start().clone(),
+            affected_block.start_tag_position_range.end().clone(),
+        ),
+        "affects".to_string(),
+        message,
+        deleted_block.severity()?,
+        Some(details),
+    ))
+}
+
+fn create_reference_violation(
+    block: &Block,
+    reference: &str,
+    problem: ReferenceProblem,
+    message: String,
+) -> anyhow::Result<Violation> {
+    let details = serde_json::to_value(ReferenceViolation { reference, problem })
+        .context("failed to serialize ReferenceViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "affects".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
 #[cfg(test)]
 mod validate_tests {
     use super::*;
@@ -461,6 +784,330 @@ pass
     }
 }
 
+#[cfg(test)]
+mod deleted_blocks_tests {
+    use super::*;
+    use crate::test_utils::{messages, validation_context_from_changes};
+    use crate::validators::ValidatorSync;
+
+    #[test]
+    fn unchanged_block_referencing_deleted_block_returns_violation() -> anyhow::Result<()> {
+        let validator = AffectsValidator::new();
+        let context = validation_context_from_changes(
+            &[
+                ("a.py", "# <block affects=\"b.py:foo\">\na\n# </block>\n"),
+                ("b.py", "x\n# <block name=\"foo\">\nfoo\n# </block>\n"),
+            ],
+            &[
+                ("a.py", "# <block affects=\"b.py:foo\">\na\n# </block>\n"),
+                ("b.py", "x\n"),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec!["Block a.py:(unnamed) at line 1 affects b.py:foo which is deleted"]
+        );
+        assert_eq!(violations.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn modified_block_referencing_deleted_block_returns_single_violation() -> anyhow::Result<()> {
+        let validator = AffectsValidator::new();
+        let context = validation_context_from_changes(
+            &[(
+                "a.py",
+                "# <block affects=\":foo\">\na\n# </block>\n# <block name=\"foo\">\n# </block>\n",
+            )],
+            &[("a.py", "# <block affects=\":foo\">\nchanged\n# </block>\n")],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec!["Block a.py:(unnamed) at line 1 affects a.py:foo which is deleted"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_file_with_referenced_block_returns_violation() -> anyhow::Result<()> {
+        let validator = AffectsValidator::new();
+        let context = validation_context_from_changes(
+            &[
+                ("a.py", "# <block affects=\"b.py:foo\">\n# </block>\n"),
+                ("b.py", "# <block name=\"foo\">\n# </block>\n"),
+            ],
+            &[("a.py", "# <block affects=\"b.py:foo\">\n# </block>\n")],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec!["Block a.py:(unnamed) at line 1 affects b.py:foo which is deleted"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_block_and_reference_to_it_return_ok() -> anyhow::Result<()> {
+        let validator = AffectsValidator::new();
+        let context = validation_context_from_changes(
+            &[
+                ("a.py", "# <block affects=\"b.py:foo\">\na\n# </block>\n"),
+                ("b.py", "# <block name=\"foo\">\nfoo\n# </block>\n"),
+            ],
+            &[("a.py", "a\n"), ("b.py", "")],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_block_with_unmodified_affected_block_returns_violation() -> anyhow::Result<()> {
+        let validator = AffectsValidator::new();
+        let context = validation_context_from_changes(
+            &[
+                (
+                    "a.py",
+                    "# <block name=\"a\" affects=\"b.py:foo\">\na\n# </block>\n",
+                ),
+                ("b.py", "# <block name=\"foo\">\nfoo\n# </block>\n"),
+            ],
+            &[
+                ("a.py", ""),
+                ("b.py", "# <block name=\"foo\">\nfoo\n# </block>\n"),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "b.py"),
+            vec!["Block a.py:a at line 1 is deleted, but b.py:foo is not modified"]
+        );
+        let violation = &violations[Path::new("b.py")][0];
+        assert_eq!(violation.range().start(), &crate::Position::new(1, 3));
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({
+                "deleted_block_file_path": "a.py",
+                "deleted_block_name": "a",
+                "deleted_block_line": 1,
+            }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_block_with_modified_affected_block_returns_ok() -> anyhow::Result<()> {
+        let validator = AffectsValidator::new();
+        let context = validation_context_from_changes(
+            &[
+                ("a.py", "# <block affects=\"b.py:foo\">\na\n# </block>\n"),
+                ("b.py", "# <block name=\"foo\">\nfoo\n# </block>\n"),
+            ],
+            &[
+                ("a.py", ""),
+                ("b.py", "# <block name=\"foo\">\nchanged\n# </block>\n"),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn detect_deleted_detects_named_blocks_and_blocks_with_affects_only() -> anyhow::Result<()> {
+        let detector = AffectsValidatorDetector::new();
+        let detect = |attributes: &[(&str, &str)]| {
+            let block = Block::new(
+                attributes
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                crate::Position::new(1, 1)..=crate::Position::new(1, 1),
+                0..0,
+                crate::Position::new(1, 1)..crate::Position::new(1, 1),
+            );
+            validators::ValidatorDetector::<crate::test_utils::FakeFileSystem>::detect_deleted(
+                &detector,
+                &HashMap::from([(PathBuf::from("a.py"), vec![block])]),
+            )
+        };
+
+        assert!(detect(&[("name", "foo")])?.is_some());
+        assert!(detect(&[("affects", ":foo")])?.is_some());
+        assert!(detect(&[("keep-sorted", "asc")])?.is_none());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod check_references_tests {
+    use super::*;
+    use crate::test_utils::{merge_validation_contexts, messages, validation_context};
+
+    fn check(
+        files: &[(&str, &str)],
+        existing_files: &[&str],
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let context = merge_validation_contexts(
+            files
+                .iter()
+                .map(|(file_name, contents)| validation_context(file_name, contents))
+                .collect(),
+        );
+        check_references(
+            &context.blocks,
+            &existing_files.iter().map(PathBuf::from).collect(),
+        )
+    }
+
+    #[test]
+    fn resolved_references_return_ok() -> anyhow::Result<()> {
+        let violations = check(
+            &[
+                (
+                    "a.py",
+                    "# <block name=\"a\" affects=\":b, b.py:foo\">\na\n# </block>\n# <block name=\"b\">\nb\n# </block>\n",
+                ),
+                (
+                    "b.py",
+                    "# <block name=\"foo\" affects=\"a.py:a\">\nfoo\n# </block>\n",
+                ),
+            ],
+            &[],
+        )?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reference_to_missing_file_returns_violation() -> anyhow::Result<()> {
+        let violations = check(
+            &[(
+                "a.py",
+                "# <block affects=\"docs/api.md:foo\">\na\n# </block>\n",
+            )],
+            &["a.py"],
+        )?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:(unnamed) at line 1 affects docs/api.md:foo, but docs/api.md does not exist"
+            ]
+        );
+        assert_eq!(
+            violations[Path::new("a.py")][0].data(),
+            Some(&serde_json::json!({"reference": "docs/api.md:foo", "problem": "missing-file"}))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reference_to_missing_block_returns_violation() -> anyhow::Result<()> {
+        let violations = check(
+            &[
+                (
+                    "a.py",
+                    "# <block affects=\"b.py:missing, c.md:foo\">\na\n# </block>\n",
+                ),
+                ("b.py", "# <block name=\"foo\">\nfoo\n# </block>\n"),
+            ],
+            // Files without blocks are not in the tree.
+            &["a.py", "b.py", "c.md"],
+        )?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:(unnamed) at line 1 affects b.py:missing, but there is no block named \"missing\" in b.py",
+                "Block a.py:(unnamed) at line 1 affects c.md:foo, but there is no block named \"foo\" in c.md",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reference_to_duplicated_name_returns_violation() -> anyhow::Result<()> {
+        let violations = check(
+            &[
+                ("a.py", "# <block affects=\"b.py:foo\">\na\n# </block>\n"),
+                (
+                    "b.py",
+                    "# <block name=\"foo\">\nfoo\n# </block>\n# <block name=\"foo\">\nbar\n# </block>\n",
+                ),
+            ],
+            &[],
+        )?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:(unnamed) at line 1 affects b.py:foo, but there are 2 blocks named \"foo\" in b.py"
+            ]
+        );
+        assert_eq!(
+            violations[Path::new("a.py")][0].data(),
+            Some(&serde_json::json!({"reference": "b.py:foo", "problem": "ambiguous-block"}))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn self_reference_returns_violation() -> anyhow::Result<()> {
+        let violations = check(
+            &[(
+                "a.py",
+                "# <block name=\"foo\" affects=\":foo, a.py:foo\">\na\n# </block>\n",
+            )],
+            &[],
+        )?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:foo at line 1 affects itself",
+                "Block a.py:foo at line 1 affects itself",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_attribute_returns_violation() -> anyhow::Result<()> {
+        let violations = check(
+            &[("a.py", "# <block affects=\"foo\">\na\n# </block>\n")],
+            &[],
+        )?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:(unnamed) at line 1 has a malformed \"affects\" attribute: Invalid \"affects\" attribute value: \"foo\""
+            ]
+        );
+        assert_eq!(
+            violations[Path::new("a.py")][0].data(),
+            Some(&serde_json::json!({"reference": "foo", "problem": "malformed"}))
+        );
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod parse_affects_attribute_tests {
     use crate::validators::parse_affects_attribute;
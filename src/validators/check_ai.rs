@@ -1,26 +1,43 @@
-use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::Position;
+use crate::blocks::{Block, BlockSeverity, BlockWithContext, FileSystem};
+use crate::validators::check_ai::cache::AiCache;
+use crate::validators::check_ai::fixtures::{
+    AiMode, Fixtures, MissingFixture, RecordingClient, ReplayClient,
+};
+use crate::validators::check_ai::limits::{AiFailureMode, AiLimits, RetryingClient};
 use crate::validators::{
-    ValidationContext, ValidatorAsync, ValidatorDetector, ValidatorType, Violation, ViolationRange,
+    AffectedBlock, ValidationContext, ValidatorAsync, ValidatorDetector, ValidatorType, Violation,
+    ViolationRange, resolve_affected_blocks,
 };
 use anyhow::{Context, anyhow};
 use async_openai::Client;
 use async_openai::config::{Config, OPENAI_API_BASE, OpenAIConfig};
 use async_openai::types::chat::{
     ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-    ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
+    ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs, ResponseFormat,
+    ResponseFormatJsonSchema,
 };
 use async_trait::async_trait;
 use secrecy::ExposeSecret;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 
-const DEFAULT_SYSTEM_PROMPT: &str = r"You are a strict validator. You are given a CONDITION and a BLOCK.
-- If the BLOCK satisfies the CONDITION, reply with exactly: OK
-- If the BLOCK violates the CONDITION, reply ONLY with a short, meaningful, and actionable error message describing what must be changed.
-- Do not include quotes, labels, or extra text.";
+mod cache;
+mod fixtures;
+mod limits;
+mod providers;
+
+const DEFAULT_SYSTEM_PROMPT: &str = r#"You are a strict validator. You are given a CONDITION and a BLOCK whose lines are prefixed with their numbers, starting at 1.
+Reply ONLY with a JSON object: {"findings": [{"line": <number or null>, "message": "<text>", "suggestion": "<text>" or null, "affected_block": "<name>" or null}]}
+- If the BLOCK satisfies the CONDITION, "findings" is empty.
+- Otherwise add a finding for every problem: "line" is the number of the BLOCK line with the problem (null if the problem concerns the whole BLOCK), "message" is a short, meaningful, and actionable error message describing what must be changed, "suggestion" is the text replacing the line (without its number) to fix the problem (null if there is none).
+- The BLOCK may be followed by AFFECTED BLOCKs, named "<file>:<name>", which must stay consistent with the BLOCK. For a problem in an AFFECTED BLOCK (e.g. it no longer describes the BLOCK accurately), set "affected_block" to its name and "line" to the number of its line; otherwise "affected_block" is null.
+- Do not include any other text."#;
 
 // <block check-lua="scripts/check_latest_gpt_nano_model.lua" check-lua-pattern='str = "(?P<value>[^"]+)"'>
 const DEFAULT_MODEL_NAME: &str = "gpt-5-nano";
@@ -30,10 +47,20 @@ const DEFAULT_MODEL_NAME: &str = "gpt-5-nano";
 const API_KEY_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_API_KEY";
 const API_URL_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_API_URL";
 const API_MODEL_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_MODEL";
+const PROVIDER_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_PROVIDER";
+const RESPONSE_PATH_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_RESPONSE_PATH";
+const AI_MODE_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_MODE";
+const AI_CONCURRENCY_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_CONCURRENCY";
+const AI_MAX_RETRIES_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_MAX_RETRIES";
+const AI_TIMEOUT_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_TIMEOUT";
+const AI_ON_ERROR_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_ON_ERROR";
 // </block>
 
 pub(crate) struct CheckAiValidator<C: AiClient> {
     client: Arc<C>,
+    mode: AiMode,
+    limits: AiLimits,
+    failure_mode: AiFailureMode,
 }
 
 #[async_trait]
@@ -44,6 +71,10 @@ impl<C: AiClient + 'static> ValidatorAsync for CheckAiValidator<C> {
     ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
         let mut violations = HashMap::new();
         let mut tasks = JoinSet::new();
+        let client = self.client_for_mode(&context)?;
+        // Bounds the blocks in flight, not only their requests: the blocks waiting for a request
+        // would otherwise hold their affected blocks and cache lookups all at once.
+        let blocks = Arc::new(Semaphore::new(self.limits.concurrency));
         for (file_path, file_blocks) in &context.blocks {
             for (block_idx, block_with_context) in
                 file_blocks.blocks_with_context.iter().enumerate()
@@ -65,36 +96,177 @@ impl<C: AiClient + 'static> ValidatorAsync for CheckAiValidator<C> {
                     continue;
                 }
 
-                let client = Arc::clone(&self.client);
+                let client = Arc::clone(&client);
                 let context = Arc::clone(&context);
                 let file_path = file_path.clone();
+                let failure_mode = self.failure_mode;
+                let permit = Arc::clone(&blocks).acquire_owned().await?;
                 tasks.spawn(async move {
+                    let _permit = permit;
                     let file_blocks = &context.blocks[&file_path];
                     let block_with_context = &file_blocks.blocks_with_context[block_idx];
                     let condition = &block_with_context.block.attributes["check-ai"];
-                    let content = block_content(block_with_context, &file_blocks.file_content)?;
+                    let (content, content_offset) =
+                        block_content(block_with_context, &file_blocks.file_content)?;
+                    let affected_blocks =
+                        resolve_affected_blocks(&context, &file_path, &block_with_context.block)?;
 
-                    let result = client.check_block(condition, content).await;
-                    Self::process_ai_response(file_path, block_with_context, result)
+                    let cache = context.ai_cache_dir().map(AiCache::new);
+                    let result = check_block_cached(
+                        client.as_ref(),
+                        cache.as_ref(),
+                        condition,
+                        content,
+                        &affected_blocks,
+                        context.verbose(),
+                    )
+                    .await;
+                    if let Ok((_, true)) = result
+                        && context.verbose()
+                    {
+                        eprintln!(
+                            "check-ai cache hit for {}:{} at line {}",
+                            file_path.display(),
+                            block_with_context.block.name_display(),
+                            block_with_context
+                                .block
+                                .start_tag_position_range
+                                .start()
+                                .line
+                        );
+                    }
+                    let findings = match result {
+                        Ok((findings, _)) => findings,
+                        Err(error)
+                            if failure_mode == AiFailureMode::Warn
+                                && !error.chain().any(|cause| cause.is::<MissingFixture>()) =>
+                        {
+                            let violation = create_failure_violation(
+                                &file_path,
+                                &block_with_context.block,
+                                &error,
+                            )?;
+                            return anyhow::Ok((block_idx, file_path, vec![violation]));
+                        }
+                        Err(error) => {
+                            return Err(error.context(format!(
+                                "check-ai API error in {}:{} at line {}",
+                                file_path.display(),
+                                block_with_context.block.name_display(),
+                                block_with_context
+                                    .block
+                                    .start_tag_position_range
+                                    .start()
+                                    .line
+                            )));
+                        }
+                    };
+                    let violations = findings
+                        .iter()
+                        .map(|finding| {
+                            let affected_block = finding.affected_block.as_ref().and_then(|name| {
+                                affected_blocks
+                                    .iter()
+                                    .find(|affected_block| &affected_block.reference() == name)
+                            });
+                            // The lines of the affected blocks are not in the validated content,
+                            // so their findings (even of a block the model made up) are reported
+                            // on the start tag.
+                            let range = if finding.affected_block.is_some() {
+                                start_tag_range(&block_with_context.block)
+                            } else {
+                                finding_range(
+                                    &block_with_context.block,
+                                    &file_blocks.file_content,
+                                    content,
+                                    content_offset,
+                                    finding,
+                                )
+                            };
+                            create_violation(
+                                &file_path,
+                                &block_with_context.block,
+                                finding,
+                                affected_block,
+                                range,
+                            )
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    anyhow::Ok((block_idx, file_path, violations))
                 });
             }
         }
+        let mut results = Vec::new();
         while let Some(task_result) = tasks.join_next().await {
-            match task_result.context("check-ai task failed")? {
-                Ok(None) => continue,
-                Ok(Some((file_path, violation))) => {
-                    violations
-                        .entry(file_path)
-                        .or_insert_with(Vec::new)
-                        .push(violation);
+            results.push(task_result.context("check-ai task failed")??);
+        }
+        // Report the findings in the order of the blocks rather than of the responses.
+        results.sort_by_key(|(block_idx, _, _)| *block_idx);
+        for (_, file_path, block_violations) in results {
+            if !block_violations.is_empty() {
+                violations
+                    .entry(file_path)
+                    .or_insert_with(Vec::new)
+                    .extend(block_violations);
+            }
+        }
+        // The cache only grows until the next run if pruning fails, so it doesn't fail this one.
+        if let Some(cache_dir) = context.ai_cache_dir() {
+            let pruned = AiCache::new(cache_dir).prune(cache::MAX_AGE);
+            if context.verbose() {
+                match pruned {
+                    Ok(0) => {}
+                    Ok(pruned) => eprintln!("check-ai cache: pruned {pruned} unused entries"),
+                    Err(e) => eprintln!("check-ai cache: failed to prune: {e:#}"),
                 }
-                Err(e) => return Err(e),
             }
         }
         Ok(violations)
     }
 }
 
+/// Checks the block with `client`, reusing the findings cached in `cache` (if any) for the same
+/// model, system prompt, condition and contents. Returns the findings and whether they were cached.
+///
+/// Only successful responses are cached. Failing to cache one only costs another request next
+/// time, so it is reported in the `verbose` mode only.
+async fn check_block_cached(
+    client: &(impl AiClient + ?Sized),
+    cache: Option<&AiCache>,
+    condition: &str,
+    block_content: &str,
+    affected_blocks: &[AffectedBlock],
+    verbose: bool,
+) -> anyhow::Result<(Vec<AiFinding>, bool)> {
+    let Some((cache, model)) = cache.zip(client.cache_key()) else {
+        return Ok((
+            client
+                .check_block(condition, block_content, affected_blocks)
+                .await?,
+            false,
+        ));
+    };
+    let key = AiCache::key(
+        &model,
+        DEFAULT_SYSTEM_PROMPT,
+        condition,
+        block_content,
+        affected_blocks,
+    );
+    if let Some(findings) = cache.get(&key) {
+        return Ok((findings, true));
+    }
+    let findings = client
+        .check_block(condition, block_content, affected_blocks)
+        .await?;
+    if let Err(e) = cache.put(&key, &findings)
+        && verbose
+    {
+        eprintln!("check-ai cache: failed to store a response: {e:#}");
+    }
+    Ok((findings, false))
+}
+
 pub(crate) struct CheckAiValidatorDetector();
 
 impl CheckAiValidatorDetector {
@@ -111,7 +283,10 @@ impl<Fs: FileSystem> ValidatorDetector<Fs> for CheckAiValidatorDetector {
     ) -> anyhow::Result<Option<ValidatorType>> {
         if block_with_context.block.attributes.contains_key("check-ai") {
             Ok(Some(ValidatorType::Async(Box::new(
-                CheckAiValidator::with_client(OpenAiClient::new_from_env()),
+                CheckAiValidator::with_client(providers::client_from_env()?)
+                    .with_mode(AiMode::from_env()?)
+                    .with_limits(AiLimits::from_env())
+                    .with_failure_mode(AiFailureMode::from_env()?),
             ))))
         } else {
             Ok(None)
@@ -119,33 +294,92 @@ impl<Fs: FileSystem> ValidatorDetector<Fs> for CheckAiValidatorDetector {
     }
 }
 
+/// Returns the part of the block's content sent to the AI and its byte offset in the file: the
+/// match of `check-ai-pattern` (its named group `value` if present) or the trimmed content.
 fn block_content<'c>(
     block_with_context: &BlockWithContext,
     file_content: &'c str,
-) -> anyhow::Result<&'c str> {
-    let content = if let Some(pattern) = block_with_context.block.attributes.get("check-ai-pattern")
-    {
+) -> anyhow::Result<(&'c str, usize)> {
+    let block = &block_with_context.block;
+    let content = block.content(file_content);
+    let range = if let Some(pattern) = block.attributes.get("check-ai-pattern") {
         let re = regex::Regex::new(pattern).context("check-ai-pattern is not a valid regex")?;
-        if let Some(c) = re.captures(block_with_context.block.content(file_content)) {
+        if let Some(c) = re.captures(content) {
             // If named group "value" exists use it, otherwise use the whole match
             if let Some(m) = c.name("value") {
-                m.as_str()
+                m.range()
             } else {
-                c.get(0).map_or("", |m| m.as_str())
+                c.get(0).map_or(0..0, |m| m.range())
             }
         } else {
-            ""
+            0..0
         }
     } else {
-        block_with_context.block.content(file_content).trim()
+        let start = content.len() - content.trim_start().len();
+        start..start + content.trim().len()
     };
-    Ok(content)
+    Ok((
+        &content[range.clone()],
+        block.content_bytes_range.start + range.start,
+    ))
+}
+
+/// Returns the range of the `finding` in the file: its line of the `content` starting at the byte
+/// `content_offset` of the file, without the surrounding whitespace.
+///
+/// The findings without a line or with a line out of the content are reported on the start tag.
+fn finding_range(
+    block: &Block,
+    file_content: &str,
+    content: &str,
+    content_offset: usize,
+    finding: &AiFinding,
+) -> ViolationRange {
+    let Some(line) = finding
+        .line
+        .filter(|line| (1..=content.lines().count()).contains(line))
+    else {
+        return start_tag_range(block);
+    };
+    let line_offset = content_offset
+        + content
+            .split_inclusive('\n')
+            .take(line - 1)
+            .map(str::len)
+            .sum::<usize>();
+    let line_text = content.lines().nth(line - 1).unwrap_or_default();
+    let leading_whitespace = line_text.len() - line_text.trim_start().len();
+    let start = line_offset + leading_whitespace;
+    let end = match line_text.trim().char_indices().last() {
+        Some((last_char_offset, _)) => start + last_char_offset,
+        None => start,
+    };
+    ViolationRange::new(
+        position_at(file_content, start),
+        position_at(file_content, end),
+    )
+}
+
+fn start_tag_range(block: &Block) -> ViolationRange {
+    ViolationRange::new(
+        block.start_tag_position_range.start().clone(),
+        block.start_tag_position_range.end().clone(),
+    )
+}
+
+/// Returns the position of the byte `offset` in `source`.
+fn position_at(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(before.matches('\n').count() + 1, offset - line_start + 1)
 }
 
 fn create_violation(
     file_path: &Path,
     block: &Block,
-    ai_message: &str,
+    finding: &AiFinding,
+    affected_block: Option<&AffectedBlock>,
+    range: ViolationRange,
 ) -> anyhow::Result<Violation> {
     let details = serde_json::to_value(CheckAiViolation {
         condition: block
@@ -153,20 +387,38 @@ fn create_violation(
             .get("check-ai")
             .expect("check-ai attribute must be present")
             .trim(),
-        ai_message: Some(ai_message),
+        ai_message: Some(&finding.message),
+        suggestion: finding.suggestion.as_deref(),
+        affected_block: finding.affected_block.as_deref(),
     })
     .context("failed to serialize CheckAiDetails")?;
-    let error_message = format!(
-        "Block {}:{} defined at line {} failed AI check: {ai_message}",
-        file_path.display(),
-        block.name_display(),
-        block.start_tag_position_range.start().line,
-    );
-    Ok(Violation::new(
-        ViolationRange::new(
-            block.start_tag_position_range.start().clone(),
-            block.start_tag_position_range.end().clone(),
+    let error_message = match (affected_block, &finding.affected_block) {
+        (Some(affected_block), _) => format!(
+            "Block {}:{} defined at line {} failed AI check of the affected block {}: {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line,
+            affected_block.reference(),
+            finding.message,
         ),
+        (None, Some(name)) => format!(
+            "Block {}:{} defined at line {} failed AI check of an unknown affected block {}: {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line,
+            name,
+            finding.message,
+        ),
+        (None, None) => format!(
+            "Block {}:{} defined at line {} failed AI check: {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line,
+            finding.message,
+        ),
+    };
+    Ok(Violation::new(
+        range,
         "check-ai".to_string(),
         error_message,
         block.severity()?,
@@ -178,68 +430,283 @@ impl<C: AiClient> CheckAiValidator<C> {
     pub(super) fn with_client(client: C) -> Self {
         Self {
             client: Arc::new(client),
+            mode: AiMode::Live,
+            limits: AiLimits::default(),
+            failure_mode: AiFailureMode::Fail,
         }
     }
 
-    fn process_ai_response(
-        file_path: PathBuf,
-        block_with_context: &BlockWithContext,
-        result: anyhow::Result<Option<String>>,
-    ) -> anyhow::Result<Option<(PathBuf, Violation)>> {
-        match result.context(format!(
-            "check-ai API error in {}:{} at line {}",
-            file_path.display(),
-            block_with_context.block.name_display(),
-            block_with_context
-                .block
-                .start_tag_position_range
-                .start()
-                .line
-        ))? {
-            None => Ok(None),
-            Some(msg) => {
-                let violation = create_violation(&file_path, &block_with_context.block, &msg)?;
-                Ok(Some((file_path, violation)))
-            }
-        }
+    /// Sets how the responses are obtained: from the client, the fixtures or both.
+    fn with_mode(mut self, mode: AiMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the limits of the requests to the client.
+    fn with_limits(mut self, limits: AiLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether the blocks which can't be checked fail the run or are reported as warnings.
+    fn with_failure_mode(mut self, failure_mode: AiFailureMode) -> Self {
+        self.failure_mode = failure_mode;
+        self
+    }
+
+    /// Returns the client answering the checks in the mode of the validator.
+    fn client_for_mode(&self, context: &ValidationContext) -> anyhow::Result<Arc<dyn AiClient>>
+    where
+        C: 'static,
+    {
+        let fixtures = || {
+            let fixtures_path = context.ai_fixtures_path().ok_or_else(|| {
+                anyhow!(
+                    "the record and replay modes of {AI_MODE_ENV_VAR_NAME} are not supported by this command"
+                )
+            })?;
+            Fixtures::load(fixtures_path)
+        };
+        let retrying = || {
+            Arc::new(RetryingClient::new(
+                Arc::clone(&self.client),
+                self.limits,
+                context.verbose(),
+            ))
+        };
+        Ok(match self.mode {
+            AiMode::Live => retrying(),
+            AiMode::Record => Arc::new(RecordingClient::new(retrying(), fixtures()?)),
+            AiMode::Replay => Arc::new(ReplayClient::new(fixtures()?)),
+        })
     }
 }
 
+/// Returns the warning reported on `block` when it can't be checked because of the `error`.
+fn create_failure_violation(
+    file_path: &Path,
+    block: &Block,
+    error: &anyhow::Error,
+) -> anyhow::Result<Violation> {
+    let details = serde_json::to_value(CheckAiViolation {
+        condition: block
+            .attributes
+            .get("check-ai")
+            .expect("check-ai attribute must be present")
+            .trim(),
+        ai_message: None,
+        suggestion: None,
+        affected_block: None,
+    })
+    .context("failed to serialize CheckAiDetails")?;
+    Ok(Violation::new(
+        start_tag_range(block),
+        "check-ai".to_string(),
+        format!(
+            "Block {}:{} defined at line {} could not be checked by AI: {error:#}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line,
+        ),
+        BlockSeverity::Warning,
+        Some(details),
+    ))
+}
+
 #[derive(Serialize)]
 struct CheckAiViolation<'a> {
     condition: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
     ai_message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    affected_block: Option<&'a str>,
+}
+
+/// A problem found by the AI in a block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct AiFinding {
+    /// Line of the problem within the checked content (or the affected block), starting at 1.
+    /// `None` if the problem concerns the whole content.
+    #[serde(default)]
+    pub(crate) line: Option<usize>,
+    pub(crate) message: String,
+    /// Text replacing the line to fix the problem.
+    #[serde(default)]
+    pub(crate) suggestion: Option<String>,
+    /// Reference (`file:name`) of the affected block with the problem, if it is not in the
+    /// checked content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) affected_block: Option<String>,
+}
+
+impl AiFinding {
+    /// Returns a finding concerning the whole content.
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            message: message.into(),
+            suggestion: None,
+            affected_block: None,
+        }
+    }
+}
+
+/// The structured reply asked for by [`DEFAULT_SYSTEM_PROMPT`].
+#[derive(Deserialize)]
+struct AiReply {
+    findings: Vec<AiFinding>,
+}
+
+/// Returns the JSON schema of [`AiReply`], for the providers supporting structured outputs.
+fn reply_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "findings": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "line": {"type": ["integer", "null"]},
+                        "message": {"type": "string"},
+                        "suggestion": {"type": ["string", "null"]},
+                        "affected_block": {"type": ["string", "null"]}
+                    },
+                    "required": ["line", "message", "suggestion", "affected_block"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["findings"],
+        "additionalProperties": false
+    })
 }
 
 #[async_trait]
 pub(crate) trait AiClient: Send + Sync {
-    /// Returns Ok(None) if the block satisfies the condition, Ok(Some(error_message)) otherwise.
+    /// Returns the problems found in the block and the blocks it affects: none if they satisfy
+    /// the condition.
     async fn check_block(
         &self,
         condition: &str,
         block_content: &str,
-    ) -> anyhow::Result<Option<String>>;
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>>;
+
+    /// Identifies the model answering the checks, to cache its responses. `None` disables the
+    /// cache.
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+}
+
+#[async_trait]
+impl<C: AiClient + ?Sized> AiClient for Box<C> {
+    async fn check_block(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        (**self)
+            .check_block(condition, block_content, affected_blocks)
+            .await
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        (**self).cache_key()
+    }
+}
+
+/// Returns the user message asking to check `block_content` and its `affected_blocks` against
+/// `condition`.
+fn user_prompt(condition: &str, block_content: &str, affected_blocks: &[AffectedBlock]) -> String {
+    let mut prompt = format!(
+        "CONDITION:\n{condition}\n\nBLOCK (formatting preserved, lines prefixed with their numbers):\n{}",
+        numbered_lines(block_content)
+    );
+    for affected_block in affected_blocks {
+        prompt.push_str(&format!(
+            "\n\nAFFECTED BLOCK {} (formatting preserved, lines prefixed with their numbers):\n{}",
+            affected_block.reference(),
+            numbered_lines(&affected_block.content)
+        ));
+    }
+    prompt
+}
+
+fn numbered_lines(content: &str) -> String {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| format!("{}| {line}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Converts the AI reply to the result of [`AiClient::check_block`].
+///
+/// A reply which is not the structured JSON (e.g. from a provider ignoring the instructions) is
+/// either `OK` or a single finding concerning the whole content.
+fn parse_findings(reply: &str) -> Vec<AiFinding> {
+    let reply = reply.trim();
+    if reply.eq_ignore_ascii_case("OK") || reply.eq_ignore_ascii_case("OK.") {
+        return Vec::new();
+    }
+    // Models often wrap the JSON in a Markdown code block.
+    let json = reply
+        .strip_prefix("```json")
+        .or_else(|| reply.strip_prefix("```"))
+        .and_then(|json| json.strip_suffix("```"))
+        .unwrap_or(reply);
+    match serde_json::from_str::<AiReply>(json) {
+        Ok(ai_reply) => ai_reply.findings,
+        Err(_) => vec![AiFinding::new(reply)],
+    }
 }
 
 /// Default OpenAI-based implementation. Uses async-openai crate.
+///
+/// Also used for the local OpenAI compatible endpoints (Ollama, llama.cpp) which don't need an
+/// API key.
 pub(super) struct OpenAiClient {
     client: Client<OpenAIConfig>,
     model: String,
+    api_key_required: bool,
 }
 
 impl OpenAiClient {
     /// Creates a new OpenAI client from environment variables (BLOCKWATCH_AI_*),
     /// falling back to `async-openai` crate defaults when not provided.
     pub(crate) fn new_from_env() -> Self {
-        let model = std::env::var(API_MODEL_ENV_VAR_NAME).unwrap_or(DEFAULT_MODEL_NAME.into());
-        let api_base = std::env::var(API_URL_ENV_VAR_NAME).unwrap_or(OPENAI_API_BASE.into());
+        Self::from_env(OPENAI_API_BASE, DEFAULT_MODEL_NAME, true)
+    }
+
+    /// Creates a client for a local OpenAI compatible endpoint from environment variables
+    /// (BLOCKWATCH_AI_*), falling back to the defaults of Ollama.
+    pub(crate) fn new_local_from_env() -> Self {
+        Self::from_env(
+            providers::OLLAMA_API_BASE,
+            providers::DEFAULT_OLLAMA_MODEL_NAME,
+            false,
+        )
+    }
+
+    fn from_env(default_api_base: &str, default_model: &str, api_key_required: bool) -> Self {
+        let model = std::env::var(API_MODEL_ENV_VAR_NAME).unwrap_or(default_model.into());
+        let api_base = std::env::var(API_URL_ENV_VAR_NAME).unwrap_or(default_api_base.into());
         let api_key = std::env::var(API_KEY_ENV_VAR_NAME).unwrap_or("".into());
         let config = OpenAIConfig::new()
             .with_api_base(api_base)
             .with_api_key(api_key);
         let client = Client::with_config(config);
-        Self { model, client }
+        Self {
+            model,
+            client,
+            api_key_required,
+        }
     }
 }
 
@@ -249,16 +716,15 @@ impl AiClient for OpenAiClient {
         &self,
         condition: &str,
         block_content: &str,
-    ) -> anyhow::Result<Option<String>> {
-        if self.client.config().api_key().expose_secret().is_empty() {
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        if self.api_key_required && self.client.config().api_key().expose_secret().is_empty() {
             return Err(anyhow::anyhow!(
                 "API key is empty. Is {API_KEY_ENV_VAR_NAME} env variable set?"
             ));
         }
-        let user =
-            format!("CONDITION:\n{condition}\n\nBLOCK (formatting preserved):\n{block_content}");
         let user_msg = ChatCompletionRequestUserMessageArgs::default()
-            .content(user)
+            .content(user_prompt(condition, block_content, affected_blocks))
             .build()
             .context("failed to build user message")?;
 
@@ -273,6 +739,14 @@ impl AiClient for OpenAiClient {
                 ChatCompletionRequestMessage::System(system_msg),
                 ChatCompletionRequestMessage::User(user_msg),
             ])
+            .response_format(ResponseFormat::JsonSchema {
+                json_schema: ResponseFormatJsonSchema {
+                    description: None,
+                    name: "check_ai_findings".to_string(),
+                    schema: reply_schema(),
+                    strict: Some(true),
+                },
+            })
             .build()
             .context("failed to build OpenAI request")?;
 
@@ -286,14 +760,18 @@ impl AiClient for OpenAiClient {
         if let Some(chat_choice) = resp.choices.into_iter().next()
             && let Some(message) = chat_choice.message.content
         {
-            return if message.eq_ignore_ascii_case("OK") || message.eq_ignore_ascii_case("OK.") {
-                Ok(None)
-            } else {
-                Ok(Some(message))
-            };
+            return Ok(parse_findings(&message));
         }
         Err(anyhow!("empty response from AI"))
     }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!(
+            "openai {} {}",
+            self.client.config().api_base(),
+            self.model
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -308,13 +786,15 @@ mod tests {
         None,
         // Violation.
         Some(String),
+        // Violations found at lines.
+        Findings(Vec<AiFinding>),
         // Error message for a failed request.
         Err(String),
     }
 
     #[derive(Default)]
     struct FakeClient {
-        // A map from the block's condition to a response: None = OK, Some(msg) = violation.
+        // A map from the block's condition and content to a response.
         responses: HashMap<(String, String), FakeAiResponse>,
     }
 
@@ -330,7 +810,8 @@ mod tests {
             &self,
             condition: &str,
             block_content: &str,
-        ) -> anyhow::Result<Option<String>> {
+            _affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
             let response = self
                 .responses
                 .get(&(condition.to_string(), block_content.to_string()))
@@ -339,13 +820,40 @@ mod tests {
                     panic!("Unexpected AiClient call: {condition:?}, {block_content:?}")
                 });
             match response {
-                FakeAiResponse::None => Ok(None),
-                FakeAiResponse::Some(validation_error) => Ok(Some(validation_error)),
+                FakeAiResponse::None => Ok(Vec::new()),
+                FakeAiResponse::Some(validation_error) => {
+                    Ok(vec![AiFinding::new(validation_error)])
+                }
+                FakeAiResponse::Findings(findings) => Ok(findings),
                 FakeAiResponse::Err(error_message) => Err(anyhow!(error_message)),
             }
         }
     }
 
+    /// Reports the affected blocks which don't mention the validated block's content.
+    struct DriftClient;
+
+    #[async_trait]
+    impl AiClient for DriftClient {
+        async fn check_block(
+            &self,
+            _condition: &str,
+            block_content: &str,
+            affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
+            Ok(affected_blocks
+                .iter()
+                .filter(|affected_block| !affected_block.content.contains(block_content))
+                .map(|affected_block| AiFinding {
+                    line: Some(1),
+                    message: format!("{block_content} is not described."),
+                    suggestion: Some(format!("// {block_content}")),
+                    affected_block: Some(affected_block.reference()),
+                })
+                .collect())
+        }
+    }
+
     #[tokio::test]
     async fn when_ai_returns_ok_returns_no_violations() -> anyhow::Result<()> {
         let validator = CheckAiValidator::with_client(FakeClient::new(HashMap::from([(
@@ -428,6 +936,227 @@ I like apples
         Ok(())
     }
 
+    #[tokio::test]
+    async fn findings_are_reported_at_their_lines() -> anyhow::Result<()> {
+        let validator = CheckAiValidator::with_client(FakeClient::new(HashMap::from([(
+            (
+                "fruits must be yellow".into(),
+                "fruits = [\n    \"banana\",\n    \"apple\",\n    \"kiwi\",\n]".into(),
+            ),
+            FakeAiResponse::Findings(vec![
+                AiFinding {
+                    line: Some(4),
+                    message: "Kiwi is green.".into(),
+                    suggestion: None,
+                    affected_block: None,
+                },
+                AiFinding {
+                    line: Some(3),
+                    message: "Apples are red.".into(),
+                    suggestion: Some("    \"lemon\",".into()),
+                    affected_block: None,
+                },
+                AiFinding {
+                    line: Some(42),
+                    message: "Out of the block.".into(),
+                    suggestion: None,
+                    affected_block: None,
+                },
+            ]),
+        )])));
+        let context = validation_context(
+            "example.py",
+            r#"# <block check-ai="fruits must be yellow">
+fruits = [
+    "banana",
+    "apple",
+    "kiwi",
+]
+# </block>"#,
+        );
+        let violations = validator.validate(context).await?;
+        let violations = &violations[&PathBuf::from("example.py")];
+        let ranges: Vec<_> = violations
+            .iter()
+            .map(|violation| {
+                let range = serde_json::to_value(&violation.range).unwrap();
+                (
+                    range["start"]["line"].as_u64().unwrap(),
+                    range["start"]["character"].as_u64().unwrap(),
+                    range["end"]["line"].as_u64().unwrap(),
+                    range["end"]["character"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(5, 5, 5, 11), (4, 5, 4, 12), (1, 3, 1, 42)]);
+        assert_eq!(
+            violations[0].message,
+            "Block example.py:(unnamed) defined at line 1 failed AI check: Kiwi is green."
+        );
+        assert_eq!(
+            violations[1].data,
+            Some(json!({
+                "condition": "fruits must be yellow",
+                "ai_message": "Apples are red.",
+                "suggestion": "    \"lemon\","
+            }))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finding_lines_are_relative_to_the_pattern_match() -> anyhow::Result<()> {
+        let validator = CheckAiValidator::with_client(FakeClient::new(HashMap::from([(
+            ("must be sorted".into(), "b\na\n".into()),
+            FakeAiResponse::Findings(vec![AiFinding {
+                line: Some(1),
+                message: "Move b after a.".into(),
+                suggestion: None,
+                affected_block: None,
+            }]),
+        )])));
+        let context = validation_context(
+            "example.py",
+            r#"# <block check-ai="must be sorted" check-ai-pattern="items: (?P<value>(?s:.*))">
+items: b
+a
+# </block>"#,
+        );
+        let violations = validator.validate(context).await?;
+        let violation = &violations[&PathBuf::from("example.py")][0];
+        assert_eq!(
+            serde_json::to_value(&violation.range)?,
+            json!({"start": {"line": 2, "character": 8}, "end": {"line": 2, "character": 8}})
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn findings_name_the_affected_blocks() -> anyhow::Result<()> {
+        let validator = CheckAiValidator::with_client(DriftClient);
+        let context = validation_context(
+            "example.rs",
+            r#"// <block check-ai="the docs must describe the value" affects=":current, :stale, :missing">
+Banana
+// </block>
+// <block name="current">
+// Banana
+// </block>
+// <block name="stale">
+// Apple
+// </block>"#,
+        );
+        let violations = validator.validate(context).await?;
+        let violations = &violations[&PathBuf::from("example.rs")];
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].message,
+            "Block example.rs:(unnamed) defined at line 1 failed AI check of the affected block example.rs:stale: Banana is not described."
+        );
+        assert_eq!(
+            serde_json::to_value(&violations[0].range)?,
+            json!({"start": {"line": 1, "character": 4}, "end": {"line": 1, "character": 91}})
+        );
+        assert_eq!(
+            violations[0].data,
+            Some(json!({
+                "condition": "the docs must describe the value",
+                "ai_message": "Banana is not described.",
+                "suggestion": "// Banana",
+                "affected_block": "example.rs:stale"
+            }))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finding_of_unknown_affected_block_is_reported_at_start_tag() -> anyhow::Result<()> {
+        let validator = CheckAiValidator::with_client(FakeClient::new(HashMap::from([(
+            ("must be yellow".into(), "banana\nlemon".into()),
+            FakeAiResponse::Findings(vec![AiFinding {
+                line: Some(2),
+                message: "Kiwi is green.".into(),
+                suggestion: None,
+                affected_block: Some("other.rs:fruits".into()),
+            }]),
+        )])));
+        let context = validation_context(
+            "example.py",
+            "# <block check-ai=\"must be yellow\">\nbanana\nlemon\n# </block>",
+        );
+        let violations = validator.validate(context).await?;
+        let violation = &violations[&PathBuf::from("example.py")][0];
+        assert_eq!(
+            violation.message,
+            "Block example.py:(unnamed) defined at line 1 failed AI check of an unknown affected block other.rs:fruits: Kiwi is green."
+        );
+        assert_eq!(
+            serde_json::to_value(&violation.range)?,
+            json!({"start": {"line": 1, "character": 3}, "end": {"line": 1, "character": 35}})
+        );
+        assert_eq!(
+            violation.data,
+            Some(json!({
+                "condition": "must be yellow",
+                "ai_message": "Kiwi is green.",
+                "affected_block": "other.rs:fruits"
+            }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn user_prompt_includes_affected_blocks() {
+        let affected_blocks = [AffectedBlock {
+            file: "README.md".into(),
+            name: "fruits".into(),
+            content: "- Apple\n- Banana".into(),
+        }];
+        assert_eq!(
+            user_prompt("must match", "enum Fruit {}", &affected_blocks),
+            "CONDITION:\nmust match\n\nBLOCK (formatting preserved, lines prefixed with their numbers):\n1| enum Fruit {}\n\nAFFECTED BLOCK README.md:fruits (formatting preserved, lines prefixed with their numbers):\n1| - Apple\n2| - Banana"
+        );
+    }
+
+    #[test]
+    fn parse_findings_reads_structured_replies() {
+        assert_eq!(parse_findings("OK"), Vec::new());
+        assert_eq!(parse_findings(r#"{"findings": []}"#), Vec::new());
+        assert_eq!(
+            parse_findings(
+                "```json\n{\"findings\": [{\"line\": 2, \"message\": \"Fix it.\", \"suggestion\": \"fixed\"}]}\n```"
+            ),
+            vec![AiFinding {
+                line: Some(2),
+                message: "Fix it.".into(),
+                suggestion: Some("fixed".into()),
+                affected_block: None,
+            }]
+        );
+        assert_eq!(
+            parse_findings(r#"{"findings": [{"line": null, "message": "Fix it."}]}"#),
+            vec![AiFinding::new("Fix it.")]
+        );
+    }
+
+    #[test]
+    fn parse_findings_reports_free_text_as_one_finding() {
+        assert_eq!(
+            parse_findings(" The block does not mention 'banana'. Add it.\n"),
+            vec![AiFinding::new(
+                "The block does not mention 'banana'. Add it."
+            )]
+        );
+    }
+
+    #[test]
+    fn user_prompt_numbers_block_lines() {
+        assert_eq!(
+            user_prompt("condition", "first\nsecond", &[]),
+            "CONDITION:\ncondition\n\nBLOCK (formatting preserved, lines prefixed with their numbers):\n1| first\n2| second"
+        );
+    }
+
     #[tokio::test]
     async fn when_ai_fails_with_error_it_is_propagated() -> anyhow::Result<()> {
         let validator = CheckAiValidator::with_client(FakeClient::new(HashMap::from([(
@@ -445,6 +1174,137 @@ text
         Ok(())
     }
 
+    #[tokio::test]
+    async fn when_ai_fails_in_warn_mode_returns_warning() -> anyhow::Result<()> {
+        let validator = CheckAiValidator::with_client(FakeClient::new(HashMap::from([(
+            ("condition".into(), "text".into()),
+            FakeAiResponse::Err("API error".into()),
+        )])))
+        .with_failure_mode(AiFailureMode::Warn);
+        let context = validation_context(
+            "example.py",
+            r#"# <block check-ai="condition">
+text
+# </block>"#,
+        );
+        let violations = validator.validate(context).await?;
+        let violation = &violations[&PathBuf::from("example.py")][0];
+        assert_eq!(
+            violation.message,
+            "Block example.py:(unnamed) defined at line 1 could not be checked by AI: API error"
+        );
+        assert_eq!(violation.severity(), BlockSeverity::Warning);
+        assert_eq!(violation.data, Some(json!({"condition": "condition"})));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn missing_fixture_in_warn_mode_returns_error() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let validator = CheckAiValidator::with_client(ReplayClient::new(Fixtures::load(
+            &dir.path().join("ai.json"),
+        )?))
+        .with_failure_mode(AiFailureMode::Warn);
+        let context = validation_context(
+            "example.py",
+            r#"# <block check-ai="condition">
+text
+# </block>"#,
+        );
+        let err = validator.validate(context).await.unwrap_err();
+        assert!(format!("{err:#}").contains("no response to the condition \"condition\""));
+        Ok(())
+    }
+
+    /// Answers every check with a violation and counts the calls.
+    #[derive(Default)]
+    struct CountingClient {
+        calls: std::sync::atomic::AtomicUsize,
+        cache_key: Option<String>,
+    }
+
+    #[async_trait]
+    impl AiClient for CountingClient {
+        async fn check_block(
+            &self,
+            condition: &str,
+            _block_content: &str,
+            _affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
+            self.calls
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(vec![AiFinding::new(format!("violates {condition}"))])
+        }
+
+        fn cache_key(&self) -> Option<String> {
+            self.cache_key.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn cached_response_is_reused() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        let client = CountingClient {
+            cache_key: Some("model".into()),
+            ..Default::default()
+        };
+        let first =
+            check_block_cached(&client, Some(&cache), "condition", "text", &[], false).await?;
+        let second =
+            check_block_cached(&client, Some(&cache), "condition", "text", &[], false).await?;
+        assert_eq!(first, (vec![AiFinding::new("violates condition")], false));
+        assert_eq!(second, (vec![AiFinding::new("violates condition")], true));
+        assert_eq!(client.calls.load(std::sync::atomic::Ordering::Relaxed), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn changed_content_is_not_read_from_cache() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        let client = CountingClient {
+            cache_key: Some("model".into()),
+            ..Default::default()
+        };
+        check_block_cached(&client, Some(&cache), "condition", "text", &[], false).await?;
+        let (_, cached) =
+            check_block_cached(&client, Some(&cache), "condition", "other", &[], false).await?;
+        assert!(!cached);
+        assert_eq!(client.calls.load(std::sync::atomic::Ordering::Relaxed), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn client_without_cache_key_is_not_cached() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        let client = CountingClient::default();
+        check_block_cached(&client, Some(&cache), "condition", "text", &[], false).await?;
+        let (_, cached) =
+            check_block_cached(&client, Some(&cache), "condition", "text", &[], false).await?;
+        assert!(!cached);
+        assert_eq!(client.calls.load(std::sync::atomic::Ordering::Relaxed), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failing_to_cache_response_returns_findings() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        // The cache directory can't be created under a file.
+        let cache_dir = dir.path().join("file");
+        std::fs::write(&cache_dir, "")?;
+        let cache = AiCache::new(&cache_dir);
+        let client = CountingClient {
+            cache_key: Some("model".into()),
+            ..Default::default()
+        };
+        let result =
+            check_block_cached(&client, Some(&cache), "condition", "text", &[], false).await?;
+        assert_eq!(result, (vec![AiFinding::new("violates condition")], false));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn empty_condition_returns_error() -> anyhow::Result<()> {
         let validator = CheckAiValidator::with_client(FakeClient::default());
@@ -0,0 +1,290 @@
+//! On-disk cache of the `check-ai` responses.
+//!
+//! Every response is stored in its own file named after a hash of the model, the system prompt,
+//! the condition and the contents of the block and the blocks it affects, so an unchanged block is
+//! not sent to the model again.
+
+use super::AiFinding;
+use crate::validators::AffectedBlock;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Bumped when the format of the cached responses changes, to ignore the old entries.
+const CACHE_VERSION: &str = "3";
+
+/// Entries not used for this long are removed by [`AiCache::prune`].
+pub(super) const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Numbers the temporary files of this process, as blocks with the same key are written
+/// concurrently.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// The response of [`super::AiClient::check_block`].
+    findings: Vec<AiFinding>,
+}
+
+pub(super) struct AiCache {
+    dir: PathBuf,
+}
+
+impl AiCache {
+    /// Creates the cache stored in the `check-ai` subdirectory of `cache_dir`.
+    pub(super) fn new(cache_dir: &Path) -> Self {
+        Self {
+            dir: cache_dir.join("check-ai"),
+        }
+    }
+
+    /// Returns the key of the response of `model` to the check of `block_content` and its
+    /// `affected_blocks` against `condition` with the `system_prompt`.
+    pub(super) fn key(
+        model: &str,
+        system_prompt: &str,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> String {
+        let mut hasher = Sha256::new();
+        let affected_parts = affected_blocks.iter().flat_map(|affected_block| {
+            [affected_block.reference(), affected_block.content.clone()]
+        });
+        let parts = [
+            CACHE_VERSION,
+            model,
+            system_prompt,
+            condition,
+            block_content,
+        ]
+        .map(String::from)
+        .into_iter()
+        .chain(affected_parts);
+        for part in parts {
+            // The length prefixes keep the boundaries between the parts unambiguous.
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Returns the cached response for `key`, if any, and marks it as used.
+    ///
+    /// Unreadable entries are treated as missing.
+    pub(super) fn get(&self, key: &str) -> Option<Vec<AiFinding>> {
+        let path = self.entry_path(key);
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        // Failing to mark the entry as used only makes it pruned earlier.
+        let _ = fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(entry.findings)
+    }
+
+    /// Stores the response `findings` for `key`.
+    pub(super) fn put(&self, key: &str, findings: &[AiFinding]) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let contents = serde_json::to_string(&CacheEntry {
+            findings: findings.to_vec(),
+        })?;
+        // Write to a temporary file first so that concurrent runs never read a partial entry.
+        let path = self.entry_path(key);
+        let temp_path = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, contents)
+            .with_context(|| format!("failed to write {}", temp_path.display()))?;
+        fs::rename(&temp_path, &path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Removes the entries which were not used for longer than `max_age` and returns their number.
+    ///
+    /// The temporary files of the entries being written are left alone, and the entries removed
+    /// meanwhile (e.g. by a concurrent run) are skipped.
+    pub(super) fn prune(&self, max_age: Duration) -> anyhow::Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.dir.display()));
+            }
+        };
+        let now = SystemTime::now();
+        let mut pruned = 0;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("failed to read {}", self.dir.display()))?
+                .path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to read {}", path.display()));
+                }
+            };
+            if now.duration_since(modified).unwrap_or_default() > max_age {
+                match fs::remove_file(&path) {
+                    Ok(()) => pruned += 1,
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("failed to remove {}", path.display()));
+                    }
+                }
+            }
+        }
+        Ok(pruned)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_depends_on_every_part() {
+        let affected = AffectedBlock {
+            file: "README.md".into(),
+            name: "docs".into(),
+            content: "content".into(),
+        };
+        let key = AiCache::key("model", "prompt", "condition", "content", &[]);
+        assert_eq!(
+            key,
+            AiCache::key("model", "prompt", "condition", "content", &[])
+        );
+        assert_eq!(key.len(), 64);
+        for other in [
+            AiCache::key("other", "prompt", "condition", "content", &[]),
+            AiCache::key("model", "other", "condition", "content", &[]),
+            AiCache::key("model", "prompt", "other", "content", &[]),
+            AiCache::key("model", "prompt", "condition", "other", &[]),
+            AiCache::key("model", "prompt", "conditioncontent", "", &[]),
+            AiCache::key(
+                "model",
+                "prompt",
+                "condition",
+                "content",
+                std::slice::from_ref(&affected),
+            ),
+        ] {
+            assert_ne!(key, other);
+        }
+        let other_affected = AffectedBlock {
+            content: "other".into(),
+            ..affected.clone()
+        };
+        assert_ne!(
+            AiCache::key("model", "prompt", "condition", "content", &[affected]),
+            AiCache::key("model", "prompt", "condition", "content", &[other_affected])
+        );
+    }
+
+    #[test]
+    fn put_then_get_returns_response() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        let findings = vec![AiFinding {
+            line: Some(2),
+            message: "Add a banana.".into(),
+            suggestion: Some("banana".into()),
+            affected_block: None,
+        }];
+        cache.put("ok", &[])?;
+        cache.put("violation", &findings)?;
+        assert_eq!(cache.get("ok"), Some(Vec::new()));
+        assert_eq!(cache.get("violation"), Some(findings));
+        assert_eq!(cache.get("missing"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_puts_of_same_key_succeed() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (0..20).try_for_each(|_| cache.put("key", &[]))))
+                .collect();
+            handles
+                .into_iter()
+                .try_for_each(|handle| handle.join().unwrap())
+        })?;
+        assert_eq!(cache.get("key"), Some(Vec::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted_entry_is_treated_as_missing() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        cache.put("key", &[])?;
+        fs::write(cache.entry_path("key"), "{")?;
+        assert_eq!(cache.get("key"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn prune_removes_entries_not_used_for_max_age() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        cache.put("old", &[])?;
+        cache.put("used", &[])?;
+        cache.put("new", &[])?;
+        let long_ago = SystemTime::now() - 2 * MAX_AGE;
+        for key in ["old", "used"] {
+            fs::File::options()
+                .write(true)
+                .open(cache.entry_path(key))?
+                .set_modified(long_ago)?;
+        }
+        assert!(cache.get("used").is_some());
+
+        assert_eq!(cache.prune(MAX_AGE)?, 1);
+        assert_eq!(cache.get("old"), None);
+        assert!(cache.get("used").is_some());
+        assert!(cache.get("new").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn prune_skips_temporary_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = AiCache::new(dir.path());
+        cache.put("key", &[])?;
+        let temp_path = cache.entry_path("key").with_extension("1.tmp");
+        fs::write(&temp_path, "{")?;
+        fs::File::options()
+            .write(true)
+            .open(&temp_path)?
+            .set_modified(SystemTime::now() - 2 * MAX_AGE)?;
+
+        assert_eq!(cache.prune(MAX_AGE)?, 0);
+        assert!(temp_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn prune_without_cache_dir_returns_zero() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(AiCache::new(&dir.path().join("missing")).prune(MAX_AGE)?, 0);
+        Ok(())
+    }
+}
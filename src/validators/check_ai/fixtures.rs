@@ -0,0 +1,393 @@
+//! Recorded `check-ai` responses for the `record` and `replay` modes of `BLOCKWATCH_AI_MODE`.
+//!
+//! The responses are stored in a JSON file committed to the repository, so that the runs without
+//! network access or an API key (e.g. CI of forks) still check the blocks reproducibly.
+
+use super::{AI_MODE_ENV_VAR_NAME, AiClient, AiFinding};
+use crate::validators::AffectedBlock;
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How the `check-ai` responses are obtained.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum AiMode {
+    /// Ask the AI provider.
+    Live,
+    /// Ask the AI provider and record the responses in the fixtures file.
+    Record,
+    /// Answer from the fixtures file only, failing on the checks which were not recorded.
+    Replay,
+}
+
+impl AiMode {
+    /// Returns the mode set by `BLOCKWATCH_AI_MODE` (live by default).
+    pub(super) fn from_env() -> anyhow::Result<Self> {
+        let mode = std::env::var(AI_MODE_ENV_VAR_NAME).unwrap_or_default();
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "" | "live" => Ok(Self::Live),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            _ => Err(anyhow!(
+                "unsupported {AI_MODE_ENV_VAR_NAME} \"{mode}\", expected one of: live, record, replay"
+            )),
+        }
+    }
+}
+
+/// A recorded check and its response.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fixture {
+    condition: String,
+    block: String,
+    /// The blocks referenced by the `affects` attribute of the block.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    affected_blocks: Vec<AffectedBlock>,
+    /// Empty if the block satisfies the condition.
+    findings: Vec<AiFinding>,
+}
+
+/// The condition, block content and affected blocks of a recorded check.
+type FixtureKey = (String, String, Vec<AffectedBlock>);
+
+/// The responses of the fixtures file by their condition and contents.
+pub(super) struct Fixtures {
+    path: PathBuf,
+    responses: Mutex<BTreeMap<FixtureKey, Vec<AiFinding>>>,
+}
+
+impl Fixtures {
+    /// Loads the fixtures file at `path`. A missing file has no fixtures.
+    pub(super) fn load(path: &Path) -> anyhow::Result<Self> {
+        let fixtures: Vec<Fixture> = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("invalid check-ai fixtures file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            responses: Mutex::new(
+                fixtures
+                    .into_iter()
+                    .map(|fixture| {
+                        (
+                            (fixture.condition, fixture.block, fixture.affected_blocks),
+                            fixture.findings,
+                        )
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Returns the recorded response to the check of `block_content` and its `affected_blocks`
+    /// against `condition`.
+    fn get(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> Option<Vec<AiFinding>> {
+        self.responses
+            .lock()
+            .expect("no active locks")
+            .get(&key(condition, block_content, affected_blocks))
+            .cloned()
+    }
+
+    /// Records the response `findings` and writes the fixtures file.
+    ///
+    /// The fixtures are sorted by their condition and block content to keep the diffs of the file
+    /// small.
+    fn record(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+        findings: &[AiFinding],
+    ) -> anyhow::Result<()> {
+        let mut responses = self.responses.lock().expect("no active locks");
+        responses.insert(
+            key(condition, block_content, affected_blocks),
+            findings.to_vec(),
+        );
+        let fixtures: Vec<Fixture> = responses
+            .iter()
+            .map(|((condition, block, affected_blocks), findings)| Fixture {
+                condition: condition.clone(),
+                block: block.clone(),
+                affected_blocks: affected_blocks.clone(),
+                findings: findings.clone(),
+            })
+            .collect();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&fixtures)? + "\n")
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+}
+
+fn key(condition: &str, block_content: &str, affected_blocks: &[AffectedBlock]) -> FixtureKey {
+    (
+        condition.to_string(),
+        block_content.to_string(),
+        affected_blocks.to_vec(),
+    )
+}
+
+/// Asks `client` and records its responses in the fixtures.
+pub(super) struct RecordingClient<C: ?Sized> {
+    client: Arc<C>,
+    fixtures: Fixtures,
+}
+
+impl<C: ?Sized> RecordingClient<C> {
+    pub(super) fn new(client: Arc<C>, fixtures: Fixtures) -> Self {
+        Self { client, fixtures }
+    }
+}
+
+#[async_trait]
+impl<C: AiClient + ?Sized> AiClient for RecordingClient<C> {
+    async fn check_block(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        let findings = self
+            .client
+            .check_block(condition, block_content, affected_blocks)
+            .await?;
+        self.fixtures
+            .record(condition, block_content, affected_blocks, &findings)?;
+        Ok(findings)
+    }
+}
+
+/// Answers from the fixtures only.
+pub(super) struct ReplayClient {
+    fixtures: Fixtures,
+}
+
+impl ReplayClient {
+    pub(super) fn new(fixtures: Fixtures) -> Self {
+        Self { fixtures }
+    }
+}
+
+#[async_trait]
+impl AiClient for ReplayClient {
+    async fn check_block(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        self.fixtures
+            .get(condition, block_content, affected_blocks)
+            .ok_or_else(|| {
+                MissingFixture {
+                    condition: condition.to_string(),
+                    path: self.fixtures.path.clone(),
+                }
+                .into()
+            })
+    }
+}
+
+/// The error of a check without a recorded response in the `replay` mode.
+///
+/// It means the fixtures are outdated rather than that the provider is unavailable, so it fails
+/// the run even with `BLOCKWATCH_AI_ON_ERROR=warn`.
+#[derive(Debug)]
+pub(super) struct MissingFixture {
+    condition: String,
+    path: PathBuf,
+}
+
+impl std::fmt::Display for MissingFixture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no response to the condition \"{}\" for the current block content is recorded in {}, record it with {AI_MODE_ENV_VAR_NAME}=record",
+            self.condition,
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for MissingFixture {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClient;
+
+    #[async_trait]
+    impl AiClient for FakeClient {
+        async fn check_block(
+            &self,
+            condition: &str,
+            block_content: &str,
+            _affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
+            if block_content.contains(condition) {
+                Ok(Vec::new())
+            } else {
+                Ok(vec![AiFinding::new(format!("Mention {condition}."))])
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn recorded_responses_are_replayed() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("fixtures/ai.json");
+        let recording = RecordingClient::new(Arc::new(FakeClient), Fixtures::load(&path)?);
+        assert_eq!(
+            recording.check_block("banana", "pear", &[]).await?,
+            vec![AiFinding::new("Mention banana.")]
+        );
+        assert_eq!(
+            recording.check_block("apple", "apple pie", &[]).await?,
+            Vec::new()
+        );
+
+        let replay = ReplayClient::new(Fixtures::load(&path)?);
+        assert_eq!(
+            replay.check_block("banana", "pear", &[]).await?,
+            vec![AiFinding::new("Mention banana.")]
+        );
+        assert_eq!(
+            replay.check_block("apple", "apple pie", &[]).await?,
+            Vec::new()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recording_keeps_fixtures_sorted_and_updates_them() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ai.json");
+        fs::write(
+            &path,
+            r#"[{"condition": "banana", "block": "pear", "findings": []}]"#,
+        )?;
+        let recording = RecordingClient::new(Arc::new(FakeClient), Fixtures::load(&path)?);
+        recording.check_block("banana", "pear", &[]).await?;
+        recording.check_block("apple", "apple pie", &[]).await?;
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            r#"[
+  {
+    "condition": "apple",
+    "block": "apple pie",
+    "findings": []
+  },
+  {
+    "condition": "banana",
+    "block": "pear",
+    "findings": [
+      {
+        "line": null,
+        "message": "Mention banana.",
+        "suggestion": null
+      }
+    ]
+  }
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn responses_are_recorded_with_affected_blocks() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ai.json");
+        let affected_blocks = [AffectedBlock {
+            file: "README.md".into(),
+            name: "fruits".into(),
+            content: "banana".into(),
+        }];
+        let recording = RecordingClient::new(Arc::new(FakeClient), Fixtures::load(&path)?);
+        recording
+            .check_block("banana", "pear", &affected_blocks)
+            .await?;
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            r#"[
+  {
+    "condition": "banana",
+    "block": "pear",
+    "affected_blocks": [
+      {
+        "file": "README.md",
+        "name": "fruits",
+        "content": "banana"
+      }
+    ],
+    "findings": [
+      {
+        "line": null,
+        "message": "Mention banana.",
+        "suggestion": null
+      }
+    ]
+  }
+]
+"#
+        );
+
+        let replay = ReplayClient::new(Fixtures::load(&path)?);
+        assert_eq!(
+            replay
+                .check_block("banana", "pear", &affected_blocks)
+                .await?,
+            vec![AiFinding::new("Mention banana.")]
+        );
+        assert!(replay.check_block("banana", "pear", &[]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_without_recorded_response_returns_error() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ai.json");
+        let replay = ReplayClient::new(Fixtures::load(&path)?);
+        let err = replay.check_block("banana", "pear", &[]).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "no response to the condition \"banana\" for the current block content is recorded in {}, record it with BLOCKWATCH_AI_MODE=record",
+                path.display()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_fixtures_file_returns_error() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ai.json");
+        fs::write(&path, "{")?;
+        let err = Fixtures::load(&path).err().expect("invalid fixtures file");
+        assert!(
+            err.to_string()
+                .starts_with("invalid check-ai fixtures file")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,499 @@
+//! Limits of the requests to the AI providers: how many are in flight at once, how long each may
+//! take and how the transient failures (e.g. rate limits) are retried.
+
+use super::{
+    AI_CONCURRENCY_ENV_VAR_NAME, AI_MAX_RETRIES_ENV_VAR_NAME, AI_ON_ERROR_ENV_VAR_NAME,
+    AI_TIMEOUT_ENV_VAR_NAME, AiClient, AiFinding,
+};
+use crate::validators::AffectedBlock;
+use anyhow::anyhow;
+use async_openai::error::OpenAIError;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// The first delay between the retries, doubled after every retry.
+const BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// The longest wait asked by a `Retry-After` header that is honoured.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+/// The limits of the requests to the AI provider.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct AiLimits {
+    /// How many requests may be in flight at once.
+    pub(super) concurrency: usize,
+    /// How many times a request failing with a transient error is retried.
+    pub(super) max_retries: u32,
+    /// How long a request may take before it is cancelled (and retried).
+    pub(super) timeout: Duration,
+    /// The delay before the first retry.
+    pub(super) backoff: Duration,
+}
+
+impl Default for AiLimits {
+    fn default() -> Self {
+        // <block affects="README.md:check-ai-limits">
+        Self {
+            concurrency: 8,
+            max_retries: 3,
+            timeout: Duration::from_secs(60),
+            backoff: BACKOFF,
+        }
+        // </block>
+    }
+}
+
+impl AiLimits {
+    /// Returns the limits set by the `BLOCKWATCH_AI_CONCURRENCY`, `BLOCKWATCH_AI_MAX_RETRIES` and
+    /// `BLOCKWATCH_AI_TIMEOUT` (seconds) environment variables. Unset variables and values which
+    /// are not integers (positive except for the retries) leave the default.
+    pub(super) fn from_env() -> Self {
+        let integer = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let positive_integer = |name: &str| integer(name).filter(|value| *value > 0);
+        let default = Self::default();
+        Self {
+            concurrency: positive_integer(AI_CONCURRENCY_ENV_VAR_NAME)
+                .map_or(default.concurrency, |concurrency| {
+                    usize::try_from(concurrency).unwrap_or(usize::MAX)
+                }),
+            max_retries: integer(AI_MAX_RETRIES_ENV_VAR_NAME)
+                .map_or(default.max_retries, |retries| {
+                    u32::try_from(retries).unwrap_or(u32::MAX)
+                }),
+            timeout: positive_integer(AI_TIMEOUT_ENV_VAR_NAME)
+                .map_or(default.timeout, Duration::from_secs),
+            backoff: default.backoff,
+        }
+    }
+
+    /// Returns the delay before the retry number `retry` (starting at 0): the `retry_after` asked
+    /// by the provider, or an exponential backoff. Both are spread randomly so that the requests
+    /// failing together are not retried together.
+    fn retry_delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(retry_after) => {
+                let retry_after = retry_after.min(MAX_RETRY_AFTER);
+                retry_after + jitter(retry_after / 4)
+            }
+            None => {
+                let backoff = self
+                    .backoff
+                    .saturating_mul(2u32.saturating_pow(retry))
+                    .min(MAX_BACKOFF);
+                backoff / 2 + jitter(backoff / 2)
+            }
+        }
+    }
+}
+
+/// Returns a random duration up to `max`.
+fn jitter(max: Duration) -> Duration {
+    max.mul_f64(rand::random::<f64>())
+}
+
+/// What to do when a block can't be checked because the AI provider keeps failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum AiFailureMode {
+    /// Fail the run.
+    Fail,
+    /// Report a warning on the block and go on.
+    Warn,
+}
+
+impl AiFailureMode {
+    /// Returns the mode set by `BLOCKWATCH_AI_ON_ERROR` (fail by default).
+    pub(super) fn from_env() -> anyhow::Result<Self> {
+        let mode = std::env::var(AI_ON_ERROR_ENV_VAR_NAME).unwrap_or_default();
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "" | "fail" => Ok(Self::Fail),
+            "warn" => Ok(Self::Warn),
+            _ => Err(anyhow!(
+                "unsupported {AI_ON_ERROR_ENV_VAR_NAME} \"{mode}\", expected one of: fail, warn"
+            )),
+        }
+    }
+}
+
+/// An unsuccessful response of an AI provider.
+#[derive(Debug)]
+pub(super) struct AiApiError {
+    pub(super) status: StatusCode,
+    /// The wait asked by the `Retry-After` header of the response.
+    pub(super) retry_after: Option<Duration>,
+    pub(super) body: String,
+}
+
+impl AiApiError {
+    /// Returns the error of the unsuccessful `response`.
+    pub(super) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_default();
+        Self {
+            status,
+            retry_after,
+            body,
+        }
+    }
+}
+
+impl fmt::Display for AiApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AI API request failed with {}: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for AiApiError {}
+
+/// Parses the `Retry-After` header in seconds. Dates are not supported and ignored.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<f64>().ok().and_then(|seconds| {
+        (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+    })
+}
+
+/// Whether a request failing with the `status` may succeed when retried.
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+/// Returns `Some` with the wait asked by the provider if the request failing with `error` may
+/// succeed when retried (rate limits, server errors, connection failures), `None` otherwise.
+fn transient_failure(error: &anyhow::Error) -> Option<Option<Duration>> {
+    for cause in error.chain() {
+        if let Some(api_error) = cause.downcast_ref::<AiApiError>() {
+            return is_transient_status(api_error.status).then_some(api_error.retry_after);
+        }
+        match cause.downcast_ref::<OpenAIError>() {
+            // async-openai drops the response headers, so its rate limits can't honour
+            // `Retry-After` (see README.md:check-ai-limits).
+            Some(OpenAIError::ApiError(api_error)) => {
+                return is_transient_status(api_error.status_code).then_some(None);
+            }
+            Some(OpenAIError::Reqwest(e)) => {
+                return (e.is_connect() || e.is_timeout()).then_some(None);
+            }
+            Some(_) => return None,
+            None => {}
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return (e.is_connect() || e.is_timeout()).then_some(None);
+        }
+    }
+    None
+}
+
+/// Limits the requests of `client` in flight, cancels the requests taking too long and retries
+/// the transient failures.
+pub(super) struct RetryingClient<C: ?Sized> {
+    client: Arc<C>,
+    limits: AiLimits,
+    requests: Semaphore,
+    verbose: bool,
+}
+
+impl<C: ?Sized> RetryingClient<C> {
+    /// Creates the client reporting the retries to stderr if `verbose`.
+    pub(super) fn new(client: Arc<C>, limits: AiLimits, verbose: bool) -> Self {
+        Self {
+            client,
+            limits,
+            requests: Semaphore::new(limits.concurrency),
+            verbose,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: AiClient + ?Sized> AiClient for RetryingClient<C> {
+    async fn check_block(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        let mut retry = 0;
+        loop {
+            let response = {
+                let _permit = self.requests.acquire().await?;
+                tokio::time::timeout(
+                    self.limits.timeout,
+                    self.client
+                        .check_block(condition, block_content, affected_blocks),
+                )
+                .await
+            };
+            let (error, retry_after) = match response {
+                Ok(Ok(findings)) => return Ok(findings),
+                Ok(Err(error)) => match transient_failure(&error) {
+                    Some(retry_after) => (error, retry_after),
+                    None => return Err(error),
+                },
+                Err(_) => (
+                    anyhow!(
+                        "AI API request timed out after {}s",
+                        self.limits.timeout.as_secs_f64()
+                    ),
+                    None,
+                ),
+            };
+            if retry == self.limits.max_retries {
+                return Err(if retry == 0 {
+                    error
+                } else {
+                    error.context(format!("AI API request failed {} times", retry + 1))
+                });
+            }
+            let delay = self.limits.retry_delay(retry, retry_after);
+            if self.verbose {
+                eprintln!(
+                    "check-ai request failed, retrying in {:.1}s: {error:#}",
+                    delay.as_secs_f64()
+                );
+            }
+            tokio::time::sleep(delay).await;
+            retry += 1;
+        }
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        self.client.cache_key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers with the errors in order, then with no findings.
+    struct FlakyClient {
+        errors: Mutex<Vec<anyhow::Error>>,
+        calls: AtomicUsize,
+    }
+
+    impl FlakyClient {
+        fn new(errors: Vec<anyhow::Error>) -> Self {
+            Self {
+                errors: Mutex::new(errors),
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AiClient for FlakyClient {
+        async fn check_block(
+            &self,
+            _condition: &str,
+            _block_content: &str,
+            _affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            let mut errors = self.errors.lock().unwrap();
+            if errors.is_empty() {
+                Ok(Vec::new())
+            } else {
+                Err(errors.remove(0))
+            }
+        }
+    }
+
+    fn api_error(status: StatusCode) -> anyhow::Error {
+        AiApiError {
+            status,
+            retry_after: None,
+            body: "{}".into(),
+        }
+        .into()
+    }
+
+    fn fast_limits() -> AiLimits {
+        AiLimits {
+            backoff: Duration::from_millis(1),
+            ..AiLimits::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() -> anyhow::Result<()> {
+        let client = Arc::new(FlakyClient::new(vec![
+            api_error(StatusCode::TOO_MANY_REQUESTS),
+            api_error(StatusCode::SERVICE_UNAVAILABLE),
+        ]));
+        let retrying = RetryingClient::new(Arc::clone(&client), fast_limits(), false);
+        assert_eq!(retrying.check_block("c", "b", &[]).await?, Vec::new());
+        assert_eq!(client.calls.load(Ordering::Relaxed), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried() {
+        let client = Arc::new(FlakyClient::new(vec![api_error(StatusCode::UNAUTHORIZED)]));
+        let retrying = RetryingClient::new(Arc::clone(&client), fast_limits(), false);
+        let err = retrying.check_block("c", "b", &[]).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AI API request failed with 401 Unauthorized: {}"
+        );
+        assert_eq!(client.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_are_limited() {
+        let client = Arc::new(FlakyClient::new(
+            (0..5)
+                .map(|_| api_error(StatusCode::TOO_MANY_REQUESTS))
+                .collect(),
+        ));
+        let limits = AiLimits {
+            max_retries: 2,
+            ..fast_limits()
+        };
+        let retrying = RetryingClient::new(Arc::clone(&client), limits, false);
+        let err = retrying.check_block("c", "b", &[]).await.unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "AI API request failed 3 times: AI API request failed with 429 Too Many Requests: {}"
+        );
+        assert_eq!(client.calls.load(Ordering::Relaxed), 3);
+    }
+
+    struct SlowClient;
+
+    #[async_trait]
+    impl AiClient for SlowClient {
+        async fn check_block(
+            &self,
+            _condition: &str,
+            _block_content: &str,
+            _affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_requests_time_out() {
+        let limits = AiLimits {
+            max_retries: 0,
+            timeout: Duration::from_millis(10),
+            ..fast_limits()
+        };
+        let retrying = RetryingClient::new(Arc::new(SlowClient), limits, false);
+        let err = retrying.check_block("c", "b", &[]).await.unwrap_err();
+        assert_eq!(err.to_string(), "AI API request timed out after 0.01s");
+    }
+
+    /// Counts the requests in flight at once.
+    #[derive(Default)]
+    struct ConcurrencyClient {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AiClient for ConcurrencyClient {
+        async fn check_block(
+            &self,
+            _condition: &str,
+            _block_content: &str,
+            _affected_blocks: &[AffectedBlock],
+        ) -> anyhow::Result<Vec<AiFinding>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_in_flight_are_limited() -> anyhow::Result<()> {
+        let client = Arc::new(ConcurrencyClient::default());
+        let limits = AiLimits {
+            concurrency: 2,
+            ..fast_limits()
+        };
+        let retrying = Arc::new(RetryingClient::new(Arc::clone(&client), limits, false));
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..6 {
+            let retrying = Arc::clone(&retrying);
+            tasks.spawn(async move { retrying.check_block("c", "b", &[]).await });
+        }
+        while let Some(result) = tasks.join_next().await {
+            result??;
+        }
+        assert_eq!(client.max_in_flight.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn retry_delay_honours_retry_after() {
+        let limits = AiLimits::default();
+        let delay = limits.retry_delay(0, Some(Duration::from_secs(4)));
+        assert!((Duration::from_secs(4)..=Duration::from_secs(5)).contains(&delay));
+        let delay = limits.retry_delay(0, Some(Duration::from_secs(3600)));
+        assert!((MAX_RETRY_AFTER..=MAX_RETRY_AFTER * 5 / 4).contains(&delay));
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        let limits = AiLimits::default();
+        for (retry, backoff) in [(0, BACKOFF), (2, BACKOFF * 4), (20, MAX_BACKOFF)] {
+            let delay = limits.retry_delay(retry, None);
+            assert!(
+                (backoff / 2..=backoff).contains(&delay),
+                "{retry}: {delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_retry_after_reads_seconds() {
+        assert_eq!(parse_retry_after("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_retry_after(" 0.5 "), Some(Duration::from_millis(500)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn transient_failure_classifies_errors() {
+        let rate_limited: anyhow::Error = AiApiError {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            retry_after: Some(Duration::from_secs(3)),
+            body: String::new(),
+        }
+        .into();
+        assert_eq!(
+            transient_failure(&rate_limited.context("context")),
+            Some(Some(Duration::from_secs(3)))
+        );
+        assert_eq!(
+            transient_failure(&api_error(StatusCode::BAD_GATEWAY)),
+            Some(None)
+        );
+        assert_eq!(transient_failure(&api_error(StatusCode::BAD_REQUEST)), None);
+        assert_eq!(transient_failure(&anyhow!("API key is empty.")), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! The AI providers selectable with the `BLOCKWATCH_AI_PROVIDER` environment variable.
+
+use super::limits::AiApiError;
+use super::{
+    API_KEY_ENV_VAR_NAME, API_MODEL_ENV_VAR_NAME, API_URL_ENV_VAR_NAME, AiClient, AiFinding,
+    DEFAULT_SYSTEM_PROMPT, OpenAiClient, PROVIDER_ENV_VAR_NAME, RESPONSE_PATH_ENV_VAR_NAME,
+    parse_findings, reply_schema, user_prompt,
+};
+use crate::validators::AffectedBlock;
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+
+pub(super) const OLLAMA_API_BASE: &str = "http://localhost:11434/v1";
+pub(super) const DEFAULT_OLLAMA_MODEL_NAME: &str = "llama3.2";
+
+const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+const DEFAULT_ANTHROPIC_MODEL_NAME: &str = "claude-haiku-4-5";
+const ANTHROPIC_MAX_TOKENS: u32 = 1024;
+
+const DEFAULT_RESPONSE_PATH: &str = "/response";
+
+/// Returns the client of the provider set by `BLOCKWATCH_AI_PROVIDER` (OpenAI by default).
+pub(super) fn client_from_env() -> anyhow::Result<Box<dyn AiClient>> {
+    let provider = std::env::var(PROVIDER_ENV_VAR_NAME).unwrap_or_default();
+    // <block affects="README.md:check-ai-providers">
+    match provider.trim().to_ascii_lowercase().as_str() {
+        "" | "openai" => Ok(Box::new(OpenAiClient::new_from_env())),
+        "anthropic" => Ok(Box::new(AnthropicClient::new_from_env())),
+        "ollama" => Ok(Box::new(OpenAiClient::new_local_from_env())),
+        "http" => Ok(Box::new(HttpClient::new_from_env()?)),
+        _ => Err(anyhow!(
+            "unsupported {PROVIDER_ENV_VAR_NAME} \"{provider}\", expected one of: openai, anthropic, ollama, http"
+        )),
+    }
+    // </block>
+}
+
+/// Sends the JSON `body` with `request` and returns the JSON response.
+async fn post_json(request: reqwest::RequestBuilder, body: &Value) -> anyhow::Result<Value> {
+    let response = request
+        .json(body)
+        .send()
+        .await
+        .context("AI API request failed")?;
+    if !response.status().is_success() {
+        return Err(AiApiError::from_response(response).await.into());
+    }
+    let text = response
+        .text()
+        .await
+        .context("failed to read AI API response")?;
+    serde_json::from_str(&text).with_context(|| format!("AI API response is not JSON: {text}"))
+}
+
+/// Anthropic Messages API client.
+pub(super) struct AnthropicClient {
+    client: reqwest::Client,
+    api_base: String,
+    api_key: String,
+    model: String,
+}
+
+impl AnthropicClient {
+    /// Creates a new Anthropic client from environment variables (BLOCKWATCH_AI_*).
+    fn new_from_env() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_base: std::env::var(API_URL_ENV_VAR_NAME).unwrap_or(ANTHROPIC_API_BASE.into()),
+            api_key: std::env::var(API_KEY_ENV_VAR_NAME).unwrap_or_default(),
+            model: std::env::var(API_MODEL_ENV_VAR_NAME)
+                .unwrap_or(DEFAULT_ANTHROPIC_MODEL_NAME.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl AiClient for AnthropicClient {
+    async fn check_block(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        if self.api_key.is_empty() {
+            return Err(anyhow!(
+                "API key is empty. Is {API_KEY_ENV_VAR_NAME} env variable set?"
+            ));
+        }
+        let request = self
+            .client
+            .post(format!("{}/messages", self.api_base.trim_end_matches('/')))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_API_VERSION);
+        let response = post_json(
+            request,
+            &json!({
+                "model": self.model,
+                "max_tokens": ANTHROPIC_MAX_TOKENS,
+                "system": DEFAULT_SYSTEM_PROMPT,
+                "messages": [{"role": "user", "content": user_prompt(condition, block_content, affected_blocks)}],
+            }),
+        )
+        .await?;
+        anthropic_message(&response).map(|message| parse_findings(&message))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!("anthropic {} {}", self.api_base, self.model))
+    }
+}
+
+/// Returns the concatenated text content of an Anthropic Messages API response.
+fn anthropic_message(response: &Value) -> anyhow::Result<String> {
+    let message: String = response
+        .get("content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|content| content.get("type").and_then(Value::as_str) == Some("text"))
+        .filter_map(|content| content.get("text").and_then(Value::as_str))
+        .collect();
+    if message.is_empty() {
+        Err(anyhow!("empty response from AI"))
+    } else {
+        Ok(message)
+    }
+}
+
+/// Generic HTTP client: POSTs the check as JSON to `BLOCKWATCH_AI_API_URL` and reads the reply at
+/// the `BLOCKWATCH_AI_RESPONSE_PATH` JSON Pointer of the response.
+pub(super) struct HttpClient {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    model: Option<String>,
+    response_path: String,
+}
+
+impl HttpClient {
+    /// Creates a new HTTP client from environment variables (BLOCKWATCH_AI_*).
+    fn new_from_env() -> anyhow::Result<Self> {
+        let url = std::env::var(API_URL_ENV_VAR_NAME).map_err(|_| {
+            anyhow!("{API_URL_ENV_VAR_NAME} must be set when {PROVIDER_ENV_VAR_NAME} is \"http\"")
+        })?;
+        let response_path =
+            std::env::var(RESPONSE_PATH_ENV_VAR_NAME).unwrap_or(DEFAULT_RESPONSE_PATH.into());
+        if !response_path.is_empty() && !response_path.starts_with('/') {
+            return Err(anyhow!(
+                "{RESPONSE_PATH_ENV_VAR_NAME} must be a JSON Pointer starting with \"/\", got \"{response_path}\""
+            ));
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url,
+            api_key: std::env::var(API_KEY_ENV_VAR_NAME).unwrap_or_default(),
+            model: std::env::var(API_MODEL_ENV_VAR_NAME).ok(),
+            response_path,
+        })
+    }
+}
+
+#[async_trait]
+impl AiClient for HttpClient {
+    async fn check_block(
+        &self,
+        condition: &str,
+        block_content: &str,
+        affected_blocks: &[AffectedBlock],
+    ) -> anyhow::Result<Vec<AiFinding>> {
+        let mut request = self.client.post(&self.url);
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+        let response = post_json(
+            request,
+            &json!({
+                "model": self.model,
+                "system": DEFAULT_SYSTEM_PROMPT,
+                "prompt": user_prompt(condition, block_content, affected_blocks),
+                "condition": condition,
+                "block": block_content,
+                "affected_blocks": affected_blocks,
+                "response_schema": reply_schema(),
+            }),
+        )
+        .await?;
+        response_message(&response, &self.response_path).map(|message| parse_findings(&message))
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!(
+            "http {} {} {}",
+            self.url,
+            self.model.as_deref().unwrap_or_default(),
+            self.response_path
+        ))
+    }
+}
+
+/// Returns the string at the `path` JSON Pointer of `response`.
+fn response_message(response: &Value, path: &str) -> anyhow::Result<String> {
+    match response.pointer(path) {
+        Some(Value::String(message)) => Ok(message.clone()),
+        _ => Err(anyhow!(
+            "AI API response has no string at \"{path}\": {response}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anthropic_message_concatenates_text_content() -> anyhow::Result<()> {
+        let response = json!({
+            "content": [
+                {"type": "thinking", "thinking": "hmm"},
+                {"type": "text", "text": "Add "},
+                {"type": "text", "text": "a banana."}
+            ]
+        });
+        assert_eq!(anthropic_message(&response)?, "Add a banana.");
+        Ok(())
+    }
+
+    #[test]
+    fn anthropic_message_without_text_returns_error() {
+        let err = anthropic_message(&json!({"content": []})).unwrap_err();
+        assert_eq!(err.to_string(), "empty response from AI");
+    }
+
+    #[test]
+    fn response_message_reads_json_pointer() -> anyhow::Result<()> {
+        let response = json!({"choices": [{"text": "OK"}]});
+        assert_eq!(response_message(&response, "/choices/0/text")?, "OK");
+        Ok(())
+    }
+
+    #[test]
+    fn response_message_without_string_returns_error() {
+        let err = response_message(&json!({"response": 1}), "/response").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AI API response has no string at \"/response\": {\"response\":1}"
+        );
+    }
+}
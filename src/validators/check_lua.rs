@@ -1,17 +1,27 @@
-use crate::blocks::{Block, BlockWithContext, FileSystem};
-use crate::validators::parse_affects_attribute;
+use crate::Position;
+use crate::blocks::{Block, BlockSeverity, BlockWithContext, FileSystem};
+use crate::language_parsers::LanguageParser;
+use crate::validators;
 use crate::validators::{
-    ValidationContext, ValidatorAsync, ValidatorDetector, ValidatorType, Violation, ViolationRange,
+    AffectedBlock, Fixer, ValidationContext, ValidatorAsync, ValidatorDetector, ValidatorType,
+    Violation, ViolationRange,
 };
 use anyhow::{Context, anyhow};
 use async_trait::async_trait;
-use mlua::{Lua, StdLib};
+use mlua::{ChunkMode, DebugEvent, HookTriggers, Lua, LuaSerdeExt, StdLib, VmState};
 use serde::Serialize;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::ops::{Range, RangeInclusive};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::task::JoinSet;
 
+mod blockwatch_module;
+
 const LUA_STDLIB_ENV_VAR: &str = "BLOCKWATCH_LUA_MODE";
 
 /// Returns the Lua standard library set based on the `BLOCKWATCH_LUA_MODE` environment variable.
@@ -36,149 +46,571 @@ fn lua_from_env() -> Lua {
     // </block>
 }
 
+const LUA_TIMEOUT_ENV_VAR: &str = "BLOCKWATCH_LUA_TIMEOUT";
+const LUA_MEMORY_LIMIT_ENV_VAR: &str = "BLOCKWATCH_LUA_MEMORY_LIMIT";
+const LUA_INSTRUCTION_LIMIT_ENV_VAR: &str = "BLOCKWATCH_LUA_INSTRUCTION_LIMIT";
+
+const MIB: usize = 1024 * 1024;
+
+/// How many instructions a script runs between the checks of its timeout and instruction limit.
+const INSTRUCTIONS_PER_CHECK: u32 = 1000;
+
+/// The limits of a script run for a block.
+#[derive(Clone, Copy, Debug)]
+struct LuaLimits {
+    /// How long the script may run, unless the block sets `check-lua-timeout`.
+    timeout: Duration,
+    /// How many bytes the Lua state may allocate.
+    memory: usize,
+    /// How many Lua instructions the script may run (unlimited if `None`).
+    instructions: Option<u64>,
+}
+
+impl LuaLimits {
+    /// Returns the limits set by the `BLOCKWATCH_LUA_TIMEOUT` (seconds),
+    /// `BLOCKWATCH_LUA_MEMORY_LIMIT` (MiB) and `BLOCKWATCH_LUA_INSTRUCTION_LIMIT` environment
+    /// variables. Unset variables and values which are not positive integers leave the default.
+    fn from_env() -> Self {
+        let positive_integer = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .filter(|value| *value > 0)
+        };
+        // <block affects="README.md:lua-limits">
+        Self {
+            timeout: Duration::from_secs(positive_integer(LUA_TIMEOUT_ENV_VAR).unwrap_or(30)),
+            memory: positive_integer(LUA_MEMORY_LIMIT_ENV_VAR).map_or(256 * MIB, |mib| {
+                usize::try_from(mib).unwrap_or(usize::MAX / MIB) * MIB
+            }),
+            instructions: positive_integer(LUA_INSTRUCTION_LIMIT_ENV_VAR),
+        }
+        // </block>
+    }
+
+    /// Returns the limits with the `check-lua-timeout` of the `block` defined in `file_path`.
+    fn for_block(self, file_path: &Path, block: &Block) -> anyhow::Result<Self> {
+        let Some(timeout) = block.attributes.get("check-lua-timeout") else {
+            return Ok(self);
+        };
+        match timeout.trim().parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Ok(Self {
+                timeout: Duration::from_secs(seconds),
+                ..self
+            }),
+            _ => Err(anyhow!(
+                "check-lua-timeout expected a positive number of seconds, got \"{}\" in {}:{} at line {}",
+                timeout,
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line
+            )),
+        }
+    }
+
+    /// Applies the limits to `lua`, starting the timeout.
+    ///
+    /// Once a limit is exceeded, every function call fails as well as the instructions checks, so
+    /// that a script can't keep running by catching the error with `pcall`.
+    ///
+    /// The hook only runs between the instructions of the VM, so the timeout can't interrupt a
+    /// long call of a C function (e.g. `string.find` with a pathological pattern): it is only
+    /// reported once the call returns. Nothing can be enforced from outside either, as the call
+    /// blocks the worker thread and a thread can't be stopped safely.
+    fn apply(self, lua: &Lua) -> anyhow::Result<()> {
+        lua.set_memory_limit(self.memory)
+            .context("failed to set the Lua memory limit")?;
+        let deadline = Instant::now() + self.timeout;
+        let instructions = AtomicU64::new(0);
+        let exceeded = OnceLock::new();
+        lua.set_global_hook(
+            HookTriggers::new()
+                .every_nth_instruction(INSTRUCTIONS_PER_CHECK)
+                .on_calls(),
+            move |_, debug| {
+                if debug.event() == DebugEvent::Count {
+                    let executed = instructions
+                        .fetch_add(u64::from(INSTRUCTIONS_PER_CHECK), Ordering::Relaxed)
+                        + u64::from(INSTRUCTIONS_PER_CHECK);
+                    if Instant::now() > deadline {
+                        let _ = exceeded.set(format!("the timeout of {}s", self.timeout.as_secs()));
+                    } else if let Some(limit) = self.instructions
+                        && executed > limit
+                    {
+                        let _ = exceeded.set(format!("the limit of {limit} instructions"));
+                    }
+                }
+                match exceeded.get() {
+                    None => Ok(VmState::Continue),
+                    Some(limit) => Err(mlua::Error::external(LimitExceeded(limit.clone()))),
+                }
+            },
+        )
+        .context("failed to set the Lua limits")
+    }
+
+    /// Replaces the `error` of a script which exceeded a limit with one naming the limit.
+    fn describe(&self, error: anyhow::Error) -> anyhow::Error {
+        let limit = error.chain().find_map(|cause| {
+            cause
+                .downcast_ref::<mlua::Error>()
+                .and_then(|cause| self.exceeded_limit(cause))
+        });
+        match limit {
+            Some(limit) => anyhow!("the script exceeded {limit}"),
+            None => error,
+        }
+    }
+
+    fn exceeded_limit(&self, error: &mlua::Error) -> Option<String> {
+        match error {
+            mlua::Error::MemoryError(_) => Some(format!(
+                "the memory limit of {} MiB",
+                self.memory.div_ceil(MIB)
+            )),
+            mlua::Error::CallbackError { cause, .. } | mlua::Error::WithContext { cause, .. } => {
+                self.exceeded_limit(cause)
+            }
+            mlua::Error::ExternalError(cause) => cause
+                .downcast_ref::<LimitExceeded>()
+                .map(|LimitExceeded(limit)| limit.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The error raised in a script which exceeded its timeout or instruction limit.
+#[derive(Debug)]
+struct LimitExceeded(String);
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the script exceeded {}", self.0)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
 pub(crate) struct CheckLuaValidator<Fs: FileSystem> {
-    file_system: Arc<Fs>,
+    scripts: Arc<ScriptCache<Fs>>,
+    limits: LuaLimits,
 }
 
 impl<Fs: FileSystem + 'static> CheckLuaValidator<Fs> {
     pub(super) fn new(file_system: Arc<Fs>) -> Self {
-        Self { file_system }
+        Self {
+            scripts: Arc::new(ScriptCache::new(file_system)),
+            limits: LuaLimits::from_env(),
+        }
     }
 }
 
 #[async_trait]
 impl<Fs: FileSystem + 'static> ValidatorAsync for CheckLuaValidator<Fs> {
+    /// Checks the blocks with a bounded pool of blocking workers, and reports the violations (or the first
+    /// error) in the order of the blocks regardless of which worker finishes first.
     async fn validate(
         &self,
         context: Arc<ValidationContext>,
     ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
-        let mut violations = HashMap::new();
-        let mut tasks = JoinSet::new();
+        let mut jobs = Vec::new();
         for (file_path, file_blocks) in &context.blocks {
             for (block_idx, block_with_context) in
                 file_blocks.blocks_with_context.iter().enumerate()
             {
-                if let Some(script_path) = block_with_context.block.attributes.get("check-lua") {
-                    if script_path.trim().is_empty() {
-                        return Err(anyhow!(
-                            "check-lua requires a non-empty script path in {}:{} at line {}",
-                            file_path.display(),
-                            block_with_context.block.name_display(),
-                            block_with_context
-                                .block
-                                .start_tag_position_range
-                                .start()
-                                .line
-                        ));
-                    };
-                } else {
-                    continue;
+                if script_path(file_path, &block_with_context.block)?.is_some() {
+                    jobs.push((file_path.clone(), block_idx));
                 }
-
-                let context = Arc::clone(&context);
-                let file_path = file_path.clone();
-                let file_system = Arc::clone(&self.file_system);
-                tasks.spawn(async move {
-                    let file_blocks = &context.blocks[&file_path];
-                    let block_with_context = &file_blocks.blocks_with_context[block_idx];
-                    let script_path = &block_with_context.block.attributes["check-lua"];
-                    let content = block_content(block_with_context, &file_blocks.file_content)?;
-                    let affected_blocks =
-                        resolve_affected_blocks(&context, &file_path, &block_with_context.block)?;
-
-                    let result = run_lua_script(
-                        script_path,
-                        file_system.as_ref(),
-                        &file_path,
-                        block_with_context,
-                        content,
-                        &affected_blocks,
-                    )
-                    .await;
-
-                    match result.context(format!(
-                        "check-lua script error in {}:{} at line {}",
-                        file_path.display(),
-                        block_with_context.block.name_display(),
-                        block_with_context
-                            .block
-                            .start_tag_position_range
-                            .start()
-                            .line
-                    ))? {
-                        None => Ok(None),
-                        Some(msg) => {
-                            let violation = create_violation(
-                                &file_path,
-                                &block_with_context.block,
-                                script_path,
-                                &msg,
-                            )?;
-                            Ok(Some((file_path, violation)))
-                        }
-                    }
-                });
             }
         }
-        while let Some(task_result) = tasks.join_next().await {
-            match task_result.context("check-lua task failed")? {
-                Ok(None) => continue,
-                Ok(Some((file_path, violation))) => {
-                    violations
-                        .entry(file_path)
-                        .or_insert_with(Vec::new)
-                        .push(violation);
+        jobs.sort();
+        let workers = std::thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(jobs.len());
+        let jobs = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+
+        let runtime = tokio::runtime::Handle::current();
+        let mut tasks = JoinSet::new();
+        for _ in 0..workers {
+            let context = Arc::clone(&context);
+            let scripts = Arc::clone(&self.scripts);
+            let limits = self.limits;
+            let jobs = Arc::clone(&jobs);
+            let runtime = runtime.clone();
+            // The scripts block while they run, so the workers get threads of their own instead of
+            // starving the asynchronous validators of the runtime.
+            tasks.spawn_blocking(move || {
+                let mut results = Vec::new();
+                loop {
+                    let job = jobs.lock().expect("no active locks").next();
+                    let Some((job_idx, (file_path, block_idx))) = job else {
+                        break;
+                    };
+                    let result = runtime.block_on(check_block(
+                        &context, &scripts, limits, &file_path, block_idx,
+                    ));
+                    results.push((job_idx, file_path, result));
                 }
-                Err(e) => return Err(e),
+                results
+            });
+        }
+        let mut results = Vec::new();
+        while let Some(task_result) = tasks.join_next().await {
+            results.extend(task_result.context("check-lua task failed")?);
+        }
+        results.sort_by_key(|(job_idx, _, _)| *job_idx);
+
+        let mut violations = HashMap::new();
+        for (_, file_path, result) in results {
+            let block_violations = result?;
+            if !block_violations.is_empty() {
+                violations
+                    .entry(file_path)
+                    .or_insert_with(Vec::new)
+                    .extend(block_violations);
             }
         }
         Ok(violations)
     }
 }
 
-async fn run_lua_script<Fs: FileSystem>(
-    script_path: &str,
-    file_system: &Fs,
+/// Runs the script of the block at `block_idx` of the file at `file_path` and returns the
+/// violations it reports.
+async fn check_block<Fs: FileSystem>(
+    context: &ValidationContext,
+    scripts: &ScriptCache<Fs>,
+    limits: LuaLimits,
     file_path: &Path,
-    block_with_context: &BlockWithContext,
-    content: &str,
-    affected_blocks: &[AffectedBlock],
-) -> anyhow::Result<Option<String>> {
-    let lua = lua_from_env();
+    block_idx: usize,
+) -> anyhow::Result<Vec<Violation>> {
+    let file_blocks = &context.blocks[file_path];
+    let block_with_context = &file_blocks.blocks_with_context[block_idx];
+    let block = &block_with_context.block;
+    let script_path = &block.attributes["check-lua"];
+    let limits = limits.for_block(file_path, block)?;
+    let (content, content_offset) = block_content(block_with_context, &file_blocks.file_content)?;
+    let affected_blocks = validators::resolve_affected_blocks(context, file_path, block)?;
+
+    let result = run_lua_script(
+        script_path,
+        scripts,
+        limits,
+        context.language_parser(file_path).cloned(),
+        file_path,
+        block_with_context,
+        content,
+        block.content(&file_blocks.file_content),
+        &affected_blocks,
+    )
+    .await
+    .map_err(|e| limits.describe(e));
+
+    match result.context(format!(
+        "check-lua script error in {}:{} at line {}",
+        file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line
+    ))? {
+        None => Ok(Vec::new()),
+        Some(LuaProblem::Invalid(lua_violations)) => {
+            let content_start = position_at(&file_blocks.file_content, content_offset);
+            lua_violations
+                .into_iter()
+                .map(|lua_violation| {
+                    create_violation(file_path, block, script_path, lua_violation, &content_start)
+                })
+                .collect()
+        }
+        Some(LuaProblem::OutOfDate(diff)) => Ok(vec![create_out_of_date_violation(
+            file_path,
+            block,
+            script_path,
+            diff,
+        )?]),
+    }
+}
+
+/// The bytecode of a compiled script, or the message of the error compiling it (kept to report it
+/// for every block using the script).
+type CompiledScript = Result<Arc<[u8]>, String>;
+
+/// The Lua scripts of a run compiled to bytecode, keyed by their path.
+///
+/// Every block still runs its script in a fresh Lua state, so no globals leak between blocks,
+/// but each script is read and compiled only once.
+struct ScriptCache<Fs: FileSystem> {
+    file_system: Arc<Fs>,
+    bytecodes: Mutex<HashMap<String, CompiledScript>>,
+}
+
+impl<Fs: FileSystem> ScriptCache<Fs> {
+    fn new(file_system: Arc<Fs>) -> Self {
+        Self {
+            file_system,
+            bytecodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the bytecode of the script at `script_path`, compiling it on first use.
+    fn bytecode(&self, script_path: &str) -> anyhow::Result<Arc<[u8]>> {
+        self.bytecodes
+            .lock()
+            .expect("no active locks")
+            .entry(script_path.to_string())
+            .or_insert_with(|| {
+                compile_script(script_path, self.file_system.as_ref()).map_err(|e| format!("{e:#}"))
+            })
+            .clone()
+            .map_err(|e| anyhow!(e))
+    }
+}
 
+/// Reads the Lua script at `script_path` and compiles it to bytecode.
+fn compile_script<Fs: FileSystem>(
+    script_path: &str,
+    file_system: &Fs,
+) -> anyhow::Result<Arc<[u8]>> {
     // `FileSystemImpl` canonicalizes the path and confines it to the repository root, so the
     // bespoke `resolve_script_path` security check that used to live here now lives in one place.
     let script_content = file_system
         .read_to_string(Path::new(script_path))
         .with_context(|| format!("failed to read Lua script: {script_path}"))?;
+    let lua = Lua::new();
+    let function = lua
+        .load(&script_content)
+        .set_name(format!("@{script_path}"))
+        .into_function()
+        .with_context(|| format!("failed to compile Lua script: {script_path}"))?;
+    Ok(function.dump(false).into())
+}
 
-    lua.load(&script_content)
-        .exec_async()
-        .await
+/// What is wrong with a block according to its Lua script.
+enum LuaProblem {
+    /// The violations returned by `validate()`.
+    Invalid(Vec<LuaViolation>),
+    /// The unified diff between the content returned by `generate()` and the block's content.
+    OutOfDate(String),
+}
+
+/// A violation returned by `validate()`, either as a message or as a table.
+struct LuaViolation {
+    message: String,
+    /// The range within the content passed to `validate()` (1-based, the end is inclusive), or
+    /// `None` to report the block's start tag.
+    range: Option<RangeInclusive<Position>>,
+    /// The severity overriding the block's one.
+    severity: Option<BlockSeverity>,
+    data: Option<serde_json::Value>,
+}
+
+impl LuaViolation {
+    fn from_message(message: String) -> Self {
+        Self {
+            message,
+            range: None,
+            severity: None,
+            data: None,
+        }
+    }
+
+    /// Reads a `{ message, line, column, end_line, end_column, severity, data }` table whose
+    /// positions refer to `content`.
+    ///
+    /// Without `line` the block's start tag is reported. `column` defaults to 1, `end_line` to
+    /// `line` and `end_column` to the end of `end_line`.
+    fn from_table(lua: &Lua, table: &mlua::Table, content: &str) -> anyhow::Result<Self> {
+        let message: String = table
+            .get::<Option<String>>("message")?
+            .context("violation table must have a string 'message'")?;
+        let range = match table.get::<Option<usize>>("line")? {
+            None => None,
+            Some(line) => {
+                let lines: Vec<&str> = content.split('\n').collect();
+                let end_line = table.get::<Option<usize>>("end_line")?.unwrap_or(line);
+                if line == 0 || end_line < line || end_line > lines.len() {
+                    anyhow::bail!(
+                        "violation lines {line}-{end_line} are out of range: the content has {} lines",
+                        lines.len()
+                    );
+                }
+                let column = table.get::<Option<usize>>("column")?.unwrap_or(1);
+                let end_column = table
+                    .get::<Option<usize>>("end_column")?
+                    .unwrap_or_else(|| lines[end_line - 1].len().max(1));
+                Some(
+                    Position::new(line, column.max(1))..=Position::new(end_line, end_column.max(1)),
+                )
+            }
+        };
+        let severity = table
+            .get::<Option<String>>("severity")?
+            .map(|severity| {
+                BlockSeverity::from_str(&severity)
+                    .with_context(|| format!("invalid violation severity \"{severity}\""))
+            })
+            .transpose()?;
+        let data = match table.get::<mlua::Value>("data")? {
+            mlua::Value::Nil => None,
+            data => Some(
+                lua.from_value(data)
+                    .context("violation data must be convertible to JSON")?,
+            ),
+        };
+        Ok(Self {
+            message,
+            range,
+            severity,
+            data,
+        })
+    }
+}
+
+/// Loads the Lua script at `script_path`, which defines the `validate`, `fix` and `generate`
+/// functions (all optional), into a fresh Lua state with the `limits` and the `blockwatch` module
+/// parsing with `language_parser`.
+fn load_script<Fs: FileSystem>(
+    script_path: &str,
+    scripts: &ScriptCache<Fs>,
+    limits: LuaLimits,
+    language_parser: Option<LanguageParser>,
+) -> anyhow::Result<Lua> {
+    let bytecode = scripts.bytecode(script_path)?;
+    let lua = lua_from_env();
+    limits.apply(&lua)?;
+    blockwatch_module::register(&lua, language_parser)
+        .context("failed to register the blockwatch module")?;
+    lua.load(&bytecode[..])
+        .set_mode(ChunkMode::Binary)
+        .exec()
         .with_context(|| format!("failed to execute Lua script: {script_path}"))?;
+    Ok(lua)
+}
+
+/// Returns the global function `name` of the script (if defined).
+fn script_function(lua: &Lua, name: &str) -> anyhow::Result<Option<mlua::Function>> {
+    lua.globals()
+        .get(name)
+        .with_context(|| format!("global '{name}' of the Lua script must be a function"))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_lua_script<Fs: FileSystem>(
+    script_path: &str,
+    scripts: &ScriptCache<Fs>,
+    limits: LuaLimits,
+    language_parser: Option<LanguageParser>,
+    file_path: &Path,
+    block_with_context: &BlockWithContext,
+    content: &str,
+    full_content: &str,
+    affected_blocks: &[AffectedBlock],
+) -> anyhow::Result<Option<LuaProblem>> {
+    let lua = load_script(script_path, scripts, limits, language_parser)?;
+    let validate_fn = script_function(&lua, "validate")?;
+    let generate_fn = script_function(&lua, "generate")?;
+    if validate_fn.is_none() && generate_fn.is_none() {
+        anyhow::bail!("Lua script must define a global 'validate' or 'generate' function");
+    }
+    let ctx_table = create_ctx_table(&lua, file_path, &block_with_context.block, affected_blocks)?;
+
+    if let Some(validate_fn) = validate_fn {
+        let result: mlua::Value = validate_fn
+            .call_async((ctx_table.clone(), content.to_string()))
+            .await
+            .with_context(|| format!("failed to call validate() in {script_path}"))?;
+
+        match result {
+            mlua::Value::Nil => {}
+            mlua::Value::String(s) => {
+                return Ok(Some(LuaProblem::Invalid(vec![LuaViolation::from_message(
+                    s.to_str()?.to_string(),
+                )])));
+            }
+            mlua::Value::Table(list) => {
+                let violations = list
+                    .sequence_values::<mlua::Table>()
+                    .enumerate()
+                    .map(|(i, table)| {
+                        LuaViolation::from_table(&lua, &table?, content).with_context(|| {
+                            format!("invalid violation {} returned by validate()", i + 1)
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                if !violations.is_empty() {
+                    return Ok(Some(LuaProblem::Invalid(violations)));
+                }
+            }
+            other => {
+                return Err(anyhow!(
+                    "validate() must return nil, a string or a list of violation tables, got: {:?}",
+                    other.type_name()
+                ));
+            }
+        }
+    }
+
+    if let Some(generate_fn) = generate_fn {
+        let generated = call_generate(&generate_fn, ctx_table, script_path)?;
+        let actual = validators::unfenced_lines(full_content);
+        if actual != generated {
+            let generated: String = generated.iter().map(|line| format!("{line}\n")).collect();
+            let actual: String = actual.iter().map(|line| format!("{line}\n")).collect();
+            let diff = similar::TextDiff::from_lines(&generated, &actual)
+                .unified_diff()
+                .header(
+                    script_path,
+                    &format!(
+                        "{}:{}",
+                        file_path.display(),
+                        block_with_context.block.name_display()
+                    ),
+                )
+                .to_string();
+            return Ok(Some(LuaProblem::OutOfDate(diff)));
+        }
+    }
+    Ok(None)
+}
 
-    let validate_fn: mlua::Function = lua
-        .globals()
-        .get("validate")
-        .context("Lua script must define a global 'validate' function")?;
+/// Calls `generate(ctx)` and returns the lines of the generated content without the blank lines
+/// around them.
+fn call_generate(
+    generate_fn: &mlua::Function,
+    ctx_table: mlua::Table,
+    script_path: &str,
+) -> anyhow::Result<Vec<String>> {
+    let result: mlua::Value = generate_fn
+        .call(ctx_table)
+        .with_context(|| format!("failed to call generate() in {script_path}"))?;
+    match result {
+        mlua::Value::String(s) => Ok(validators::trimmed_lines(&s.to_str()?)
+            .into_iter()
+            .map(str::to_string)
+            .collect()),
+        other => Err(anyhow!(
+            "generate() must return a string, got: {:?}",
+            other.type_name()
+        )),
+    }
+}
 
+/// Builds the `ctx` table passed to the functions of the script.
+fn create_ctx_table(
+    lua: &Lua,
+    file_path: &Path,
+    block: &Block,
+    affected_blocks: &[AffectedBlock],
+) -> anyhow::Result<mlua::Table> {
     let ctx_table = lua.create_table().context("failed to create ctx table")?;
     ctx_table
         .set("file", file_path.to_string_lossy().as_ref())
         .context("failed to set ctx.file")?;
     ctx_table
-        .set(
-            "line",
-            block_with_context
-                .block
-                .start_tag_position_range
-                .start()
-                .line,
-        )
+        .set("line", block.start_tag_position_range.start().line)
         .context("failed to set ctx.line")?;
 
     let attrs_table = lua.create_table().context("failed to create attrs table")?;
-    for (key, value) in &block_with_context.block.attributes {
+    for (key, value) in &block.attributes {
         attrs_table
             .set(key.as_str(), value.as_str())
             .with_context(|| format!("failed to set attr {key}"))?;
@@ -189,7 +621,7 @@ async fn run_lua_script<Fs: FileSystem>(
 
     // When the block carries an `affects` attribute, expose the affected blocks as
     // `ctx.affects = [{ file, name, content }, …]` so scripts can inspect them in sandboxed mode.
-    if block_with_context.block.attributes.contains_key("affects") {
+    if block.attributes.contains_key("affects") {
         let affects_table = lua
             .create_table()
             .context("failed to create affects table")?;
@@ -214,117 +646,220 @@ async fn run_lua_script<Fs: FileSystem>(
             .set("affects", affects_table)
             .context("failed to set ctx.affects")?;
     }
+    Ok(ctx_table)
+}
 
-    let result: mlua::Value = validate_fn
-        .call_async((ctx_table, content.to_string()))
-        .await
-        .with_context(|| format!("failed to call validate() in {script_path}"))?;
+impl<Fs: FileSystem + 'static> Fixer for CheckLuaValidator<Fs> {
+    /// Replaces the content with the one returned by `generate(ctx)` and then the part of it
+    /// passed to `validate()` with the one returned by `fix(ctx, content)`.
+    fn fix(
+        &self,
+        context: &ValidationContext,
+        file_path: &Path,
+        block: &Block,
+        content: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let Some(script_path) = script_path(file_path, block)? else {
+            return Ok(None);
+        };
+        let error_context = || {
+            format!(
+                "check-lua script error in {}:{} at line {}",
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line
+            )
+        };
+        let limits = self.limits.for_block(file_path, block)?;
+        let lua = load_script(
+            script_path,
+            &self.scripts,
+            limits,
+            context.language_parser(file_path).cloned(),
+        )
+        .map_err(|e| limits.describe(e))
+        .with_context(error_context)?;
+        let generate_fn = script_function(&lua, "generate").with_context(error_context)?;
+        let fix_fn = script_function(&lua, "fix").with_context(error_context)?;
+        if generate_fn.is_none() && fix_fn.is_none() {
+            return Ok(None);
+        }
+        let affected_blocks = validators::resolve_affected_blocks(context, file_path, block)?;
+        let ctx_table = create_ctx_table(&lua, file_path, block, &affected_blocks)?;
 
-    match result {
-        mlua::Value::Nil => Ok(None),
-        mlua::Value::String(s) => Ok(Some(s.to_str()?.to_string())),
-        other => Err(anyhow!(
-            "validate() must return nil or a string, got: {:?}",
-            other.type_name()
-        )),
+        let mut fixed = content.to_string();
+        if let Some(generate_fn) = generate_fn {
+            let generated = call_generate(&generate_fn, ctx_table.clone(), script_path)
+                .map_err(|e| limits.describe(e))
+                .with_context(error_context)?;
+            if validators::unfenced_lines(&fixed) != generated {
+                fixed = validators::replace_unfenced_lines(&fixed, &generated);
+            }
+        }
+        if let Some(fix_fn) = fix_fn
+            && let Some(range) = lua_content_range(block, &fixed)?
+        {
+            let result: mlua::Value = fix_fn
+                .call((ctx_table, fixed[range.clone()].to_string()))
+                .with_context(|| format!("failed to call fix() in {script_path}"))
+                .map_err(|e| limits.describe(e))
+                .with_context(error_context)?;
+            match result {
+                mlua::Value::Nil => {}
+                mlua::Value::String(s) => fixed.replace_range(range, &s.to_str()?),
+                other => {
+                    return Err(anyhow!(
+                        "fix() must return nil or a string, got: {:?}",
+                        other.type_name()
+                    ))
+                    .with_context(error_context);
+                }
+            }
+        }
+        Ok((fixed != content).then_some(fixed))
+    }
+}
+
+/// Returns the `check-lua` script path of the `block` defined in `file_path` (if any).
+fn script_path<'a>(file_path: &Path, block: &'a Block) -> anyhow::Result<Option<&'a str>> {
+    let Some(script_path) = block.attributes.get("check-lua") else {
+        return Ok(None);
+    };
+    if script_path.trim().is_empty() {
+        return Err(anyhow!(
+            "check-lua requires a non-empty script path in {}:{} at line {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line
+        ));
     }
+    Ok(Some(script_path))
 }
 
+/// Creates the violation of a `lua_violation` whose range is relative to the content passed to
+/// `validate()`, which starts at `content_start` in the file.
 fn create_violation(
     file_path: &Path,
     block: &Block,
     script_path: &str,
-    error_message: &str,
+    lua_violation: LuaViolation,
+    content_start: &Position,
 ) -> anyhow::Result<Violation> {
     let details = serde_json::to_value(CheckLuaViolation {
         script: script_path,
-        lua_error: error_message,
+        lua_error: &lua_violation.message,
+        data: lua_violation.data,
     })
     .context("failed to serialize CheckLuaDetails")?;
     let message = format!(
-        "Block {}:{} defined at line {} failed Lua check: {error_message}",
+        "Block {}:{} defined at line {} failed Lua check: {}",
         file_path.display(),
         block.name_display(),
         block.start_tag_position_range.start().line,
+        lua_violation.message,
     );
-    Ok(Violation::new(
-        ViolationRange::new(
+    let range = match lua_violation.range {
+        Some(range) => ViolationRange::new(
+            file_position(content_start, range.start()),
+            file_position(content_start, range.end()),
+        ),
+        None => ViolationRange::new(
             block.start_tag_position_range.start().clone(),
             block.start_tag_position_range.end().clone(),
         ),
+    };
+    Ok(Violation::new(
+        range,
         "check-lua".to_string(),
         message,
-        block.severity()?,
+        lua_violation
+            .severity
+            .map_or_else(|| block.severity(), Ok)?,
         Some(details),
     ))
 }
 
-/// A block referenced by the validated block's `affects` attribute, exposed to Lua scripts.
-struct AffectedBlock {
-    file: PathBuf,
-    name: String,
-    content: String,
+/// Returns the position of the byte `offset` in `source`.
+fn position_at(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(before.matches('\n').count() + 1, offset - line_start + 1)
 }
 
-/// Resolves the blocks referenced by the `affects` attribute of `block` to their `(file, name,
-/// content)` so they can be exposed to the Lua script.
-///
-/// References to blocks that don't exist in the validation context are skipped (the `affects`
-/// validator is responsible for reporting those). The content is trimmed to mirror how the
-/// validated block's own content is presented.
-fn resolve_affected_blocks(
-    context: &ValidationContext,
-    current_file_path: &Path,
-    block: &Block,
-) -> anyhow::Result<Vec<AffectedBlock>> {
-    let mut result = Vec::new();
-    let Some(affects) = block.attributes.get("affects") else {
-        return Ok(result);
-    };
-    for (file, name) in parse_affects_attribute(affects)? {
-        let file = file.unwrap_or_else(|| current_file_path.to_path_buf());
-        let Some(file_blocks) = context.blocks.get(&file) else {
-            continue;
-        };
-        for block_with_context in &file_blocks.blocks_with_context {
-            if block_with_context.block.name() == Some(name.as_str()) {
-                result.push(AffectedBlock {
-                    file: file.clone(),
-                    name: name.clone(),
-                    content: block_with_context
-                        .block
-                        .content(&file_blocks.file_content)
-                        .trim()
-                        .to_string(),
-                });
-            }
-        }
+/// Converts a `position` relative to a content starting at `content_start` in the file to a
+/// position in the file.
+fn file_position(content_start: &Position, position: &Position) -> Position {
+    if position.line == 1 {
+        Position::new(
+            content_start.line,
+            content_start.character + position.character - 1,
+        )
+    } else {
+        Position::new(content_start.line + position.line - 1, position.character)
     }
-    Ok(result)
 }
 
+fn create_out_of_date_violation(
+    file_path: &Path,
+    block: &Block,
+    script_path: &str,
+    diff: String,
+) -> anyhow::Result<Violation> {
+    let details = serde_json::to_value(CheckLuaGenerateViolation {
+        script: script_path,
+        diff,
+    })
+    .context("failed to serialize CheckLuaGenerateViolation")?;
+    let message = format!(
+        "Block {}:{} defined at line {} differs from the content generated by {script_path}",
+        file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+    );
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "check-lua".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+/// Returns the part of the block's content passed to `validate()` and its byte offset in the file.
 fn block_content<'c>(
     block_with_context: &BlockWithContext,
     file_content: &'c str,
-) -> anyhow::Result<&'c str> {
-    let content = if let Some(pattern) =
-        block_with_context.block.attributes.get("check-lua-pattern")
-    {
-        let re = regex::Regex::new(pattern).context("check-lua-pattern is not a valid regex")?;
-        if let Some(c) = re.captures(block_with_context.block.content(file_content)) {
-            // If named group "value" exists use it, otherwise use the whole match
-            if let Some(m) = c.name("value") {
-                m.as_str()
-            } else {
-                c.get(0).map_or("", |m| m.as_str())
-            }
-        } else {
-            ""
-        }
-    } else {
-        block_with_context.block.content(file_content).trim()
+) -> anyhow::Result<(&'c str, usize)> {
+    let block = &block_with_context.block;
+    let content = block.content(file_content);
+    Ok(match lua_content_range(block, content)? {
+        Some(range) => (
+            &content[range.clone()],
+            block.content_bytes_range.start + range.start,
+        ),
+        None => ("", block.content_bytes_range.start),
+    })
+}
+
+/// Returns the range of the part of the block's `content` the script works with: the match of
+/// `check-lua-pattern` (its named group `value` if present) or the trimmed content.
+///
+/// Returns `None` if the pattern doesn't match.
+fn lua_content_range(block: &Block, content: &str) -> anyhow::Result<Option<Range<usize>>> {
+    let Some(pattern) = block.attributes.get("check-lua-pattern") else {
+        let trimmed = content.trim();
+        let start = content.len() - content.trim_start().len();
+        return Ok(Some(start..start + trimmed.len()));
     };
-    Ok(content)
+    let re = regex::Regex::new(pattern).context("check-lua-pattern is not a valid regex")?;
+    // If named group "value" exists use it, otherwise use the whole match
+    Ok(re
+        .captures(content)
+        .and_then(|c| c.name("value").or_else(|| c.get(0)))
+        .map(|m| m.range()))
 }
 
 pub(crate) struct CheckLuaValidatorDetector;
@@ -359,6 +894,14 @@ impl<Fs: FileSystem + 'static> ValidatorDetector<Fs> for CheckLuaValidatorDetect
 struct CheckLuaViolation<'a> {
     script: &'a str,
     lua_error: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct CheckLuaGenerateViolation<'a> {
+    script: &'a str,
+    diff: String,
 }
 
 #[cfg(test)]
@@ -366,7 +909,7 @@ mod tests {
     use super::*;
     use crate::test_utils::{
         FakeFileSystem, merge_validation_contexts, validation_context,
-        validation_context_with_changes,
+        validation_context_from_changes, validation_context_with_changes,
     };
     use serde_json::json;
 
@@ -732,4 +1275,723 @@ some content
         assert!(violations.is_empty());
         Ok(())
     }
+
+    const COLORS_ENUM: &str = r#"enum Color {
+    // <block name="colors">
+    Red,
+    Green,
+    // </block>
+}
+"#;
+
+    /// Renders the variants of the affected enum block as a Markdown list.
+    const GENERATE_LIST: &str = r#"
+function generate(ctx)
+    local lines = {}
+    for variant in ctx.affects[1].content:gmatch("(%w+),") do
+        table.insert(lines, "- " .. variant)
+    end
+    return table.concat(lines, "\n")
+end
+"#;
+
+    const COLORS_LIST: &str = r#"<!-- <block check-lua="list.lua" affects="colors.rs:colors"> -->
+- Red
+- Green
+<!-- </block> -->
+"#;
+
+    #[tokio::test]
+    async fn generated_content_returns_no_violations() -> anyhow::Result<()> {
+        let context = merge_validation_contexts(vec![
+            validation_context("colors.rs", COLORS_ENUM),
+            validation_context("colors.md", COLORS_LIST),
+        ]);
+
+        let violations = validator(&[("list.lua", GENERATE_LIST)])
+            .validate(context)
+            .await?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outdated_generated_content_returns_violation() -> anyhow::Result<()> {
+        let context = merge_validation_contexts(vec![
+            validation_context("colors.rs", &COLORS_ENUM.replace("Green", "Blue")),
+            validation_context("colors.md", COLORS_LIST),
+        ]);
+
+        let violations = validator(&[("list.lua", GENERATE_LIST)])
+            .validate(context)
+            .await?;
+
+        let violation = &violations[Path::new("colors.md")][0];
+        assert_eq!(
+            violation.message(),
+            "Block colors.md:(unnamed) defined at line 1 differs from the content generated by list.lua"
+        );
+        assert_eq!(
+            violation.data(),
+            Some(&json!({
+                "script": "list.lua",
+                "diff": "--- list.lua\n+++ colors.md:(unnamed)\n@@ -1,2 +1,2 @@\n - Red\n-- Blue\n+- Green\n"
+            }))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ctx_affects_includes_unmodified_blocks_of_the_tree() -> anyhow::Result<()> {
+        // Only the list is modified: the enum is not in the context but is found in the tree.
+        let context = validation_context_from_changes(
+            &[
+                ("colors.rs", COLORS_ENUM),
+                ("colors.md", &COLORS_LIST.replace("Green", "Blue")),
+            ],
+            &[("colors.rs", COLORS_ENUM), ("colors.md", COLORS_LIST)],
+        );
+
+        let violations = validator(&[("list.lua", GENERATE_LIST)])
+            .validate(context)
+            .await?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn script_without_validate_and_generate_returns_error() {
+        let context = validation_context(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+        );
+
+        let err = validator(&[("check.lua", "function fix(ctx, content) return nil end")])
+            .validate(context)
+            .await
+            .unwrap_err();
+
+        assert!(
+            format!("{err:#}")
+                .contains("Lua script must define a global 'validate' or 'generate' function"),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    /// Runs the fixer of the `validator` on the first block of the `file_path` of the `context`.
+    fn fix_first_block(
+        validator: &CheckLuaValidator<FakeFileSystem>,
+        context: &ValidationContext,
+        file_path: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let file_blocks = &context.blocks[Path::new(file_path)];
+        let block = &file_blocks.blocks_with_context[0].block;
+        validator.fix(
+            context,
+            Path::new(file_path),
+            block,
+            block.content(&file_blocks.file_content),
+        )
+    }
+
+    #[test]
+    fn fix_replaces_content_with_generated_content() -> anyhow::Result<()> {
+        let context = merge_validation_contexts(vec![
+            validation_context("colors.rs", &COLORS_ENUM.replace("Green", "Blue")),
+            validation_context("colors.md", COLORS_LIST),
+        ]);
+
+        let fixed = fix_first_block(
+            &validator(&[("list.lua", GENERATE_LIST)]),
+            &context,
+            "colors.md",
+        )?;
+
+        assert_eq!(fixed.as_deref(), Some("\n- Red\n- Blue\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn fix_replaces_pattern_value_with_fixed_value() -> anyhow::Result<()> {
+        let context = validation_context(
+            "example.py",
+            "# <block check-lua=\"upper.lua\" check-lua-pattern=\"name = '(?P<value>\\w+)'\">\nname = 'blue'\n# </block>\n",
+        );
+
+        let fixed = fix_first_block(
+            &validator(&[(
+                "upper.lua",
+                r#"
+function validate(ctx, content)
+    if content ~= content:upper() then
+        return "must be upper case"
+    end
+end
+
+function fix(ctx, content)
+    return content:upper()
+end
+"#,
+            )]),
+            &context,
+            "example.py",
+        )?;
+
+        assert_eq!(fixed.as_deref(), Some("\nname = 'BLUE'\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn fix_without_fix_and_generate_returns_none() -> anyhow::Result<()> {
+        let context = validation_context(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+        );
+
+        let fixed = fix_first_block(
+            &validator(&[(
+                "check.lua",
+                "function validate(ctx, content) return 'bad' end",
+            )]),
+            &context,
+            "example.py",
+        )?;
+
+        assert_eq!(fixed, None);
+        Ok(())
+    }
+
+    /// Validates the first block of `file_path` with the `validate` function of `script` and
+    /// returns the message it reported.
+    async fn validation_message(
+        file_path: &str,
+        contents: &str,
+        script: &str,
+    ) -> anyhow::Result<String> {
+        let violations = validator(&[("check.lua", script)])
+            .validate(validation_context(file_path, contents))
+            .await?;
+        Ok(violations[Path::new(file_path)][0].message().to_string())
+    }
+
+    #[tokio::test]
+    async fn blockwatch_regex_matches_block_content() -> anyhow::Result<()> {
+        let message = validation_message(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\nv1.2 v3.4\n# </block>\n",
+            r#"
+function validate(ctx, content)
+    local first = blockwatch.regex.match([[v(?<major>\d+)\.(\d+)]], content)
+    local all = blockwatch.regex.match_all([[v(\d+)\.(\d+)]], content)
+    return table.concat({
+        first[0], first.major, first[2], all[2][1],
+        tostring(blockwatch.regex.is_match([[^\d]], content)),
+        blockwatch.regex.replace_all([[v(\d+)]], content, "V$1"),
+        tostring(blockwatch.regex.match("x", content)),
+    }, " ")
+end
+"#,
+        )
+        .await?;
+
+        assert_eq!(
+            message,
+            "Block example.py:(unnamed) defined at line 1 failed Lua check: v1.2 1 2 3 false V1.2 V3.4 nil"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blockwatch_invalid_regex_returns_error() {
+        let err = validation_message(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+            "function validate(ctx, content) return blockwatch.regex.is_match('(', content) end",
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            format!("{err:#}").contains("invalid regex \"(\""),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn blockwatch_decodes_json_yaml_and_toml() -> anyhow::Result<()> {
+        let message = validation_message(
+            "example.md",
+            "<!-- <block check-lua=\"check.lua\"> -->\ntext\n<!-- </block> -->\n",
+            r#"
+function validate(ctx, content)
+    local json = blockwatch.json.decode('{"names": ["a", "b"], "empty": null}')
+    local yaml = blockwatch.yaml.decode("count: 2\nnested:\n  flag: true\n")
+    local toml = blockwatch.toml.decode('day = 2024-01-02\n[server]\nport = 8080\n')
+    return table.concat({
+        json.names[2], tostring(json.empty), yaml.count, tostring(yaml.nested.flag),
+        toml.day, toml.server.port, blockwatch.json.encode({ b = 1, a = "x" }),
+    }, " ")
+end
+"#,
+        )
+        .await?;
+
+        assert_eq!(
+            message,
+            r#"Block example.md:(unnamed) defined at line 1 failed Lua check: b nil 2 true 2024-01-02 8080 {"a":"x","b":1}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blockwatch_invalid_json_returns_error() {
+        let err = validation_message(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+            "function validate(ctx, content) return blockwatch.json.decode(content) end",
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            format!("{err:#}").contains("invalid JSON"),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn blockwatch_syntax_tree_parses_with_the_language_of_the_file() -> anyhow::Result<()> {
+        let message = validation_message(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ndef first():\n    pass\n\ndef second():\n    pass\n# </block>\n",
+            r#"
+function validate(ctx, content)
+    local tree = blockwatch.syntax_tree(content)
+    local names = {}
+    for _, node in ipairs(tree.children) do
+        if node.type == "function_definition" then
+            for _, child in ipairs(node.children) do
+                if child.type == "identifier" then
+                    table.insert(names, child.text .. "@" .. child.start_line .. ":" .. child.start_column)
+                end
+            end
+        end
+    end
+    return tree.type .. " " .. table.concat(names, " ")
+end
+"#,
+        )
+        .await?;
+
+        assert_eq!(
+            message,
+            "Block example.py:(unnamed) defined at line 1 failed Lua check: module first@1:5 second@4:5"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blockwatch_syntax_tree_of_deeply_nested_input_does_not_overflow_the_stack()
+    -> anyhow::Result<()> {
+        let depth = 5_000;
+        let message = validation_message(
+            "example.py",
+            &format!(
+                "# <block check-lua=\"check.lua\">\nx = {}{}\n# </block>\n",
+                "[".repeat(depth),
+                "]".repeat(depth)
+            ),
+            r#"
+function validate(ctx, content)
+    local assignment = blockwatch.syntax_tree(content).children[1].children[1]
+    local node = assignment.children[3]
+    local depth = 0
+    while node.type == "list" do
+        node = node.children[2]
+        depth = depth + 1
+    end
+    return depth .. " nested lists"
+end
+"#,
+        )
+        .await?;
+
+        assert_eq!(
+            message,
+            format!(
+                "Block example.py:(unnamed) defined at line 1 failed Lua check: {depth} nested lists"
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blockwatch_syntax_tree_is_nil_for_unsupported_files() -> anyhow::Result<()> {
+        let mut context = validation_context(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+        );
+        Arc::get_mut(&mut context)
+            .expect("the context is not shared")
+            .parsers
+            .clear();
+
+        let violations = validator(&[(
+            "check.lua",
+            "function validate(ctx, content) return tostring(blockwatch.syntax_tree(content)) end",
+        )])
+        .validate(context)
+        .await?;
+
+        assert_eq!(
+            violations[Path::new("example.py")][0].message(),
+            "Block example.py:(unnamed) defined at line 1 failed Lua check: nil"
+        );
+        Ok(())
+    }
+
+    const SETTINGS: &str = r#"# <block check-lua="check.lua">
+a = 1
+b = 2
+c = x
+# </block>
+"#;
+
+    #[tokio::test]
+    async fn violation_tables_are_reported_at_their_lines() -> anyhow::Result<()> {
+        let context = validation_context("example.py", SETTINGS);
+
+        let violations = validator(&[(
+            "check.lua",
+            r#"
+function validate(ctx, content)
+    local violations = {}
+    local line = 0
+    for text in (content .. "\n"):gmatch("(.-)\n") do
+        line = line + 1
+        local key, value = text:match("^(%w+) = (.*)$")
+        if value:match("%a") then
+            table.insert(violations, {
+                message = key .. " is not a number",
+                line = line,
+                column = 5,
+                severity = "warning",
+                data = { key = key },
+            })
+        elseif key == "a" then
+            table.insert(violations, { message = "a is deprecated", line = line })
+        end
+    end
+    return violations
+end
+"#,
+        )])
+        .validate(context)
+        .await?;
+
+        let violations = &violations[Path::new("example.py")];
+        assert_eq!(violations.len(), 2);
+        let deprecated = &violations[0];
+        assert_eq!(
+            deprecated.message(),
+            "Block example.py:(unnamed) defined at line 1 failed Lua check: a is deprecated"
+        );
+        assert_eq!(
+            deprecated.range(),
+            &ViolationRange::new(Position::new(2, 1), Position::new(2, 5))
+        );
+        assert_eq!(deprecated.severity(), BlockSeverity::Error);
+        assert_eq!(
+            deprecated.data(),
+            Some(&json!({"script": "check.lua", "lua_error": "a is deprecated"}))
+        );
+        let not_a_number = &violations[1];
+        assert_eq!(
+            not_a_number.range(),
+            &ViolationRange::new(Position::new(4, 5), Position::new(4, 5))
+        );
+        assert_eq!(not_a_number.severity(), BlockSeverity::Warning);
+        assert_eq!(
+            not_a_number.data(),
+            Some(&json!({
+                "script": "check.lua",
+                "lua_error": "c is not a number",
+                "data": {"key": "c"}
+            }))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn violation_positions_are_relative_to_the_pattern_match() -> anyhow::Result<()> {
+        let context = validation_context(
+            "example.py",
+            "# <block check-lua=\"check.lua\" check-lua-pattern=\"version = (?P<value>.*)\">\nversion = 1.x\n# </block>\n",
+        );
+
+        let violations = validator(&[(
+            "check.lua",
+            r#"
+function validate(ctx, content)
+    return { { message = "invalid version", line = 1, column = 3, end_column = 3 } }
+end
+"#,
+        )])
+        .validate(context)
+        .await?;
+
+        assert_eq!(
+            violations[Path::new("example.py")][0].range(),
+            &ViolationRange::new(Position::new(2, 13), Position::new(2, 13))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn violation_table_without_line_is_reported_at_the_start_tag() -> anyhow::Result<()> {
+        let context = validation_context("example.py", SETTINGS);
+
+        let violations = validator(&[(
+            "check.lua",
+            "function validate(ctx, content) return { { message = 'bad', severity = 'info' } } end",
+        )])
+        .validate(context)
+        .await?;
+
+        let violation = &violations[Path::new("example.py")][0];
+        assert_eq!(
+            violation.range(),
+            &ViolationRange::new(Position::new(1, 3), Position::new(1, 31))
+        );
+        assert_eq!(violation.severity(), BlockSeverity::Info);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_violation_list_returns_no_violations() -> anyhow::Result<()> {
+        let context = validation_context("example.py", SETTINGS);
+
+        let violations =
+            validator(&[("check.lua", "function validate(ctx, content) return {} end")])
+                .validate(context)
+                .await?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn violation_table_out_of_the_content_returns_error() {
+        let context = validation_context("example.py", SETTINGS);
+
+        let err = validator(&[(
+            "check.lua",
+            "function validate(ctx, content) return { { message = 'bad', line = 4 } } end",
+        )])
+        .validate(context)
+        .await
+        .unwrap_err();
+
+        assert!(
+            format!("{err:#}").contains(
+                "invalid violation 1 returned by validate(): violation lines 4-4 are out of range: the content has 3 lines"
+            ),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn script_is_read_once_per_run() -> anyhow::Result<()> {
+        let validator = validator(&[(
+            "check.lua",
+            "function validate(ctx, content) return 'old' end",
+        )]);
+        let context = validation_context(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\na\n# </block>\n# <block check-lua=\"check.lua\">\nb\n# </block>\n",
+        );
+        let violations = validator.validate(Arc::clone(&context)).await?;
+        assert_eq!(violations[Path::new("example.py")].len(), 2);
+
+        validator.scripts.file_system.write(
+            Path::new("check.lua"),
+            "function validate(ctx, content) return 'new' end",
+        )?;
+        let violations = validator.validate(context).await?;
+
+        assert!(
+            violations[Path::new("example.py")]
+                .iter()
+                .all(|violation| violation.message().ends_with("failed Lua check: old"))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn globals_do_not_leak_between_blocks() -> anyhow::Result<()> {
+        let context = validation_context(
+            "example.py",
+            &"# <block check-lua=\"check.lua\">\ntext\n# </block>\n".repeat(3),
+        );
+
+        let violations = validator(&[(
+            "check.lua",
+            r#"
+calls = (calls or 0) + 1
+function validate(ctx, content)
+    seen = (seen or 0) + 1
+    if calls > 1 or seen > 1 then
+        return "state leaked from another block"
+    end
+end
+"#,
+        )])
+        .validate(context)
+        .await?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn violations_are_reported_in_the_order_of_the_blocks() -> anyhow::Result<()> {
+        let contents: String = (1..=50)
+            .map(|i| format!("# <block check-lua=\"check.lua\">\n{i}\n# </block>\n"))
+            .collect();
+        let context = validation_context("example.py", &contents);
+
+        let violations = validator(&[(
+            "check.lua",
+            "function validate(ctx, content) return content end",
+        )])
+        .validate(context)
+        .await?;
+
+        let messages: Vec<_> = violations[Path::new("example.py")]
+            .iter()
+            .map(|violation| violation.message().rsplit(' ').next().unwrap().to_string())
+            .collect();
+        let expected: Vec<_> = (1..=50).map(|i| i.to_string()).collect();
+        assert_eq!(messages, expected);
+        Ok(())
+    }
+
+    /// Validates the blocks of `contents` with `script` under `limits` and returns the error.
+    async fn limit_error(contents: &str, script: &str, limits: LuaLimits) -> String {
+        let mut validator = validator(&[("check.lua", script)]);
+        validator.limits = limits;
+        let err = validator
+            .validate(validation_context("example.py", contents))
+            .await
+            .unwrap_err();
+        format!("{err:#}")
+    }
+
+    const DEFAULT_LIMITS: LuaLimits = LuaLimits {
+        timeout: Duration::from_secs(30),
+        memory: 256 * MIB,
+        instructions: None,
+    };
+
+    #[tokio::test]
+    async fn script_exceeding_the_timeout_returns_error() {
+        let err = limit_error(
+            "# <block check-lua=\"check.lua\" check-lua-timeout=\"1\">\ntext\n# </block>\n",
+            r#"
+function validate(ctx, content)
+    while true do
+        pcall(function() while true do end end)
+    end
+end
+"#,
+            DEFAULT_LIMITS,
+        )
+        .await;
+
+        assert_eq!(
+            err,
+            "check-lua script error in example.py:(unnamed) at line 1: the script exceeded the timeout of 1s"
+        );
+    }
+
+    #[tokio::test]
+    async fn script_exceeding_the_instruction_limit_returns_error() {
+        let err = limit_error(
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+            "function validate(ctx, content) for i = 1, 1000000 do end end",
+            LuaLimits {
+                instructions: Some(10_000),
+                ..DEFAULT_LIMITS
+            },
+        )
+        .await;
+
+        assert_eq!(
+            err,
+            "check-lua script error in example.py:(unnamed) at line 1: the script exceeded the limit of 10000 instructions"
+        );
+    }
+
+    #[tokio::test]
+    async fn script_exceeding_the_memory_limit_returns_error() {
+        let err = limit_error(
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+            r#"
+function validate(ctx, content)
+    local parts = {}
+    for i = 1, 1000000 do
+        parts[i] = string.rep("x", 100) .. i
+    end
+end
+"#,
+            LuaLimits {
+                memory: MIB,
+                ..DEFAULT_LIMITS
+            },
+        )
+        .await;
+
+        assert_eq!(
+            err,
+            "check-lua script error in example.py:(unnamed) at line 1: the script exceeded the memory limit of 1 MiB"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_timeout_returns_error() {
+        let err = limit_error(
+            "# <block check-lua=\"check.lua\" check-lua-timeout=\"soon\">\ntext\n# </block>\n",
+            "function validate(ctx, content) return nil end",
+            DEFAULT_LIMITS,
+        )
+        .await;
+
+        assert_eq!(
+            err,
+            "check-lua-timeout expected a positive number of seconds, got \"soon\" in example.py:(unnamed) at line 1"
+        );
+    }
+
+    #[test]
+    fn fix_exceeding_a_limit_returns_error() {
+        let context = validation_context(
+            "example.py",
+            "# <block check-lua=\"check.lua\">\ntext\n# </block>\n",
+        );
+        let mut validator =
+            validator(&[("check.lua", "function generate(ctx) while true do end end")]);
+        validator.limits = LuaLimits {
+            instructions: Some(10_000),
+            ..DEFAULT_LIMITS
+        };
+
+        let err = fix_first_block(&validator, &context, "example.py").unwrap_err();
+
+        assert_eq!(
+            format!("{err:#}"),
+            "check-lua script error in example.py:(unnamed) at line 1: the script exceeded the limit of 10000 instructions"
+        );
+    }
 }
@@ -0,0 +1,228 @@
+//! The `blockwatch` module available to `check-lua` scripts in every Lua mode.
+//!
+//! It exposes helpers which the sandboxed standard library lacks, so that scripts don't need to
+//! re-implement parsing with Lua patterns. None of them accesses the file system or the OS.
+
+use crate::language_parsers::LanguageParser;
+use mlua::{DeserializeOptions, Lua, LuaSerdeExt, SerializeOptions, Table, Value};
+use regex::{Captures, Regex};
+
+/// Sets the global `blockwatch` table of `lua`.
+///
+/// `blockwatch.syntax_tree(text)` parses `text` with the `language_parser` of the block's file.
+pub(super) fn register(lua: &Lua, language_parser: Option<LanguageParser>) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set("regex", regex_table(lua)?)?;
+    module.set("json", json_table(lua)?)?;
+    module.set("yaml", yaml_table(lua)?)?;
+    module.set("toml", toml_table(lua)?)?;
+    module.set(
+        "syntax_tree",
+        lua.create_function(move |lua, text: String| {
+            let Some(language_parser) = &language_parser else {
+                return Ok(Value::Nil);
+            };
+            let tree = language_parser
+                .lock()
+                .expect("no active locks")
+                .syntax_tree(&text);
+            match tree {
+                Some(tree) => Ok(Value::Table(node_table(lua, tree.root_node(), &text)?)),
+                None => Ok(Value::Nil),
+            }
+        })?,
+    )?;
+    lua.globals().set("blockwatch", module)
+}
+
+/// `blockwatch.regex`: matching with the syntax of the Rust `regex` crate.
+fn regex_table(lua: &Lua) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set(
+        "is_match",
+        lua.create_function(|_, (pattern, text): (String, String)| {
+            Ok(compile(&pattern)?.is_match(&text))
+        })?,
+    )?;
+    table.set(
+        "match",
+        lua.create_function(|lua, (pattern, text): (String, String)| {
+            let regex = compile(&pattern)?;
+            match regex.captures(&text) {
+                Some(captures) => Ok(Value::Table(captures_table(lua, &regex, &captures)?)),
+                None => Ok(Value::Nil),
+            }
+        })?,
+    )?;
+    table.set(
+        "match_all",
+        lua.create_function(|lua, (pattern, text): (String, String)| {
+            let regex = compile(&pattern)?;
+            lua.create_sequence_from(
+                regex
+                    .captures_iter(&text)
+                    .map(|captures| captures_table(lua, &regex, &captures))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )
+        })?,
+    )?;
+    table.set(
+        "replace_all",
+        lua.create_function(
+            |_, (pattern, text, replacement): (String, String, String)| {
+                Ok(compile(&pattern)?
+                    .replace_all(&text, replacement.as_str())
+                    .into_owned())
+            },
+        )?,
+    )?;
+    Ok(table)
+}
+
+fn compile(pattern: &str) -> mlua::Result<Regex> {
+    Regex::new(pattern).map_err(|e| mlua::Error::runtime(format!("invalid regex {pattern:?}: {e}")))
+}
+
+/// Returns the match at index 0, the groups at their indexes and the named groups at their names.
+/// Groups which didn't participate in the match are nil.
+fn captures_table(lua: &Lua, regex: &Regex, captures: &Captures) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    for (i, group) in captures.iter().enumerate() {
+        if let Some(group) = group {
+            table.set(i, group.as_str())?;
+        }
+    }
+    for name in regex.capture_names().flatten() {
+        if let Some(group) = captures.name(name) {
+            table.set(name, group.as_str())?;
+        }
+    }
+    Ok(table)
+}
+
+/// Converts decoded data to Lua with `null` values as nil.
+fn data_to_lua(lua: &Lua, data: &impl serde::Serialize) -> mlua::Result<Value> {
+    lua.to_value_with(
+        data,
+        SerializeOptions::new()
+            .serialize_none_to_null(false)
+            .serialize_unit_to_null(false),
+    )
+}
+
+/// `blockwatch.json`: decoding and encoding JSON.
+fn json_table(lua: &Lua) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set(
+        "decode",
+        lua.create_function(|lua, text: String| {
+            let data: serde_json::Value = serde_json::from_str(&text)
+                .map_err(|e| mlua::Error::runtime(format!("invalid JSON: {e}")))?;
+            data_to_lua(lua, &data)
+        })?,
+    )?;
+    table.set(
+        "encode",
+        lua.create_function(|lua, value: Value| {
+            let data: serde_json::Value =
+                lua.from_value_with(value, DeserializeOptions::new().sort_keys(true))?;
+            serde_json::to_string(&data).map_err(mlua::Error::external)
+        })?,
+    )?;
+    Ok(table)
+}
+
+/// `blockwatch.yaml`: decoding YAML.
+fn yaml_table(lua: &Lua) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set(
+        "decode",
+        lua.create_function(|lua, text: String| {
+            let data: serde_yaml_ng::Value = serde_yaml_ng::from_str(&text)
+                .map_err(|e| mlua::Error::runtime(format!("invalid YAML: {e}")))?;
+            data_to_lua(lua, &data)
+        })?,
+    )?;
+    Ok(table)
+}
+
+/// `blockwatch.toml`: decoding TOML.
+fn toml_table(lua: &Lua) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set(
+        "decode",
+        lua.create_function(|lua, text: String| {
+            let data: toml::Table = toml::from_str(&text)
+                .map_err(|e| mlua::Error::runtime(format!("invalid TOML: {e}")))?;
+            data_to_lua(lua, &toml_to_json(toml::Value::Table(data)))
+        })?,
+    )?;
+    Ok(table)
+}
+
+/// Converts a TOML value to JSON, turning the dates and times into their TOML strings (they would
+/// otherwise serialize as private tables).
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(datetime) => serde_json::Value::String(datetime.to_string()),
+        toml::Value::Array(array) => array.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_json(value)))
+            .collect(),
+    }
+}
+
+/// Converts a syntax tree node to `{ type, named, text, start_line, start_column, end_line,
+/// end_column, children }`. Lines and columns start at 1; columns count bytes.
+///
+/// The tree is walked with a cursor rather than recursively, so that deeply nested input can't
+/// overflow the stack.
+fn node_table(lua: &Lua, node: tree_sitter::Node, source: &str) -> mlua::Result<Table> {
+    let (table, children) = node_fields(lua, node, source)?;
+    // The `children` of the nodes from `node` to the one under the cursor.
+    let mut path = vec![children];
+    let mut cursor = node.walk();
+    loop {
+        if cursor.goto_first_child() {
+            let (child, children) = node_fields(lua, cursor.node(), source)?;
+            path.last().expect("the path has the parent").push(child)?;
+            path.push(children);
+            continue;
+        }
+        // Leave the nodes without any next sibling, then visit the next sibling (if any).
+        loop {
+            path.pop();
+            if cursor.goto_next_sibling() {
+                let (sibling, children) = node_fields(lua, cursor.node(), source)?;
+                path.last()
+                    .expect("the path has the parent")
+                    .push(sibling)?;
+                path.push(children);
+                break;
+            }
+            if !cursor.goto_parent() {
+                return Ok(table);
+            }
+        }
+    }
+}
+
+/// Returns the table of the `node` with its fields, and its still empty `children`.
+fn node_fields(lua: &Lua, node: tree_sitter::Node, source: &str) -> mlua::Result<(Table, Table)> {
+    let table = lua.create_table()?;
+    table.set("type", node.kind())?;
+    table.set("named", node.is_named())?;
+    table.set("text", &source[node.byte_range()])?;
+    table.set("start_line", node.start_position().row + 1)?;
+    table.set("start_column", node.start_position().column + 1)?;
+    table.set("end_line", node.end_position().row + 1)?;
+    table.set("end_column", node.end_position().column + 1)?;
+    let children = lua.create_table()?;
+    table.set("children", &children)?;
+    Ok((table, children))
+}
@@ -0,0 +1,617 @@
+use crate::blocks::{Block, BlockSeverity, BlockWithContext, FileSystem};
+use crate::validators;
+use crate::validators::{
+    Fixer, ValidationContext, ValidatorAsync, ValidatorDetector, ValidatorType, Violation,
+    ViolationRange,
+};
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+const GENERATE_CMD_ENV_VAR: &str = "BLOCKWATCH_GENERATE_CMD_MODE";
+
+/// How long a command may run unless the block sets `generate-cmd-timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether the commands may be run, based on the `BLOCKWATCH_GENERATE_CMD_MODE` environment
+/// variable.
+///
+/// - `disabled` (default): No commands are run.
+/// - `enabled`: The commands of the blocks are run.
+fn commands_enabled_from_env() -> bool {
+    // <block affects="README.md:generate-cmd-modes">
+    std::env::var(GENERATE_CMD_ENV_VAR).as_deref() == Ok("enabled")
+    // </block>
+}
+
+pub(crate) struct GenerateCmdValidator {
+    commands_enabled: bool,
+}
+
+impl GenerateCmdValidator {
+    pub(super) fn new(commands_enabled: bool) -> Self {
+        Self { commands_enabled }
+    }
+
+    pub(super) fn from_env() -> Self {
+        Self::new(commands_enabled_from_env())
+    }
+}
+
+#[derive(Serialize)]
+struct GenerateCmdViolation<'a> {
+    command: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+}
+
+/// Returns the command of the `block` defined in `file_path` (if any).
+fn command<'a>(file_path: &Path, block: &'a Block) -> anyhow::Result<Option<&'a str>> {
+    let Some(command) = block.attributes.get("generate-cmd") else {
+        return Ok(None);
+    };
+    let command = command.trim();
+    if command.is_empty() {
+        return Err(anyhow!(
+            "generate-cmd requires a non-empty command in {}:{} at line {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line
+        ));
+    }
+    Ok(Some(command))
+}
+
+/// Returns the `generate-cmd-timeout` of the `block` defined in `file_path`, in seconds.
+fn timeout(file_path: &Path, block: &Block) -> anyhow::Result<Duration> {
+    let Some(timeout) = block.attributes.get("generate-cmd-timeout") else {
+        return Ok(DEFAULT_TIMEOUT);
+    };
+    match timeout.trim().parse::<u64>() {
+        Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => Err(anyhow!(
+            "generate-cmd-timeout expected a positive number of seconds, got \"{}\" in {}:{} at line {}",
+            timeout,
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line
+        )),
+    }
+}
+
+/// Runs the `command` with the platform's shell in the directory `dir` (the current directory if
+/// `None`) and returns its standard output.
+///
+/// Fails if the command doesn't finish within the `timeout` (it is killed then, together with the
+/// processes it started) or exits with a non-zero status.
+fn run_command(command: &str, timeout: Duration, dir: Option<&Path>) -> anyhow::Result<String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        // A process group of its own lets the command be killed with all its children.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut shell, 0);
+        shell
+    };
+    if let Some(dir) = dir {
+        shell.current_dir(dir);
+    }
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to start the command")?;
+    // The outputs are read while the command runs, otherwise it blocks once a pipe is full.
+    let read_output = |mut output: Box<dyn Read + Send>| {
+        std::thread::spawn(move || {
+            let mut bytes = Vec::new();
+            output.read_to_end(&mut bytes).map(|_| bytes)
+        })
+    };
+    let stdout = read_output(Box::new(child.stdout.take().expect("stdout is piped")));
+    let stderr = read_output(Box::new(child.stderr.take().expect("stderr is piped")));
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().context("failed to wait for the command")? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // The reading threads finish on their own once the killed processes close the pipes.
+            kill_process_tree(&mut child);
+            let _ = child.wait();
+            anyhow::bail!("the command timed out after {}s", timeout.as_secs());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let join = |output: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+        output
+            .join()
+            .map_err(|_| anyhow!("failed to read the output of the command"))?
+            .context("failed to read the output of the command")
+    };
+    let stdout = join(stdout)?;
+    let stderr = join(stderr)?;
+    if !status.success() {
+        anyhow::bail!(
+            "the command failed with {}: {}",
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Kills the shell `child` and the processes it started.
+fn kill_process_tree(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(child.id()) else {
+            let _ = child.kill();
+            return;
+        };
+        // SAFETY: `kill` has no memory safety requirements; the negative pid addresses the process
+        // group the shell leads (see `run_command`).
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+    #[cfg(windows)]
+    {
+        let killed = Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &child.id().to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !killed {
+            let _ = child.kill();
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = child.kill();
+    }
+}
+
+/// Runs the command of the `block` defined in `file_path` in the directory `dir` and returns the
+/// lines of its output without the blank lines around them.
+fn generate(
+    file_path: &Path,
+    block: &Block,
+    command: &str,
+    dir: Option<&Path>,
+) -> anyhow::Result<Vec<String>> {
+    let output = run_command(command, timeout(file_path, block)?, dir).with_context(|| {
+        format!(
+            "generate-cmd \"{}\" of {}:{} at line {} failed",
+            command,
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line
+        )
+    })?;
+    Ok(validators::trimmed_lines(&output)
+        .into_iter()
+        .map(str::to_string)
+        .collect())
+}
+
+fn join_lines(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+#[async_trait]
+impl ValidatorAsync for GenerateCmdValidator {
+    /// Runs the commands with a bounded pool of blocking workers, and reports the violations (or
+    /// the first error) in the order of the blocks regardless of which worker finishes first.
+    async fn validate(
+        &self,
+        context: Arc<ValidationContext>,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut violations = HashMap::new();
+        let mut jobs = Vec::new();
+        for (file_path, file_blocks) in &context.blocks {
+            for (block_idx, block_with_context) in
+                file_blocks.blocks_with_context.iter().enumerate()
+            {
+                let block = &block_with_context.block;
+                let Some(command) = command(file_path, block)? else {
+                    continue;
+                };
+                if !self.commands_enabled {
+                    violations
+                        .entry(file_path.clone())
+                        .or_insert_with(Vec::new)
+                        .push(create_disabled_violation(file_path, block, command)?);
+                    continue;
+                }
+                jobs.push((file_path.clone(), block_idx));
+            }
+        }
+        jobs.sort();
+        let workers = std::thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(jobs.len());
+        let jobs = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+
+        let mut tasks = JoinSet::new();
+        for _ in 0..workers {
+            let context = Arc::clone(&context);
+            let jobs = Arc::clone(&jobs);
+            // The commands block while they run, so the workers get threads of their own instead
+            // of starving the asynchronous validators of the runtime.
+            tasks.spawn_blocking(move || {
+                let mut results = Vec::new();
+                loop {
+                    let job = jobs.lock().expect("no active locks").next();
+                    let Some((job_idx, (file_path, block_idx))) = job else {
+                        break;
+                    };
+                    let result = check_block(&context, &file_path, block_idx);
+                    results.push((job_idx, file_path, result));
+                }
+                results
+            });
+        }
+        let mut results = Vec::new();
+        while let Some(task_result) = tasks.join_next().await {
+            results.extend(task_result.context("generate-cmd task failed")?);
+        }
+        results.sort_by_key(|(job_idx, _, _)| *job_idx);
+
+        for (_, file_path, result) in results {
+            if let Some(violation) = result? {
+                violations
+                    .entry(file_path)
+                    .or_insert_with(Vec::new)
+                    .push(violation);
+            }
+        }
+        Ok(violations)
+    }
+}
+
+/// Runs the command of the block at `block_idx` of the file at `file_path` and returns the
+/// violation if the block differs from its output.
+fn check_block(
+    context: &ValidationContext,
+    file_path: &Path,
+    block_idx: usize,
+) -> anyhow::Result<Option<Violation>> {
+    let file_blocks = &context.blocks[file_path];
+    let block = &file_blocks.blocks_with_context[block_idx].block;
+    let command = block.attributes["generate-cmd"].trim();
+    let generated = generate(file_path, block, command, context.root_path())?;
+    let actual = validators::unfenced_lines(block.content(&file_blocks.file_content));
+    if actual == generated {
+        return Ok(None);
+    }
+    create_violation(file_path, block, command, &generated, &actual).map(Some)
+}
+
+impl Fixer for GenerateCmdValidator {
+    fn fix(
+        &self,
+        context: &ValidationContext,
+        file_path: &Path,
+        block: &Block,
+        content: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let Some(command) = command(file_path, block)? else {
+            return Ok(None);
+        };
+        if !self.commands_enabled {
+            // The check reports the blocks which can't be generated.
+            return Ok(None);
+        }
+        let generated = generate(file_path, block, command, context.root_path())?;
+        if validators::unfenced_lines(content) == generated {
+            return Ok(None);
+        }
+        Ok(Some(validators::replace_unfenced_lines(
+            content, &generated,
+        )))
+    }
+}
+
+fn create_violation(
+    block_file_path: &Path,
+    block: &Block,
+    command: &str,
+    generated: &[String],
+    actual: &[&str],
+) -> anyhow::Result<Violation> {
+    let message = format!(
+        "Block {}:{} at line {} differs from the output of \"{}\"",
+        block_file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+        command
+    );
+    let generated = join_lines(generated);
+    let actual: String = actual.iter().map(|line| format!("{line}\n")).collect();
+    let diff = similar::TextDiff::from_lines(&generated, &actual)
+        .unified_diff()
+        .header(
+            command,
+            &format!("{}:{}", block_file_path.display(), block.name_display()),
+        )
+        .to_string();
+    let details = serde_json::to_value(GenerateCmdViolation {
+        command,
+        diff: Some(diff),
+    })
+    .context("failed to serialize GenerateCmdViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "generate-cmd".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+/// Returns the warning reported on the `block` when its `command` can't be run: a plain run
+/// without the environment variable must not fail because of the blocks it isn't allowed to check.
+fn create_disabled_violation(
+    block_file_path: &Path,
+    block: &Block,
+    command: &str,
+) -> anyhow::Result<Violation> {
+    let message = format!(
+        "Block {}:{} at line {} is generated by \"{}\", but running commands is disabled (set {}=enabled to run it)",
+        block_file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+        command,
+        GENERATE_CMD_ENV_VAR
+    );
+    let details = serde_json::to_value(GenerateCmdViolation {
+        command,
+        diff: None,
+    })
+    .context("failed to serialize GenerateCmdViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "generate-cmd".to_string(),
+        message,
+        BlockSeverity::Warning,
+        Some(details),
+    ))
+}
+
+pub(crate) struct GenerateCmdValidatorDetector();
+
+impl GenerateCmdValidatorDetector {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl<Fs: FileSystem> ValidatorDetector<Fs> for GenerateCmdValidatorDetector {
+    fn detect(
+        &self,
+        block_with_context: &BlockWithContext,
+        _file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        if block_with_context
+            .block
+            .attributes
+            .contains_key("generate-cmd")
+        {
+            Ok(Some(ValidatorType::Async(Box::new(
+                GenerateCmdValidator::from_env(),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{messages, validation_context};
+
+    #[tokio::test]
+    async fn same_output_returns_ok() -> anyhow::Result<()> {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"printf 'a\\nb\\n'\"> -->\n```\na\nb\n```\n<!-- </block> -->\n",
+        );
+
+        let violations = validator.validate(context).await?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn different_output_returns_violation() -> anyhow::Result<()> {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"printf 'a\\nc\\n'\"> -->\na\nb\n<!-- </block> -->\n",
+        );
+
+        let violations = validator.validate(context).await?;
+
+        assert_eq!(
+            messages(&violations, "README.md"),
+            vec![
+                "Block README.md:(unnamed) at line 1 differs from the output of \"printf 'a\\nc\\n'\""
+            ]
+        );
+        let violation = &violations[Path::new("README.md")][0];
+        assert_eq!(violation.code(), "generate-cmd");
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({
+                "command": "printf 'a\\nc\\n'",
+                "diff": "--- printf 'a\\nc\\n'\n+++ README.md:(unnamed)\n@@ -1,2 +1,2 @@\n a\n-c\n+b\n"
+            }))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disabled_commands_return_warning() -> anyhow::Result<()> {
+        let validator = GenerateCmdValidator::new(false);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"echo a\"> -->\na\n<!-- </block> -->\n",
+        );
+
+        let violations = validator.validate(context).await?;
+
+        assert_eq!(
+            messages(&violations, "README.md"),
+            vec![
+                "Block README.md:(unnamed) at line 1 is generated by \"echo a\", but running commands is disabled (set BLOCKWATCH_GENERATE_CMD_MODE=enabled to run it)"
+            ]
+        );
+        assert_eq!(
+            violations[Path::new("README.md")][0].severity(),
+            BlockSeverity::Warning
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failing_command_returns_error() {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"echo oops >&2; exit 3\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator.validate(context).await;
+
+        assert_eq!(
+            format!("{:#}", result.unwrap_err()),
+            "generate-cmd \"echo oops >&2; exit 3\" of README.md:(unnamed) at line 1 failed: the command failed with exit status: 3: oops"
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_command_times_out() {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"sleep 5\" generate-cmd-timeout=\"1\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator.validate(context).await;
+
+        assert_eq!(
+            format!("{:#}", result.unwrap_err()),
+            "generate-cmd \"sleep 5\" of README.md:(unnamed) at line 1 failed: the command timed out after 1s"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timed_out_command_is_killed_with_its_children() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let result = run_command(
+            "(sleep 2; touch started-after-timeout) & sleep 10",
+            Duration::from_millis(500),
+            Some(dir.path()),
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "the command timed out after 0s"
+        );
+        std::thread::sleep(Duration::from_secs(3));
+        assert!(!dir.path().join("started-after-timeout").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn command_runs_in_dir() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("data.txt"), "data")?;
+        let output = run_command("cat data.txt", Duration::from_secs(5), Some(dir.path()))?;
+        assert_eq!(output, "data");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_timeout_returns_error() {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"echo a\" generate-cmd-timeout=\"soon\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator.validate(context).await;
+
+        assert_eq!(
+            format!("{:#}", result.unwrap_err()),
+            "generate-cmd-timeout expected a positive number of seconds, got \"soon\" in README.md:(unnamed) at line 1"
+        );
+    }
+
+    #[test]
+    fn fix_replaces_content_within_code_fence() -> anyhow::Result<()> {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"echo new\"> -->\n\n```text\nold\n```\n\n<!-- </block> -->\n",
+        );
+        let file_blocks = &context.blocks[Path::new("README.md")];
+        let block = &file_blocks.blocks_with_context[0].block;
+
+        let fixed = validator.fix(
+            &context,
+            Path::new("README.md"),
+            block,
+            block.content(&file_blocks.file_content),
+        )?;
+
+        assert_eq!(fixed.as_deref(), Some("\n\n```text\nnew\n```\n\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn fix_of_up_to_date_block_returns_none() -> anyhow::Result<()> {
+        let validator = GenerateCmdValidator::new(true);
+        let context = validation_context(
+            "README.md",
+            "<!-- <block generate-cmd=\"echo same\"> -->\nsame\n<!-- </block> -->\n",
+        );
+        let file_blocks = &context.blocks[Path::new("README.md")];
+        let block = &file_blocks.blocks_with_context[0].block;
+
+        let fixed = validator.fix(
+            &context,
+            Path::new("README.md"),
+            block,
+            block.content(&file_blocks.file_content),
+        )?;
+
+        assert_eq!(fixed, None);
+        Ok(())
+    }
+}
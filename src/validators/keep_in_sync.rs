@@ -0,0 +1,492 @@
+use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::validators;
+use crate::validators::{
+    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+    parse_affects_attribute,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Comment markers removed from the start of the lines by the `comments` normalization.
+const COMMENT_PREFIXES: [&str; 8] = ["<!--", "//", "/*", "--", "#", ";", "%", "*"];
+/// Comment markers removed from the end of the lines by the `comments` normalization.
+const COMMENT_SUFFIXES: [&str; 2] = ["-->", "*/"];
+
+pub(crate) struct KeepInSyncValidator {}
+
+impl KeepInSyncValidator {
+    pub(super) fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Serialize)]
+struct KeepInSyncViolation<'a> {
+    in_sync_with_file_path: &'a Path,
+    in_sync_with_block_name: &'a str,
+    diff: String,
+}
+
+/// How the contents of the blocks are normalized before they are compared.
+#[derive(Default)]
+struct Normalization {
+    // Trim the whitespace around every line and drop the leading and trailing blank lines.
+    trim: bool,
+    // Remove the comment markers around every line.
+    comments: bool,
+    // Collapse the runs of whitespace within every line and drop the blank lines.
+    whitespace: bool,
+    // Compare only the values extracted from every line (lines without a match are dropped).
+    pattern: Option<regex::Regex>,
+}
+
+impl Normalization {
+    /// Parses the `keep-in-sync-normalize` and `keep-in-sync-pattern` attributes of the `block`.
+    fn from_block(file_path: &Path, block: &Block) -> anyhow::Result<Self> {
+        let mut normalization = Normalization::default();
+        if let Some(value) = block.attributes.get("keep-in-sync-normalize") {
+            for option in value.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+                match option {
+                    "trim" => normalization.trim = true,
+                    "comments" => normalization.comments = true,
+                    "whitespace" => normalization.whitespace = true,
+                    _ => anyhow::bail!(
+                        "keep-in-sync-normalize expected values are \"trim\", \"comments\" or \"whitespace\", got \"{}\" in {}:{} at line {}",
+                        option,
+                        file_path.display(),
+                        block.name_display(),
+                        block.start_tag_position_range.start().line
+                    ),
+                }
+            }
+        }
+        let pattern = block
+            .attributes
+            .get("keep-in-sync-pattern")
+            .map(String::as_str)
+            .unwrap_or_default();
+        if !pattern.is_empty() {
+            normalization.pattern = Some(regex::Regex::new(pattern).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid keep-in-sync-pattern expression in block {}:{} defined at line {}: {}",
+                    file_path.display(),
+                    block.name_display(),
+                    block.start_tag_position_range.start().line,
+                    e
+                )
+            })?);
+        }
+        Ok(normalization)
+    }
+
+    /// Returns the normalized `content`, one line per line of the content.
+    fn apply(&self, content: &str) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        for line in content.lines() {
+            let mut line = line;
+            if self.comments {
+                line = Self::strip_comment(line);
+            }
+            let mut line = if self.whitespace {
+                line.split_whitespace().collect::<Vec<_>>().join(" ")
+            } else if self.trim {
+                line.trim().to_string()
+            } else {
+                line.to_string()
+            };
+            if let Some(pattern) = &self.pattern {
+                let Some((value, _)) = validators::line_value(&line, Some(pattern)) else {
+                    continue;
+                };
+                line = value.to_string();
+            }
+            if self.whitespace && line.is_empty() {
+                continue;
+            }
+            lines.push(line);
+        }
+        if self.trim {
+            while lines.first().is_some_and(|line| line.trim().is_empty()) {
+                lines.remove(0);
+            }
+            while lines.last().is_some_and(|line| line.trim().is_empty()) {
+                lines.pop();
+            }
+        }
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+
+    /// Removes the comment markers (and the whitespace after the opening one) around the `line`.
+    fn strip_comment(line: &str) -> &str {
+        let mut stripped = line.trim();
+        if let Some(prefix) = COMMENT_PREFIXES
+            .iter()
+            .find(|prefix| stripped.starts_with(**prefix))
+        {
+            stripped = stripped[prefix.len()..].trim_start();
+        }
+        if let Some(suffix) = COMMENT_SUFFIXES
+            .iter()
+            .find(|suffix| stripped.ends_with(**suffix))
+        {
+            stripped = stripped[..stripped.len() - suffix.len()].trim_end();
+        }
+        stripped
+    }
+}
+
+impl ValidatorSync for KeepInSyncValidator {
+    fn validate(
+        &self,
+        context: Arc<validators::ValidationContext>,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut violations = HashMap::new();
+        for (file_path, file_blocks) in &context.blocks {
+            for block_with_context in &file_blocks.blocks_with_context {
+                let block = &block_with_context.block;
+                let Some(references) = block.attributes.get("keep-in-sync") else {
+                    continue;
+                };
+                let normalization = Normalization::from_block(file_path, block)?;
+                let content = normalization.apply(block.content(&file_blocks.file_content));
+                for (other_file_path, other_block_name) in parse_affects_attribute(references)? {
+                    let other_file_path = other_file_path.unwrap_or_else(|| file_path.clone());
+                    // There is nothing to compare with if the block doesn't exist.
+                    let Some((other_block, other_file_content)) =
+                        context.find_block(&other_file_path, &other_block_name)?
+                    else {
+                        continue;
+                    };
+                    let other_content =
+                        normalization.apply(other_block.content(other_file_content));
+                    if content == other_content {
+                        continue;
+                    }
+                    let diff = similar::TextDiff::from_lines(&other_content, &content)
+                        .unified_diff()
+                        .header(
+                            &format!("{}:{}", other_file_path.display(), other_block_name),
+                            &format!("{}:{}", file_path.display(), block.name_display()),
+                        )
+                        .to_string();
+                    violations
+                        .entry(file_path.clone())
+                        .or_insert_with(Vec::new)
+                        .push(create_violation(
+                            file_path,
+                            block,
+                            &other_file_path,
+                            &other_block_name,
+                            diff,
+                        )?);
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+fn create_violation(
+    block_file_path: &Path,
+    block: &Block,
+    in_sync_with_file_path: &Path,
+    in_sync_with_block_name: &str,
+    diff: String,
+) -> anyhow::Result<Violation> {
+    let message = format!(
+        "Block {}:{} at line {} is out of sync with {}:{}",
+        block_file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+        in_sync_with_file_path.display(),
+        in_sync_with_block_name
+    );
+    let details = serde_json::to_value(KeepInSyncViolation {
+        in_sync_with_file_path,
+        in_sync_with_block_name,
+        diff,
+    })
+    .context("failed to serialize KeepInSyncViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "keep-in-sync".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+pub(crate) struct KeepInSyncValidatorDetector();
+
+impl KeepInSyncValidatorDetector {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl<Fs: FileSystem> ValidatorDetector<Fs> for KeepInSyncValidatorDetector {
+    fn detect(
+        &self,
+        block_with_context: &BlockWithContext,
+        _file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        if block_with_context
+            .block
+            .attributes
+            .contains_key("keep-in-sync")
+        {
+            Ok(Some(ValidatorType::Sync(Box::new(
+                KeepInSyncValidator::new(),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{messages, validation_context, validation_context_from_changes};
+
+    #[test]
+    fn identical_blocks_return_ok() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"# <block keep-in-sync=":docs">
+red
+blue
+# </block>
+# <block name="docs">
+red
+blue
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn different_blocks_return_violation_with_diff() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"# <block keep-in-sync=":docs">
+red
+green
+# </block>
+# <block name="docs">
+red
+blue
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec!["Block a.py:(unnamed) at line 1 is out of sync with a.py:docs"]
+        );
+        let violation = &violations[Path::new("a.py")][0];
+        assert_eq!(violation.code(), "keep-in-sync");
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({
+                "in_sync_with_file_path": "a.py",
+                "in_sync_with_block_name": "docs",
+                "diff": "--- a.py:docs\n+++ a.py:(unnamed)\n@@ -1,3 +1,3 @@\n \n red\n-blue\n+green\n",
+            }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unmodified_block_in_other_file_is_compared() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let docs = "<!-- <block name=\"colors\"> -->\nred\n<!-- </block> -->\n";
+        let context = validation_context_from_changes(
+            &[
+                (
+                    "a.py",
+                    "# <block keep-in-sync=\"b.md:colors\">\nred\n# </block>\n",
+                ),
+                ("b.md", docs),
+            ],
+            &[
+                (
+                    "a.py",
+                    "# <block keep-in-sync=\"b.md:colors\">\nblue\n# </block>\n",
+                ),
+                ("b.md", docs),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec!["Block a.py:(unnamed) at line 1 is out of sync with b.md:colors"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_block_returns_ok() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            "# <block keep-in-sync=\":missing\">\nred\n# </block>\n",
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn trim_ignores_indentation_and_surrounding_blank_lines() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"colors = [
+    # <block keep-in-sync=":docs" keep-in-sync-normalize="trim">
+    red
+    # </block>
+]
+# <block name="docs">
+
+red
+
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn comments_ignores_comment_markers() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = merge_contexts(
+            (
+                "a.py",
+                "# <block keep-in-sync=\"b.rs:docs\" keep-in-sync-normalize=\"comments, trim\">\n# red\n# blue\n# </block>\n",
+            ),
+            (
+                "b.rs",
+                "// <block name=\"docs\">\n// red\n/* blue */\n// </block>\n",
+            ),
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn whitespace_collapses_whitespace_and_drops_blank_lines() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"# <block keep-in-sync=":docs" keep-in-sync-normalize="whitespace">
+red   =  1
+
+blue = 2
+# </block>
+# <block name="docs">
+  red = 1
+blue	= 2
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn pattern_compares_extracted_values() -> anyhow::Result<()> {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"# <block keep-in-sync=":docs" keep-in-sync-pattern="(?P<value>[A-Z]+) =">
+RED = 1
+BLUE = 2
+# </block>
+# <block name="docs">
+- RED = red color
+- BLUE = blue color
+Not a value.
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_normalization_returns_error() {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            "# <block keep-in-sync=\":docs\" keep-in-sync-normalize=\"nope\">\nred\n# </block>\n",
+        );
+
+        let result = validator.validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "keep-in-sync-normalize expected values are \"trim\", \"comments\" or \"whitespace\", got \"nope\" in a.py:(unnamed) at line 1"
+        );
+    }
+
+    #[test]
+    fn invalid_pattern_returns_error() {
+        let validator = KeepInSyncValidator::new();
+        let context = validation_context(
+            "a.py",
+            "# <block keep-in-sync=\":docs\" keep-in-sync-pattern=\"(\">\nred\n# </block>\n",
+        );
+
+        let result = validator.validate(context);
+
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .starts_with("Invalid keep-in-sync-pattern expression in block a.py:(unnamed)")
+        );
+    }
+
+    fn merge_contexts(
+        first: (&str, &str),
+        second: (&str, &str),
+    ) -> Arc<validators::ValidationContext> {
+        crate::test_utils::merge_validation_contexts(vec![
+            validation_context(first.0, first.1),
+            validation_context(second.0, second.1),
+        ])
+    }
+}
@@ -0,0 +1,410 @@
+use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::validators;
+use crate::validators::{
+    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+    parse_affects_attribute,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub(crate) struct KeepSameValuesValidator {}
+
+impl KeepSameValuesValidator {
+    pub(super) fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Serialize)]
+struct KeepSameValuesViolation<'a> {
+    other_block_file_path: &'a Path,
+    other_block_name: &'a str,
+    missing_in_block: Vec<&'a str>,
+    missing_in_other_block: Vec<&'a str>,
+    is_order_different: bool,
+}
+
+/// Returns the compiled `keep-same-values-pattern` regex of the `block` defined in `file_path`
+/// (if any).
+fn pattern(file_path: &Path, block: &Block) -> anyhow::Result<Option<regex::Regex>> {
+    let pattern = block
+        .attributes
+        .get("keep-same-values-pattern")
+        .map(String::as_str)
+        .unwrap_or_default();
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+    regex::Regex::new(pattern).map(Some).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid keep-same-values-pattern expression in block {}:{} defined at line {}: {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line,
+            e
+        )
+    })
+}
+
+/// Whether the order of the values matters: `keep-same-values-mode` is "set" (default) or
+/// "sequence".
+fn is_sequence(file_path: &Path, block: &Block) -> anyhow::Result<bool> {
+    match block
+        .attributes
+        .get("keep-same-values-mode")
+        .map(|mode| mode.trim())
+    {
+        None | Some("") | Some("set") => Ok(false),
+        Some("sequence") => Ok(true),
+        Some(mode) => anyhow::bail!(
+            "keep-same-values-mode expected values are \"set\" or \"sequence\", got \"{}\" in {}:{} at line {}",
+            mode,
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line
+        ),
+    }
+}
+
+/// Extracts the values of the `content` line by line (see [`validators::line_value`]).
+fn values<'c>(content: &'c str, pattern: Option<&regex::Regex>) -> Vec<&'c str> {
+    content
+        .lines()
+        .filter_map(|line| validators::line_value(line, pattern).map(|(value, _)| value))
+        .collect()
+}
+
+/// Returns the `values` which are not among the `other_values`, in order and without duplicates.
+fn missing<'a>(values: &[&'a str], other_values: &[&str]) -> Vec<&'a str> {
+    let other_values: HashSet<&str> = other_values.iter().copied().collect();
+    let mut seen = HashSet::new();
+    values
+        .iter()
+        .copied()
+        .filter(|value| !other_values.contains(value) && seen.insert(*value))
+        .collect()
+}
+
+impl ValidatorSync for KeepSameValuesValidator {
+    fn validate(
+        &self,
+        context: Arc<validators::ValidationContext>,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut violations = HashMap::new();
+        for (file_path, file_blocks) in &context.blocks {
+            for block_with_context in &file_blocks.blocks_with_context {
+                let block = &block_with_context.block;
+                let Some(references) = block.attributes.get("keep-same-values") else {
+                    continue;
+                };
+                let is_sequence = is_sequence(file_path, block)?;
+                let block_pattern = pattern(file_path, block)?;
+                let block_values = values(
+                    block.content(&file_blocks.file_content),
+                    block_pattern.as_ref(),
+                );
+                for (other_file_path, other_block_name) in parse_affects_attribute(references)? {
+                    let other_file_path = other_file_path.unwrap_or_else(|| file_path.clone());
+                    // There is nothing to compare with if the block doesn't exist.
+                    let Some((other_block, other_file_content)) =
+                        context.find_block(&other_file_path, &other_block_name)?
+                    else {
+                        continue;
+                    };
+                    // Each side extracts the values with its own pattern, which defaults to the
+                    // pattern of the block declaring the check.
+                    let other_pattern = pattern(&other_file_path, other_block)?;
+                    let other_values = values(
+                        other_block.content(other_file_content),
+                        other_pattern.as_ref().or(block_pattern.as_ref()),
+                    );
+                    let missing_in_block = missing(&other_values, &block_values);
+                    let missing_in_other_block = missing(&block_values, &other_values);
+                    let is_order_different = is_sequence
+                        && missing_in_block.is_empty()
+                        && missing_in_other_block.is_empty()
+                        && block_values != other_values;
+                    if missing_in_block.is_empty()
+                        && missing_in_other_block.is_empty()
+                        && !is_order_different
+                    {
+                        continue;
+                    }
+                    violations
+                        .entry(file_path.clone())
+                        .or_insert_with(Vec::new)
+                        .push(create_violation(
+                            file_path,
+                            block,
+                            KeepSameValuesViolation {
+                                other_block_file_path: &other_file_path,
+                                other_block_name: &other_block_name,
+                                missing_in_block,
+                                missing_in_other_block,
+                                is_order_different,
+                            },
+                        )?);
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+fn create_violation(
+    block_file_path: &Path,
+    block: &Block,
+    details: KeepSameValuesViolation,
+) -> anyhow::Result<Violation> {
+    let other_block = format!(
+        "{}:{}",
+        details.other_block_file_path.display(),
+        details.other_block_name
+    );
+    let mut differences = Vec::new();
+    if !details.missing_in_block.is_empty() {
+        differences.push(format!(
+            "missing here: {}",
+            details.missing_in_block.join(", ")
+        ));
+    }
+    if !details.missing_in_other_block.is_empty() {
+        differences.push(format!(
+            "missing in {other_block}: {}",
+            details.missing_in_other_block.join(", ")
+        ));
+    }
+    if details.is_order_different {
+        differences.push("the order is different".to_string());
+    }
+    let message = format!(
+        "Block {}:{} at line {} has different values than {other_block} ({})",
+        block_file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+        differences.join("; ")
+    );
+    let details =
+        serde_json::to_value(details).context("failed to serialize KeepSameValuesViolation")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "keep-same-values".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+pub(crate) struct KeepSameValuesValidatorDetector();
+
+impl KeepSameValuesValidatorDetector {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl<Fs: FileSystem> ValidatorDetector<Fs> for KeepSameValuesValidatorDetector {
+    fn detect(
+        &self,
+        block_with_context: &BlockWithContext,
+        _file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        if block_with_context
+            .block
+            .attributes
+            .contains_key("keep-same-values")
+        {
+            Ok(Some(ValidatorType::Sync(Box::new(
+                KeepSameValuesValidator::new(),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        merge_validation_contexts, messages, validation_context, validation_context_from_changes,
+    };
+
+    const RUST_ENUM: &str = r#"enum Color {
+    // <block keep-same-values="colors.md:colors" keep-same-values-pattern="^\s*(?P<value>\w+),">
+    Red,
+    Green,
+    Blue,
+    // </block>
+}
+"#;
+
+    fn context(rust: &str, markdown: &str) -> Arc<validators::ValidationContext> {
+        merge_validation_contexts(vec![
+            validation_context("colors.rs", rust),
+            validation_context("colors.md", markdown),
+        ])
+    }
+
+    #[test]
+    fn same_values_in_different_formats_return_ok() -> anyhow::Result<()> {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            RUST_ENUM,
+            r#"<!-- <block name="colors" keep-same-values-pattern="^- `(?P<value>\w+)`"> -->
+- `Blue`: the sky.
+- `Red`: the fire.
+- `Green`: the grass.
+<!-- </block> -->
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_values_on_both_sides_return_violation() -> anyhow::Result<()> {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            RUST_ENUM,
+            r#"<!-- <block name="colors" keep-same-values-pattern="^- (?P<value>\w+)"> -->
+- Red
+- Green
+- Pink
+<!-- </block> -->
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "colors.rs"),
+            vec![
+                "Block colors.rs:(unnamed) at line 2 has different values than colors.md:colors (missing here: Pink; missing in colors.md:colors: Blue)"
+            ]
+        );
+        let violation = &violations[Path::new("colors.rs")][0];
+        assert_eq!(violation.code(), "keep-same-values");
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({
+                "other_block_file_path": "colors.md",
+                "other_block_name": "colors",
+                "missing_in_block": ["Pink"],
+                "missing_in_other_block": ["Blue"],
+                "is_order_different": false,
+            }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn other_block_without_pattern_uses_pattern_of_block() -> anyhow::Result<()> {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            RUST_ENUM,
+            "<!-- <block name=\"colors\"> -->\nRed,\nGreen,\nBlue,\n<!-- </block> -->\n",
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn without_patterns_trimmed_lines_are_compared() -> anyhow::Result<()> {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            "// <block keep-same-values=\"colors.md:colors\">\n  red\n\n  blue\n// </block>\n",
+            "<!-- <block name=\"colors\"> -->\nblue\nred\nred\n<!-- </block> -->\n",
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn sequence_mode_reports_different_order() -> anyhow::Result<()> {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            "// <block keep-same-values=\"colors.md:colors\" keep-same-values-mode=\"sequence\">\nred\nblue\n// </block>\n",
+            "<!-- <block name=\"colors\"> -->\nblue\nred\n<!-- </block> -->\n",
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "colors.rs"),
+            vec![
+                "Block colors.rs:(unnamed) at line 1 has different values than colors.md:colors (the order is different)"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unmodified_other_block_is_compared() -> anyhow::Result<()> {
+        let validator = KeepSameValuesValidator::new();
+        let markdown = "<!-- <block name=\"colors\"> -->\nRed,\nGreen,\nBlue,\n<!-- </block> -->\n";
+        let context = validation_context_from_changes(
+            &[("colors.rs", RUST_ENUM), ("colors.md", markdown)],
+            &[
+                ("colors.rs", &RUST_ENUM.replace("Blue", "Pink")),
+                ("colors.md", markdown),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "colors.rs"),
+            vec![
+                "Block colors.rs:(unnamed) at line 2 has different values than colors.md:colors (missing here: Blue; missing in colors.md:colors: Pink)"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_mode_returns_error() {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            "// <block keep-same-values=\"colors.md:colors\" keep-same-values-mode=\"bag\">\nred\n// </block>\n",
+            "<!-- <block name=\"colors\"> -->\nred\n<!-- </block> -->\n",
+        );
+
+        let result = validator.validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "keep-same-values-mode expected values are \"set\" or \"sequence\", got \"bag\" in colors.rs:(unnamed) at line 1"
+        );
+    }
+
+    #[test]
+    fn invalid_pattern_returns_error() {
+        let validator = KeepSameValuesValidator::new();
+        let context = context(
+            "// <block keep-same-values=\"colors.md:colors\" keep-same-values-pattern=\"(\">\nred\n// </block>\n",
+            "<!-- <block name=\"colors\"> -->\nred\n<!-- </block> -->\n",
+        );
+
+        let result = validator.validate(context);
+
+        assert!(result.unwrap_err().to_string().starts_with(
+            "Invalid keep-same-values-pattern expression in block colors.rs:(unnamed) defined at line 1"
+        ));
+    }
+}
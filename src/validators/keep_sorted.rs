@@ -1,6 +1,7 @@
 use crate::blocks::{Block, BlockWithContext, FileSystem};
 use crate::validators::{
-    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+    Fixer, ValidationContext, ValidatorDetector, ValidatorSync, ValidatorType, Violation,
+    ViolationRange, split_list_item,
 };
 use crate::{Position, validators};
 use anyhow::{Context, anyhow};
@@ -38,41 +39,112 @@ impl SortFormat {
     }
 }
 
-pub(crate) struct KeepSortedValidator {}
-
-impl KeepSortedValidator {
-    pub(super) fn new() -> Self {
-        Self {}
-    }
+/// Sorting options parsed from the `keep-sorted*` attributes of a block.
+struct SortOptions {
+    // Normalized `keep-sorted` value: "asc" or "desc".
+    order: String,
+    // Optional `keep-sorted-pattern` to extract the compared values with.
+    pattern: Option<regex::Regex>,
+    format: SortFormat,
+}
 
-    fn trimmed_line_value(line: &str) -> Option<(&str, RangeInclusive<usize>)> {
-        let trimmed_line = line.trim();
-        if trimmed_line.is_empty() {
+impl SortOptions {
+    /// Parses the sorting options of the `block` defined in `file_path`.
+    fn from_block(file_path: &Path, block: &Block) -> anyhow::Result<Self> {
+        let keep_sorted = block
+            .attributes
+            .get("keep-sorted")
+            .map(String::as_str)
+            .unwrap_or_default();
+        let keep_sorted_cleaned = keep_sorted.trim();
+        let order = if keep_sorted_cleaned.is_empty() {
+            "asc".to_string()
+        } else {
+            keep_sorted.to_lowercase()
+        };
+        if order != "asc" && order != "desc" {
+            return Err(anyhow!(
+                "keep-sorted expected values are \"asc\" or \"desc\", got \"{}\" in {}:{} at line {}",
+                keep_sorted,
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line
+            ));
+        }
+        // Optional regex pattern similar to keep-unique: if provided, we compare extracted matches.
+        let pattern = block
+            .attributes
+            .get("keep-sorted-pattern")
+            .map(String::as_str)
+            .unwrap_or_default();
+        let pattern = if pattern.is_empty() {
             None
         } else {
-            let start = trimmed_line.as_ptr() as usize - line.as_ptr() as usize + 1;
-            let end = start + trimmed_line.len() - 1;
-            Some((trimmed_line, start..=end))
+            Some(regex::Regex::new(pattern).map_err(|e| {
+                anyhow!(
+                    "Invalid keep-sorted-pattern expression in block {}:{} defined at line {}: {}",
+                    file_path.display(),
+                    block.name_display(),
+                    block.start_tag_position_range.start().line,
+                    e
+                )
+            })?)
+        };
+
+        let format_raw = block
+            .attributes
+            .get("keep-sorted-format")
+            .map(|s| s.trim())
+            .unwrap_or_default();
+        let format = if format_raw.is_empty() {
+            SortFormat::default()
+        } else {
+            SortFormat::from_str(format_raw).map_err(|_| {
+                anyhow!(
+                    "keep-sorted-format has an unsupported value \"{}\" in {}:{} at line {}",
+                    format_raw,
+                    file_path.display(),
+                    block.name_display(),
+                    block.start_tag_position_range.start().line
+                )
+            })?
+        };
+        Ok(Self {
+            order,
+            pattern,
+            format,
+        })
+    }
+
+    /// Returns the value of the `line` to compare and its 1-based character range within the line.
+    ///
+    /// Without a pattern the trailing comma is not a part of the compared value: the fixer keeps
+    /// the commas in place while moving the lines, so the last item may lose or gain one.
+    fn value<'a>(&self, line: &'a str) -> Option<(&'a str, RangeInclusive<usize>)> {
+        let (value, range) = validators::line_value(line, self.pattern.as_ref())?;
+        if self.pattern.is_some() {
+            return Some((value, range));
         }
+        let (_, body, _) = split_list_item(value);
+        Some((if body.is_empty() { value } else { body }, range))
     }
 
-    fn regex_value<'a>(
-        line: &'a str,
-        regex: &regex::Regex,
-    ) -> Option<(&'a str, RangeInclusive<usize>)> {
-        if let Some(caps) = regex.captures(line) {
-            if let Some(m) = caps.name("value") {
-                let range = m.range();
-                Some((m.as_str(), range.start + 1..=range.end))
-            } else if let Some(m) = caps.get(0) {
-                let range = m.range();
-                Some((m.as_str(), range.start + 1..=range.end))
-            } else {
-                None
-            }
+    /// Compares the values `a` and `b` in the requested order.
+    fn cmp(&self, a: &str, b: &str) -> anyhow::Result<Ordering> {
+        let ordering = self.format.cmp(a, b)?;
+        Ok(if self.order == "asc" {
+            ordering
         } else {
-            None
-        }
+            ordering.reverse()
+        })
+    }
+}
+
+pub(crate) struct KeepSortedValidator {}
+
+impl KeepSortedValidator {
+    pub(super) fn new() -> Self {
+        Self {}
     }
 }
 
@@ -89,83 +161,28 @@ impl ValidatorSync for KeepSortedValidator {
         let mut violations = HashMap::new();
         for (file_path, file_blocks) in &context.blocks {
             for block_with_context in &file_blocks.blocks_with_context {
-                if let Some(keep_sorted) = block_with_context.block.attributes.get("keep-sorted") {
-                    let keep_sorted_cleaned = keep_sorted.trim();
-                    let keep_sorted_normalized = if keep_sorted_cleaned.is_empty() {
-                        "asc".to_string()
-                    } else {
-                        keep_sorted.to_lowercase()
-                    };
-                    if keep_sorted_normalized != "asc" && keep_sorted_normalized != "desc" {
-                        return Err(anyhow!(
-                            "keep-sorted expected values are \"asc\" or \"desc\", got \"{}\" in {}:{} at line {}",
-                            keep_sorted,
-                            file_path.display(),
-                            block_with_context.block.name_display(),
-                            block_with_context
-                                .block
-                                .start_tag_position_range
-                                .start()
-                                .line
-                        ));
-                    }
-                    // Optional regex pattern similar to keep-unique: if provided, we compare extracted matches.
-                    let pattern = block_with_context
-                        .block
-                        .attributes
-                        .get("keep-sorted-pattern")
-                        .cloned()
-                        .unwrap_or_default();
-                    let re = if pattern.is_empty() {
-                        None
-                    } else {
-                        Some(regex::Regex::new(&pattern))
-                    };
-
-                    let format_raw = block_with_context
-                        .block
-                        .attributes
-                        .get("keep-sorted-format")
-                        .map(|s| s.trim())
-                        .unwrap_or_default();
-                    let sort_format = if format_raw.is_empty() {
-                        SortFormat::default()
-                    } else {
-                        SortFormat::from_str(format_raw).map_err(|_| {
-                            anyhow!(
-                                "keep-sorted-format has an unsupported value \"{}\" in {}:{} at line {}",
-                                format_raw,
-                                file_path.display(),
-                                block_with_context.block.name_display(),
-                                block_with_context
-                                    .block
-                                    .start_tag_position_range
-                                    .start()
-                                    .line
-                            )
-                        })?
-                    };
-
-                    let violating_ord = if keep_sorted_normalized == "asc" {
-                        Ordering::Greater
-                    } else {
-                        Ordering::Less
-                    };
-                    // Keep previous value and its range for violation location purposes
-                    let mut prev_value: Option<(&str, RangeInclusive<usize>)> = None;
-                    for (line_number, line) in block_with_context
-                        .block
-                        .content(&file_blocks.file_content)
-                        .lines()
-                        .enumerate()
-                    {
-                        // Determine current comparable value and its character range within the line
-                        let value = match &re {
-                            None => Self::trimmed_line_value(line),
-                            Some(Ok(regex)) => Self::regex_value(line, regex),
-                            Some(Err(e)) => {
-                                return Err(anyhow!(
-                                    "Invalid keep-sorted-pattern expression in block {}:{} defined at line {}: {}",
+                if !block_with_context
+                    .block
+                    .attributes
+                    .contains_key("keep-sorted")
+                {
+                    continue;
+                }
+                let options = SortOptions::from_block(file_path, &block_with_context.block)?;
+                // Keep previous value and its range for violation location purposes
+                let mut prev_value: Option<(&str, RangeInclusive<usize>)> = None;
+                for (line_number, line) in block_with_context
+                    .block
+                    .content(&file_blocks.file_content)
+                    .lines()
+                    .enumerate()
+                {
+                    // Determine current comparable value and its character range within the line
+                    if let Some((curr_val, curr_range)) = options.value(line) {
+                        if let Some((prev_val, _prev_range)) = &prev_value {
+                            let cmp = options.cmp(prev_val, curr_val).with_context(|| {
+                                format!(
+                                    "in block {}:{} defined at line {}",
                                     file_path.display(),
                                     block_with_context.block.name_display(),
                                     block_with_context
@@ -173,51 +190,32 @@ impl ValidatorSync for KeepSortedValidator {
                                         .start_tag_position_range
                                         .start()
                                         .line,
-                                    e
-                                ));
-                            }
-                        };
-
-                        if let Some((curr_val, curr_range)) = value {
-                            if let Some((prev_val, _prev_range)) = &prev_value {
-                                let cmp =
-                                    sort_format.cmp(prev_val, curr_val).with_context(|| {
-                                        format!(
-                                            "in block {}:{} defined at line {}",
-                                            file_path.display(),
-                                            block_with_context.block.name_display(),
-                                            block_with_context
-                                                .block
-                                                .start_tag_position_range
-                                                .start()
-                                                .line,
-                                        )
-                                    })?;
-                                if cmp == violating_ord {
-                                    let violation_line_number = block_with_context
-                                        .block
-                                        .start_tag_position_range
-                                        .start()
-                                        .line
-                                        + line_number;
-                                    let line_character_start = *curr_range.start();
-                                    let line_character_end = *curr_range.end();
-                                    violations
-                                        .entry(file_path.clone())
-                                        .or_insert_with(Vec::new)
-                                        .push(create_violation(
-                                            file_path,
-                                            &block_with_context.block,
-                                            keep_sorted_normalized.as_str(),
-                                            violation_line_number,
-                                            line_character_start,
-                                            line_character_end,
-                                        )?);
-                                    break;
-                                }
+                                )
+                            })?;
+                            if cmp == Ordering::Greater {
+                                let violation_line_number = block_with_context
+                                    .block
+                                    .start_tag_position_range
+                                    .start()
+                                    .line
+                                    + line_number;
+                                let line_character_start = *curr_range.start();
+                                let line_character_end = *curr_range.end();
+                                violations
+                                    .entry(file_path.clone())
+                                    .or_insert_with(Vec::new)
+                                    .push(create_violation(
+                                        file_path,
+                                        &block_with_context.block,
+                                        options.order.as_str(),
+                                        violation_line_number,
+                                        line_character_start,
+                                        line_character_end,
+                                    )?);
+                                break;
                             }
-                            prev_value = Some((curr_val, curr_range));
                         }
+                        prev_value = Some((curr_val, curr_range));
                     }
                 }
             }
@@ -227,6 +225,62 @@ impl ValidatorSync for KeepSortedValidator {
     }
 }
 
+impl Fixer for KeepSortedValidator {
+    /// Sorts the lines that have a comparable value; empty and non-matching lines stay in place.
+    ///
+    /// Only the line bodies are moved: every line keeps its indentation and trailing comma, so a
+    /// list without a trailing comma after its last item stays that way.
+    fn fix(
+        &self,
+        _context: &ValidationContext,
+        file_path: &Path,
+        block: &Block,
+        content: &str,
+    ) -> anyhow::Result<Option<String>> {
+        if !block.attributes.contains_key("keep-sorted") {
+            return Ok(None);
+        }
+        let options = SortOptions::from_block(file_path, block)?;
+        let mut lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut sortable_lines = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if let Some((value, _)) = options.value(line.trim_end_matches(['\r', '\n'])) {
+                let (_, body, _) = split_list_item(line);
+                sortable_lines.push((index, value, body));
+            }
+        }
+        let mut sorted_lines = sortable_lines.clone();
+        let mut cmp_error = None;
+        sorted_lines.sort_by(|(_, a, _), (_, b, _)| {
+            options.cmp(a, b).unwrap_or_else(|e| {
+                cmp_error.get_or_insert(e);
+                Ordering::Equal
+            })
+        });
+        if let Some(e) = cmp_error {
+            return Err(e.context(format!(
+                "in block {}:{} defined at line {}",
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line,
+            )));
+        }
+        let fixed_lines: Vec<String> = sortable_lines
+            .iter()
+            .zip(&sorted_lines)
+            .map(|((index, _, _), (_, _, body))| {
+                let (indentation, _, suffix) = split_list_item(lines[*index]);
+                format!("{indentation}{body}{suffix}")
+            })
+            .collect();
+        for ((index, _, _), fixed_line) in sortable_lines.iter().zip(&fixed_lines) {
+            lines[*index] = fixed_line;
+        }
+        let fixed = lines.concat();
+        Ok((fixed != content).then_some(fixed))
+    }
+}
+
 pub(crate) struct KeepSortedValidatorDetector();
 
 impl KeepSortedValidatorDetector {
@@ -816,3 +870,91 @@ mod validate_tests {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod fix_tests {
+    use super::*;
+    use crate::test_utils::validation_context;
+
+    fn block(attributes: &[(&str, &str)]) -> Block {
+        Block::new(
+            attributes
+                .iter()
+                .map(|(key, val)| (key.to_string(), val.to_string()))
+                .collect(),
+            Position::new(1, 3)..=Position::new(1, 20),
+            0..0,
+            Position::new(1, 22)..Position::new(4, 1),
+        )
+    }
+
+    #[test]
+    fn block_without_keep_sorted_returns_none() -> anyhow::Result<()> {
+        let fixed = KeepSortedValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[]),
+            "\nb\na\n",
+        )?;
+
+        assert!(fixed.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn sorted_content_returns_none() -> anyhow::Result<()> {
+        let fixed = KeepSortedValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[("keep-sorted", "")]),
+            "\na\nb\n",
+        )?;
+
+        assert!(fixed.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn unsorted_content_keeps_indentation_and_line_endings() -> anyhow::Result<()> {
+        let fixed = KeepSortedValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[("keep-sorted", "")]),
+            "\r\n  b,\r\n    a\r\n  ",
+        )?;
+
+        assert_eq!(fixed.as_deref(), Some("\r\n  a,\r\n    b\r\n  "));
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_content_passes_validation() -> anyhow::Result<()> {
+        let content = "# <block keep-sorted>\na!,\na\n# </block>\n";
+        let context = validation_context("a.py", content);
+        let block = &context.blocks[Path::new("a.py")].blocks_with_context[0].block;
+        let block_content = block.content(content);
+
+        let fixed = KeepSortedValidator::new()
+            .fix(&context, Path::new("a.py"), block, block_content)?
+            .unwrap();
+        let fixed_content = content.replace(block_content, &fixed);
+
+        assert_eq!(fixed_content, "# <block keep-sorted>\na,\na!\n# </block>\n");
+        let violations =
+            KeepSortedValidator::new().validate(validation_context("a.py", &fixed_content))?;
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_pattern_returns_error() {
+        let result = KeepSortedValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[("keep-sorted", ""), ("keep-sorted-pattern", "(unclosed")]),
+            "\nb\na\n",
+        );
+
+        assert!(result.is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::blocks::{Block, BlockWithContext, FileSystem};
 use crate::validators::{
-    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+    Fixer, ValidationContext, ValidatorDetector, ValidatorSync, ValidatorType, Violation,
+    ViolationRange, split_list_item,
 };
 use crate::{Position, validators};
 use std::collections::{HashMap, HashSet};
@@ -14,6 +15,27 @@ impl KeepUniqueValidator {
     pub(crate) fn new() -> Self {
         Self {}
     }
+
+    /// Returns the compiled `keep-unique` regex of the `block` defined in `file_path` (if any).
+    fn pattern(file_path: &Path, block: &Block) -> anyhow::Result<Option<regex::Regex>> {
+        let pattern = block
+            .attributes
+            .get("keep-unique")
+            .map(String::as_str)
+            .unwrap_or_default();
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        regex::Regex::new(pattern).map(Some).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid keep-unique regex pattern for block {}:{} defined at line {}: {}",
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line,
+                e
+            )
+        })
+    }
 }
 
 impl ValidatorSync for KeepUniqueValidator {
@@ -31,17 +53,7 @@ impl ValidatorSync for KeepUniqueValidator {
                 {
                     continue;
                 }
-                let pattern = block_with_context
-                    .block
-                    .attributes
-                    .get("keep-unique")
-                    .cloned()
-                    .unwrap_or_default();
-                let re = if pattern.is_empty() {
-                    None
-                } else {
-                    Some(regex::Regex::new(&pattern))
-                };
+                let re = Self::pattern(file_path, &block_with_context.block)?;
                 let mut seen: HashSet<&str> = HashSet::new();
                 for (line_number, line) in block_with_context
                     .block
@@ -49,51 +61,8 @@ impl ValidatorSync for KeepUniqueValidator {
                     .lines()
                     .enumerate()
                 {
-                    let line_match = match &re {
-                        None => {
-                            let trimmed_line = line.trim();
-                            if trimmed_line.is_empty() {
-                                None
-                            } else {
-                                let line_character_start =
-                                    trimmed_line.as_ptr() as usize - line.as_ptr() as usize + 1;
-                                let line_character_end =
-                                    line_character_start + trimmed_line.len() - 1;
-                                Some((trimmed_line, line_character_start..=line_character_end))
-                            }
-                        }
-                        Some(Ok(re)) => {
-                            if let Some(c) = re.captures(line) {
-                                // If named group "value" exists use it, otherwise use whole match
-                                if let Some(m) = c.name("value") {
-                                    let range = m.range();
-                                    Some((m.as_str(), range.start + 1..=range.end))
-                                } else {
-                                    c.get(0).map(|m| {
-                                        let range = m.range();
-                                        (m.as_str(), range.start + 1..=range.end)
-                                    })
-                                }
-                            } else {
-                                None // Skip line when no match
-                            }
-                        }
-                        Some(Err(e)) => {
-                            // Invalid regex: return an error for the validator
-                            return Err(anyhow::anyhow!(
-                                "Invalid keep-unique regex pattern for block {}:{} defined at line {}: {}",
-                                file_path.display(),
-                                block_with_context.block.name_display(),
-                                block_with_context
-                                    .block
-                                    .start_tag_position_range
-                                    .start()
-                                    .line,
-                                e
-                            ));
-                        }
-                    };
-                    if let Some((matched_line, line_range)) = line_match
+                    if let Some((matched_line, line_range)) =
+                        validators::line_value(line, re.as_ref())
                         && !seen.insert(matched_line)
                     {
                         let violation_line_number = block_with_context
@@ -123,6 +92,48 @@ impl ValidatorSync for KeepUniqueValidator {
     }
 }
 
+impl Fixer for KeepUniqueValidator {
+    /// Removes the lines whose value has already been seen earlier in the block.
+    ///
+    /// If the last item of the list is removed, the new last item takes over its trailing comma (or
+    /// the lack of it).
+    fn fix(
+        &self,
+        _context: &ValidationContext,
+        file_path: &Path,
+        block: &Block,
+        content: &str,
+    ) -> anyhow::Result<Option<String>> {
+        if !block.attributes.contains_key("keep-unique") {
+            return Ok(None);
+        }
+        let re = Self::pattern(file_path, block)?;
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut last_item_suffix = None;
+        let mut last_kept_item = None;
+        let mut lines: Vec<String> = Vec::new();
+        for line in content.split_inclusive('\n') {
+            match validators::line_value(line.trim_end_matches(['\r', '\n']), re.as_ref()) {
+                Some((value, _)) if !seen.insert(value) => {
+                    last_item_suffix = Some(split_list_item(line).2);
+                }
+                Some(_) => {
+                    last_item_suffix = None;
+                    last_kept_item = Some(lines.len());
+                    lines.push(line.to_string());
+                }
+                None => lines.push(line.to_string()),
+            }
+        }
+        if let (Some(suffix), Some(index)) = (last_item_suffix, last_kept_item) {
+            let (indentation, body, _) = split_list_item(&lines[index]);
+            lines[index] = format!("{indentation}{body}{suffix}");
+        }
+        let fixed = lines.concat();
+        Ok((fixed != content).then_some(fixed))
+    }
+}
+
 pub(crate) struct KeepUniqueValidatorDetector();
 
 impl KeepUniqueValidatorDetector {
@@ -375,3 +386,72 @@ ID:2
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod fix_tests {
+    use super::*;
+    use crate::test_utils::validation_context;
+
+    fn block(attributes: &[(&str, &str)]) -> Block {
+        Block::new(
+            attributes
+                .iter()
+                .map(|(key, val)| (key.to_string(), val.to_string()))
+                .collect(),
+            Position::new(1, 3)..=Position::new(1, 20),
+            0..0,
+            Position::new(1, 22)..Position::new(4, 1),
+        )
+    }
+
+    #[test]
+    fn block_without_keep_unique_returns_none() -> anyhow::Result<()> {
+        let fixed = KeepUniqueValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[]),
+            "\na\na\n",
+        )?;
+
+        assert!(fixed.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn unique_content_returns_none() -> anyhow::Result<()> {
+        let fixed = KeepUniqueValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[("keep-unique", "")]),
+            "\na\nb\n",
+        )?;
+
+        assert!(fixed.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn duplicated_lines_are_removed_keeping_first_occurrence() -> anyhow::Result<()> {
+        let fixed = KeepUniqueValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[("keep-unique", "")]),
+            "\nb\na\n\nb\nc\na\n",
+        )?;
+
+        assert_eq!(fixed.as_deref(), Some("\nb\na\n\nc\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_pattern_returns_error() {
+        let result = KeepUniqueValidator::new().fix(
+            &validation_context("a.py", ""),
+            Path::new("a.py"),
+            &block(&[("keep-unique", "(unclosed")]),
+            "\na\n",
+        );
+
+        assert!(result.is_err());
+    }
+}
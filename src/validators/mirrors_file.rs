@@ -0,0 +1,550 @@
+use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::validators;
+use crate::validators::{
+    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub(crate) struct MirrorsFileValidator<Fs: FileSystem> {
+    file_system: Arc<Fs>,
+}
+
+impl<Fs: FileSystem> MirrorsFileValidator<Fs> {
+    pub(super) fn new(file_system: Arc<Fs>) -> Self {
+        Self { file_system }
+    }
+}
+
+#[derive(Serialize)]
+struct MirrorsFileViolation<'a> {
+    mirrored_file_path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mirrored_lines: Option<[usize; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mirrored_block_name: Option<&'a str>,
+    diff: String,
+}
+
+/// The part of the mirrored file a block is a copy of.
+enum Selector<'a> {
+    File,
+    // 1-based line numbers.
+    Lines(RangeInclusive<usize>),
+    BlockName(&'a str),
+}
+
+impl<'a> Selector<'a> {
+    /// Parses the `mirrors-file-lines` and `mirrors-file-block` attributes of the `block`.
+    fn from_block(file_path: &Path, block: &'a Block) -> anyhow::Result<Self> {
+        let lines = block.attributes.get("mirrors-file-lines");
+        let block_name = block.attributes.get("mirrors-file-block");
+        match (lines, block_name) {
+            (None, None) => Ok(Selector::File),
+            (Some(_), Some(_)) => anyhow::bail!(
+                "mirrors-file-lines and mirrors-file-block can't be used together in {}:{} at line {}",
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line
+            ),
+            (Some(lines), None) => {
+                parse_lines(lines)
+                    .map(Selector::Lines)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "mirrors-file-lines expected a line number or a range of line numbers like \"3-10\", got \"{}\" in {}:{} at line {}",
+                            lines,
+                            file_path.display(),
+                            block.name_display(),
+                            block.start_tag_position_range.start().line
+                        )
+                    })
+            }
+            (None, Some(block_name)) => Ok(Selector::BlockName(block_name.trim())),
+        }
+    }
+}
+
+/// Parses a 1-based line number ("3") or an inclusive range of line numbers ("3-10").
+fn parse_lines(value: &str) -> Option<RangeInclusive<usize>> {
+    let (start, end) = value.split_once('-').unwrap_or((value, value));
+    let start: usize = start.trim().parse().ok()?;
+    let end: usize = end.trim().parse().ok()?;
+    (start >= 1 && start <= end).then_some(start..=end)
+}
+
+impl<Fs: FileSystem> MirrorsFileValidator<Fs> {
+    /// Returns the lines of the part of the `mirrored_file_path` file selected by the block.
+    fn mirrored_lines(
+        &self,
+        context: &validators::ValidationContext,
+        file_path: &Path,
+        block: &Block,
+        mirrored_file_path: &Path,
+        selector: &Selector,
+        mirrored_content: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        match selector {
+            Selector::File => Ok(validators::trimmed_lines(mirrored_content)
+                .into_iter()
+                .map(str::to_string)
+                .collect()),
+            Selector::Lines(lines) => {
+                let file_lines: Vec<&str> = mirrored_content.lines().collect();
+                if *lines.end() > file_lines.len() {
+                    anyhow::bail!(
+                        "mirrors-file-lines {}-{} of {}:{} at line {} are out of range: {} has {} lines",
+                        lines.start(),
+                        lines.end(),
+                        file_path.display(),
+                        block.name_display(),
+                        block.start_tag_position_range.start().line,
+                        mirrored_file_path.display(),
+                        file_lines.len()
+                    );
+                }
+                Ok(validators::trimmed_lines(
+                    &file_lines[lines.start() - 1..*lines.end()].join("\n"),
+                )
+                .into_iter()
+                .map(str::to_string)
+                .collect())
+            }
+            Selector::BlockName(name) => {
+                let mirrored_block = context
+                    .parse_source(mirrored_file_path, mirrored_content)?
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|mirrored_block| mirrored_block.name() == Some(name))
+                    .with_context(|| {
+                        format!(
+                            "mirrors-file-block of {}:{} at line {} refers to a missing block \"{}\" in {}",
+                            file_path.display(),
+                            block.name_display(),
+                            block.start_tag_position_range.start().line,
+                            name,
+                            mirrored_file_path.display()
+                        )
+                    })?;
+                Ok(
+                    validators::trimmed_lines(mirrored_block.content(mirrored_content))
+                        .into_iter()
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+impl<Fs: FileSystem> MirrorsFileValidator<Fs> {
+    /// Checks the `block` of the file at `file_path` with `file_content` against the file it
+    /// mirrors, if any.
+    fn check_block(
+        &self,
+        context: &validators::ValidationContext,
+        file_path: &Path,
+        file_content: &str,
+        block: &Block,
+    ) -> anyhow::Result<Option<Violation>> {
+        let Some(mirrored_file_path) = block.attributes.get("mirrors-file") else {
+            return Ok(None);
+        };
+        let mirrored_file_path = PathBuf::from(mirrored_file_path.trim());
+        let selector = Selector::from_block(file_path, block)?;
+        // Reading through the file system keeps the mirrored file within the repository.
+        let mirrored_content = self
+            .file_system
+            .read_to_string(&mirrored_file_path)
+            .with_context(|| {
+                format!(
+                    "mirrors-file failed to read {} mirrored by {}:{} at line {}",
+                    mirrored_file_path.display(),
+                    file_path.display(),
+                    block.name_display(),
+                    block.start_tag_position_range.start().line
+                )
+            })?;
+        let expected = self.mirrored_lines(
+            context,
+            file_path,
+            block,
+            &mirrored_file_path,
+            &selector,
+            &mirrored_content,
+        )?;
+        let actual = validators::unfenced_lines(block.content(file_content));
+        if actual == expected {
+            return Ok(None);
+        }
+        create_violation(
+            file_path,
+            block,
+            &mirrored_file_path,
+            &selector,
+            &expected,
+            &actual,
+        )
+        .map(Some)
+    }
+}
+
+impl<Fs: FileSystem> ValidatorSync for MirrorsFileValidator<Fs> {
+    /// Checks the blocks of the context and, when the diff changed the files they mirror, the
+    /// unmodified blocks of the tree mirroring them.
+    fn validate(
+        &self,
+        context: Arc<validators::ValidationContext>,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut violations = HashMap::new();
+        let mut checked_blocks = HashSet::new();
+        for (file_path, file_blocks) in &context.blocks {
+            for block_with_context in &file_blocks.blocks_with_context {
+                let block = &block_with_context.block;
+                checked_blocks.insert((file_path, block.start_tag_position_range.start()));
+                if let Some(violation) =
+                    self.check_block(&context, file_path, &file_blocks.file_content, block)?
+                {
+                    violations
+                        .entry(file_path.clone())
+                        .or_insert_with(Vec::new)
+                        .push(violation);
+                }
+            }
+        }
+        if context.changed_files.is_empty() {
+            return Ok(violations);
+        }
+        for (file_path, file_blocks) in context.tree()? {
+            for block_with_context in &file_blocks.blocks_with_context {
+                let block = &block_with_context.block;
+                let mirrors_changed_file = block
+                    .attributes
+                    .get("mirrors-file")
+                    .is_some_and(|path| context.changed_files.contains(Path::new(path.trim())));
+                if !mirrors_changed_file
+                    || checked_blocks.contains(&(file_path, block.start_tag_position_range.start()))
+                {
+                    continue;
+                }
+                if let Some(violation) =
+                    self.check_block(&context, file_path, &file_blocks.file_content, block)?
+                {
+                    violations
+                        .entry(file_path.clone())
+                        .or_insert_with(Vec::new)
+                        .push(violation);
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+fn create_violation(
+    block_file_path: &Path,
+    block: &Block,
+    mirrored_file_path: &Path,
+    selector: &Selector,
+    expected: &[String],
+    actual: &[&str],
+) -> anyhow::Result<Violation> {
+    let mirrored = match selector {
+        Selector::File => mirrored_file_path.display().to_string(),
+        Selector::Lines(lines) => format!(
+            "lines {}-{} of {}",
+            lines.start(),
+            lines.end(),
+            mirrored_file_path.display()
+        ),
+        Selector::BlockName(name) => format!("{}:{}", mirrored_file_path.display(), name),
+    };
+    let message = format!(
+        "Block {}:{} at line {} differs from {}",
+        block_file_path.display(),
+        block.name_display(),
+        block.start_tag_position_range.start().line,
+        mirrored
+    );
+    let expected: String = expected.iter().map(|line| format!("{line}\n")).collect();
+    let actual: String = actual.iter().map(|line| format!("{line}\n")).collect();
+    let diff = similar::TextDiff::from_lines(&expected, &actual)
+        .unified_diff()
+        .header(
+            &mirrored_file_path.display().to_string(),
+            &format!("{}:{}", block_file_path.display(), block.name_display()),
+        )
+        .to_string();
+    let details = serde_json::to_value(MirrorsFileViolation {
+        mirrored_file_path,
+        mirrored_lines: match selector {
+            Selector::Lines(lines) => Some([*lines.start(), *lines.end()]),
+            _ => None,
+        },
+        mirrored_block_name: match selector {
+            Selector::BlockName(name) => Some(name),
+            _ => None,
+        },
+        diff,
+    })
+    .context("failed to serialize MirrorsFileViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "mirrors-file".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+pub(crate) struct MirrorsFileValidatorDetector();
+
+impl MirrorsFileValidatorDetector {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl<Fs: FileSystem + 'static> ValidatorDetector<Fs> for MirrorsFileValidatorDetector {
+    fn detect(
+        &self,
+        block_with_context: &BlockWithContext,
+        file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        if block_with_context
+            .block
+            .attributes
+            .contains_key("mirrors-file")
+        {
+            Ok(Some(ValidatorType::Sync(Box::new(
+                MirrorsFileValidator::new(Arc::clone(file_system)),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn detect_changed_files(
+        &self,
+        _changed_files: &HashSet<PathBuf>,
+        file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        // Any changed file may be mirrored by a block of the tree.
+        Ok(Some(ValidatorType::Sync(Box::new(
+            MirrorsFileValidator::new(Arc::clone(file_system)),
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        FakeFileSystem, messages, validation_context, validation_context_from_changes,
+    };
+
+    const CONFIG: &str = "[server]\nport = 8080\nhost = \"localhost\"\n\n[log]\nlevel = \"info\"\n";
+
+    fn validator() -> MirrorsFileValidator<FakeFileSystem> {
+        MirrorsFileValidator::new(Arc::new(FakeFileSystem::new(HashMap::from([
+            ("examples/config.toml".to_string(), CONFIG.to_string()),
+            (
+                "src/flags.rs".to_string(),
+                "// <block name=\"usage\">\n    blockwatch [GLOBS]...\n// </block>\n".to_string(),
+            ),
+        ]))))
+    }
+
+    #[test]
+    fn same_content_in_code_fence_returns_ok() -> anyhow::Result<()> {
+        let context = validation_context(
+            "README.md",
+            &format!(
+                "<!-- <block mirrors-file=\"examples/config.toml\"> -->\n\n```toml\n{CONFIG}```\n\n<!-- </block> -->\n"
+            ),
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn different_content_returns_violation() -> anyhow::Result<()> {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"examples/config.toml\"> -->\n[server]\nport = 80\n<!-- </block> -->\n",
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "README.md"),
+            vec!["Block README.md:(unnamed) at line 1 differs from examples/config.toml"]
+        );
+        let violation = &violations[Path::new("README.md")][0];
+        assert_eq!(violation.code(), "mirrors-file");
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({
+                "mirrored_file_path": "examples/config.toml",
+                "diff": "--- examples/config.toml\n+++ README.md:(unnamed)\n@@ -1,6 +1,2 @@\n [server]\n-port = 8080\n-host = \"localhost\"\n-\n-[log]\n-level = \"info\"\n+port = 80\n"
+            }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn same_lines_return_ok() -> anyhow::Result<()> {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"examples/config.toml\" mirrors-file-lines=\"5-6\"> -->\n[log]\nlevel = \"info\"\n<!-- </block> -->\n",
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn different_lines_return_violation() -> anyhow::Result<()> {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"examples/config.toml\" mirrors-file-lines=\"2\"> -->\nport = 80\n<!-- </block> -->\n",
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "README.md"),
+            vec![
+                "Block README.md:(unnamed) at line 1 differs from lines 2-2 of examples/config.toml"
+            ]
+        );
+        assert_eq!(
+            violations[Path::new("README.md")][0].data().unwrap()["mirrored_lines"],
+            serde_json::json!([2, 2])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn same_named_block_returns_ok() -> anyhow::Result<()> {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"src/flags.rs\" mirrors-file-block=\"usage\"> -->\n```\n    blockwatch [GLOBS]...\n```\n<!-- </block> -->\n",
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_named_block_returns_error() {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"src/flags.rs\" mirrors-file-block=\"help\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator().validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "mirrors-file-block of README.md:(unnamed) at line 1 refers to a missing block \"help\" in src/flags.rs"
+        );
+    }
+
+    #[test]
+    fn lines_out_of_range_return_error() {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"examples/config.toml\" mirrors-file-lines=\"5-9\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator().validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "mirrors-file-lines 5-9 of README.md:(unnamed) at line 1 are out of range: examples/config.toml has 6 lines"
+        );
+    }
+
+    #[test]
+    fn invalid_lines_return_error() {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"examples/config.toml\" mirrors-file-lines=\"9-5\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator().validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "mirrors-file-lines expected a line number or a range of line numbers like \"3-10\", got \"9-5\" in README.md:(unnamed) at line 1"
+        );
+    }
+
+    #[test]
+    fn missing_file_returns_error() {
+        let context = validation_context(
+            "README.md",
+            "<!-- <block mirrors-file=\"missing.toml\"> -->\n<!-- </block> -->\n",
+        );
+
+        let result = validator().validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "mirrors-file failed to read missing.toml mirrored by README.md:(unnamed) at line 1"
+        );
+    }
+
+    #[test]
+    fn changed_mirrored_file_returns_violation_in_unmodified_block() -> anyhow::Result<()> {
+        let readme = "<!-- <block mirrors-file=\"examples/config.toml\"> -->\n[server]\nport = 80\n<!-- </block> -->\n";
+        let context = validation_context_from_changes(
+            &[
+                ("examples/config.toml", "[server]\nport = 80\n"),
+                ("README.md", readme),
+            ],
+            &[("examples/config.toml", CONFIG), ("README.md", readme)],
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "README.md"),
+            vec!["Block README.md:(unnamed) at line 1 differs from examples/config.toml"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unchanged_mirrored_file_is_not_checked() -> anyhow::Result<()> {
+        let readme =
+            "<!-- <block mirrors-file=\"src/flags.rs\"> -->\noutdated\n<!-- </block> -->\n";
+        let context = validation_context_from_changes(
+            &[
+                ("examples/config.toml", "[server]\nport = 80\n"),
+                ("README.md", readme),
+            ],
+            &[("examples/config.toml", CONFIG), ("README.md", readme)],
+        );
+
+        let violations = validator().validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+}
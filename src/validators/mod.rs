@@ -1,27 +1,44 @@
 mod affects;
 mod check_ai;
 mod check_lua;
+mod generate_cmd;
+mod keep_in_sync;
+mod keep_same_values;
 mod keep_sorted;
 mod keep_unique;
 mod line_count;
 mod line_pattern;
+mod mirrors_file;
+mod sync_group;
+mod unique_names;
 
 use crate::Position;
-use crate::blocks::{BlockSeverity, BlockWithContext, FileBlocks, FileSystem};
-use crate::language_parsers::LanguageParsers;
+use crate::blocks;
+use crate::blocks::{
+    Block, BlockSeverity, BlockWithContext, ExtensionMappings, FileBlocks, FileSystem,
+};
+use crate::language_parsers::{LanguageParser, LanguageParsers};
 use crate::validators::affects::AffectsValidatorDetector;
+pub use crate::validators::affects::check_references;
 use crate::validators::check_ai::CheckAiValidatorDetector;
-use crate::validators::check_lua::CheckLuaValidatorDetector;
-use crate::validators::keep_sorted::KeepSortedValidatorDetector;
-use crate::validators::keep_unique::KeepUniqueValidatorDetector;
+use crate::validators::check_lua::{CheckLuaValidator, CheckLuaValidatorDetector};
+use crate::validators::generate_cmd::{GenerateCmdValidator, GenerateCmdValidatorDetector};
+use crate::validators::keep_in_sync::KeepInSyncValidatorDetector;
+use crate::validators::keep_same_values::KeepSameValuesValidatorDetector;
+use crate::validators::keep_sorted::{KeepSortedValidator, KeepSortedValidatorDetector};
+use crate::validators::keep_unique::{KeepUniqueValidator, KeepUniqueValidatorDetector};
 use crate::validators::line_count::LineCountValidatorDetector;
 use crate::validators::line_pattern::LinePatternValidatorDetector;
+use crate::validators::mirrors_file::MirrorsFileValidatorDetector;
+use crate::validators::sync_group::SyncGroupValidatorDetector;
+use crate::validators::unique_names::UniqueNamesValidatorDetector;
 use anyhow::Context;
 use async_trait::async_trait;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
 /// Validates the given `Context` and returns a list of the violations grouped by filename.
 #[async_trait]
@@ -48,6 +65,25 @@ pub trait ValidatorDetector<Fs: FileSystem> {
         block_with_context: &BlockWithContext,
         file_system: &Arc<Fs>,
     ) -> anyhow::Result<Option<ValidatorType>>;
+
+    /// Detects a [`ValidatorType`] for the blocks deleted by the diff grouped by their filename
+    /// (if any).
+    fn detect_deleted(
+        &self,
+        _deleted_blocks: &HashMap<PathBuf, Vec<Block>>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        Ok(None)
+    }
+
+    /// Detects a [`ValidatorType`] for the files changed by the diff (if any), including the files
+    /// without blocks, e.g. the files mirrored by the blocks of other files.
+    fn detect_changed_files(
+        &self,
+        _changed_files: &HashSet<PathBuf>,
+        _file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        Ok(None)
+    }
 }
 
 /// Validator type (sync or async).
@@ -83,6 +119,26 @@ impl Violation {
         }
     }
 
+    pub(crate) fn range(&self) -> &ViolationRange {
+        &self.range
+    }
+
+    pub(crate) fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(crate) fn severity(&self) -> BlockSeverity {
+        self.severity
+    }
+
+    pub(crate) fn data(&self) -> Option<&serde_json::Value> {
+        self.data.as_ref()
+    }
+
     pub fn as_simple_diagnostic(&self) -> SimpleDiagnostic<'_> {
         SimpleDiagnostic {
             range: &self.range,
@@ -101,9 +157,19 @@ pub struct ViolationRange {
 }
 
 impl ViolationRange {
-    fn new(start: Position, end: Position) -> Self {
+    pub(crate) fn new(start: Position, end: Position) -> Self {
         Self { start, end }
     }
+
+    /// Start position (inclusive).
+    pub(crate) fn start(&self) -> &Position {
+        &self.start
+    }
+
+    /// End position (inclusive).
+    pub(crate) fn end(&self) -> &Position {
+        &self.end
+    }
 }
 
 /// Represents a simplified, serializable diagnostic message.
@@ -126,6 +192,17 @@ impl SimpleDiagnostic<'_> {
     }
 }
 
+/// Loads all the blocks of the whole tree grouped by filename.
+type TreeLoader = Box<dyn Fn() -> anyhow::Result<HashMap<PathBuf, FileBlocks>> + Send + Sync>;
+
+/// Directory of the cache of the validators (e.g. the `check-ai` responses) relative to the
+/// repository root.
+pub const AI_CACHE_DIR: &str = ".blockwatch/cache";
+
+/// File of the `check-ai` responses recorded and replayed by `BLOCKWATCH_AI_MODE` relative to the
+/// repository root.
+pub const AI_FIXTURES_FILE: &str = ".blockwatch/ai-fixtures.json";
+
 pub struct ValidationContext {
     // Blocks with their corresponding source file contents grouped by filename.
     pub(crate) blocks: HashMap<PathBuf, FileBlocks>,
@@ -133,12 +210,180 @@ pub struct ValidationContext {
     // Language parsers for different file types, used to parse source files in validators.
     #[allow(dead_code)]
     pub(crate) parsers: LanguageParsers,
+    // Blocks deleted by the diff grouped by their filename before the change.
+    pub(crate) deleted_blocks: HashMap<PathBuf, Vec<Block>>,
+    // Files changed by the diff, including the ones without blocks.
+    pub(crate) changed_files: HashSet<PathBuf>,
+    // Extension mappings the blocks were parsed with, to parse the files again.
+    extension_mappings: ExtensionMappings,
+    // Validators disabled for individual files, for the validators not enabled by an attribute.
+    disabled_validators: HashMap<PathBuf, HashSet<String>>,
+    tree_loader: Option<TreeLoader>,
+    // The whole tree is only loaded by the validators that need it, at most once.
+    tree: OnceLock<Result<HashMap<PathBuf, FileBlocks>, String>>,
+    // Directory of the check-ai response cache; the cache is disabled when not set.
+    ai_cache_dir: Option<PathBuf>,
+    // File of the recorded check-ai responses; the record and replay modes need it.
+    ai_fixtures_path: Option<PathBuf>,
+    // Whether the validators report details of the run (e.g. cache hits) to stderr.
+    verbose: bool,
+    // Root of the repository the commands of the blocks run in; the current directory when not set.
+    root_path: Option<PathBuf>,
 }
 
 impl ValidationContext {
     /// Creates a new validation context with modified blocks grouped by filename.
     pub fn new(blocks: HashMap<PathBuf, FileBlocks>, parsers: LanguageParsers) -> Self {
-        Self { blocks, parsers }
+        Self {
+            blocks,
+            parsers,
+            deleted_blocks: HashMap::new(),
+            changed_files: HashSet::new(),
+            extension_mappings: ExtensionMappings::default(),
+            disabled_validators: HashMap::new(),
+            tree_loader: None,
+            tree: OnceLock::new(),
+            ai_cache_dir: None,
+            ai_fixtures_path: None,
+            verbose: false,
+            root_path: None,
+        }
+    }
+
+    /// Sets the blocks deleted by the diff grouped by their filename before the change (see
+    /// [`crate::blocks::parse_deleted_blocks`]).
+    pub fn with_deleted_blocks(mut self, deleted_blocks: HashMap<PathBuf, Vec<Block>>) -> Self {
+        self.deleted_blocks = deleted_blocks;
+        self
+    }
+
+    /// Sets the files changed by the diff, including the ones without blocks.
+    pub fn with_changed_files(mut self, changed_files: HashSet<PathBuf>) -> Self {
+        self.changed_files = changed_files;
+        self
+    }
+
+    /// Sets the extension mappings the blocks were parsed with (see
+    /// [`ValidationContext::all_file_blocks`]).
+    pub fn with_extension_mappings(mut self, extension_mappings: ExtensionMappings) -> Self {
+        self.extension_mappings = extension_mappings;
+        self
+    }
+
+    /// Sets how the blocks of the whole tree are loaded for [`ValidationContext::tree`].
+    pub fn with_tree_loader(
+        mut self,
+        tree_loader: impl Fn() -> anyhow::Result<HashMap<PathBuf, FileBlocks>> + Send + Sync + 'static,
+    ) -> Self {
+        self.tree_loader = Some(Box::new(tree_loader));
+        self
+    }
+
+    /// Sets the directory where the `check-ai` responses are cached. `None` disables the cache.
+    pub fn with_ai_cache_dir(mut self, ai_cache_dir: Option<PathBuf>) -> Self {
+        self.ai_cache_dir = ai_cache_dir;
+        self
+    }
+
+    /// Sets the file of the `check-ai` responses recorded and replayed by `BLOCKWATCH_AI_MODE`.
+    pub fn with_ai_fixtures_path(mut self, ai_fixtures_path: PathBuf) -> Self {
+        self.ai_fixtures_path = Some(ai_fixtures_path);
+        self
+    }
+
+    /// Sets whether the validators report details of the run to stderr.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Sets the root of the repository, where the commands of the blocks (e.g. `generate-cmd`) run.
+    pub fn with_root_path(mut self, root_path: PathBuf) -> Self {
+        self.root_path = Some(root_path);
+        self
+    }
+
+    /// Returns the root of the repository, if set.
+    pub(crate) fn root_path(&self) -> Option<&Path> {
+        self.root_path.as_deref()
+    }
+
+    /// Returns the directory of the `check-ai` response cache, if enabled.
+    pub(crate) fn ai_cache_dir(&self) -> Option<&Path> {
+        self.ai_cache_dir.as_deref()
+    }
+
+    /// Returns the file of the recorded `check-ai` responses, if set.
+    pub(crate) fn ai_fixtures_path(&self) -> Option<&Path> {
+        self.ai_fixtures_path.as_deref()
+    }
+
+    /// Whether the validators report details of the run to stderr.
+    pub(crate) fn verbose(&self) -> bool {
+        self.verbose
+    }
+
+    /// Returns all the blocks of the whole tree grouped by filename, loading them on first use.
+    ///
+    /// Without a tree loader the tree consists of the blocks of the context.
+    pub(crate) fn tree(&self) -> anyhow::Result<&HashMap<PathBuf, FileBlocks>> {
+        let Some(tree_loader) = &self.tree_loader else {
+            return Ok(&self.blocks);
+        };
+        self.tree
+            .get_or_init(|| tree_loader().map_err(|e| format!("{e:#}")))
+            .as_ref()
+            .map_err(|e| anyhow::anyhow!("Failed to load the blocks of the tree: {e}"))
+    }
+
+    /// Parses all the blocks of the file at `file_path` of the context, including the ones which are
+    /// not in the context (e.g. because they are not modified).
+    pub(crate) fn all_file_blocks(&self, file_path: &Path) -> anyhow::Result<Vec<Block>> {
+        let Some(file_blocks) = self.blocks.get(file_path) else {
+            return Ok(Vec::new());
+        };
+        Ok(self
+            .parse_source(file_path, &file_blocks.file_content)?
+            .unwrap_or_default())
+    }
+
+    /// Parses the blocks of `source` as the contents of the file at `file_path`, which doesn't need
+    /// to be in the context. Returns `None` if the file's language is not supported.
+    pub(crate) fn parse_source(
+        &self,
+        file_path: &Path,
+        source: &str,
+    ) -> anyhow::Result<Option<Vec<Block>>> {
+        blocks::parse_source(file_path, source, &self.parsers, &self.extension_mappings)
+    }
+
+    /// Finds the block named `name` in the file at `file_path` and returns it with the contents of
+    /// the file, looking it up in the whole tree unless it is in the context already.
+    pub(crate) fn find_block<'a>(
+        &'a self,
+        file_path: &Path,
+        name: &str,
+    ) -> anyhow::Result<Option<(&'a Block, &'a str)>> {
+        let find = |blocks: &'a HashMap<PathBuf, FileBlocks>| {
+            let file_blocks = blocks.get(file_path)?;
+            file_blocks
+                .blocks_with_context
+                .iter()
+                .find(|block_with_context| block_with_context.block.name() == Some(name))
+                .map(|block_with_context| {
+                    (&block_with_context.block, file_blocks.file_content.as_str())
+                })
+        };
+        match find(&self.blocks) {
+            Some(found) => Ok(Some(found)),
+            None => Ok(find(self.tree()?)),
+        }
+    }
+
+    /// Returns the parser of the language of the file at `file_path` (if supported), taking the
+    /// extension mappings into account.
+    pub(crate) fn language_parser(&self, file_path: &Path) -> Option<&LanguageParser> {
+        blocks::language_parser(file_path, &self.parsers, &self.extension_mappings)
     }
 
     /// Returns the language parsers available to validators.
@@ -146,6 +391,43 @@ impl ValidationContext {
         &self.parsers
     }
 
+    /// Disables validators for individual files by removing the block attributes enabling them.
+    ///
+    /// Every validator is enabled by the block attribute it is named after (see
+    /// [`detector_factories`]), so the blocks of a file stripped of that attribute are neither
+    /// detected nor checked by it. The validators which are not enabled by an attribute (e.g.
+    /// `unique-names`) check [`ValidationContext::is_validator_disabled`] instead.
+    pub fn disable_validators<'a>(
+        &mut self,
+        disabled_validators: impl Fn(&Path) -> HashSet<&'a str>,
+    ) {
+        for file_path in self.blocks.keys() {
+            let disabled = disabled_validators(file_path);
+            if !disabled.is_empty() {
+                self.disabled_validators.insert(
+                    file_path.clone(),
+                    disabled.into_iter().map(String::from).collect(),
+                );
+            }
+        }
+        disable_validators(&mut self.blocks, &disabled_validators);
+        for (file_path, blocks) in &mut self.deleted_blocks {
+            let disabled = disabled_validators(file_path);
+            for block in blocks {
+                block
+                    .attributes
+                    .retain(|attribute, _| !disabled.contains(attribute.as_str()));
+            }
+        }
+    }
+
+    /// Whether the `validator` is disabled for the file at `file_path`.
+    pub(crate) fn is_validator_disabled(&self, file_path: &Path, validator: &str) -> bool {
+        self.disabled_validators
+            .get(file_path)
+            .is_some_and(|disabled| disabled.contains(validator))
+    }
+
     /// Converts the validation context to a serializable report that can be displayed as JSON.
     pub fn to_serializable_report(&self) -> HashMap<PathBuf, Vec<serde_json::Value>> {
         let mut report = HashMap::new();
@@ -156,6 +438,26 @@ impl ValidationContext {
     }
 }
 
+/// Disables validators for the individual files of `blocks` like
+/// [`ValidationContext::disable_validators`] does.
+pub fn disable_validators<'a>(
+    blocks: &mut HashMap<PathBuf, FileBlocks>,
+    disabled_validators: impl Fn(&Path) -> HashSet<&'a str>,
+) {
+    for (file_path, file_blocks) in blocks {
+        let disabled = disabled_validators(file_path);
+        if disabled.is_empty() {
+            continue;
+        }
+        for block_with_context in &mut file_blocks.blocks_with_context {
+            block_with_context
+                .block
+                .attributes
+                .retain(|attribute, _| !disabled.contains(attribute.as_str()));
+        }
+    }
+}
+
 /// Runs all sync validators concurrently each in a separate thread and returns violations grouped
 /// by file paths.
 fn run_sync_validators(
@@ -268,7 +570,7 @@ type DetectorFactory<Fs> = fn() -> Box<dyn ValidatorDetector<Fs>>;
 /// instantiated per `Fs` (the production `FileSystemImpl`, a `FakeFileSystem` in tests).
 pub fn detector_factories<Fs: FileSystem + 'static>() -> Vec<(&'static str, DetectorFactory<Fs>)> {
     vec![
-        // <block affects="README.md:available-validators">
+        // <block affects="README.md:available-validators, src/reporters/sarif.rs:sarif-rules">
         ("affects", || Box::new(AffectsValidatorDetector::new())),
         ("keep-sorted", || {
             Box::new(KeepSortedValidatorDetector::new())
@@ -282,10 +584,170 @@ pub fn detector_factories<Fs: FileSystem + 'static>() -> Vec<(&'static str, Dete
         ("line-count", || Box::new(LineCountValidatorDetector::new())),
         ("check-ai", || Box::new(CheckAiValidatorDetector::new())),
         ("check-lua", || Box::new(CheckLuaValidatorDetector::new())),
+        ("sync-group", || Box::new(SyncGroupValidatorDetector::new())),
+        ("keep-in-sync", || {
+            Box::new(KeepInSyncValidatorDetector::new())
+        }),
+        ("keep-same-values", || {
+            Box::new(KeepSameValuesValidatorDetector::new())
+        }),
+        ("unique-names", || {
+            Box::new(UniqueNamesValidatorDetector::new())
+        }),
+        ("mirrors-file", || {
+            Box::new(MirrorsFileValidatorDetector::new())
+        }),
+        ("generate-cmd", || {
+            Box::new(GenerateCmdValidatorDetector::new())
+        }),
         // </block>
     ]
 }
 
+/// Rewrites a block's content so that it satisfies the validator the fixer belongs to.
+pub trait Fixer {
+    /// Returns the fixed `content` of the `block` defined in `file_path`, which may depend on the
+    /// other blocks of the `context`.
+    ///
+    /// Returns `None` if the validator does not apply to the block or the content is already valid.
+    fn fix(
+        &self,
+        context: &ValidationContext,
+        file_path: &Path,
+        block: &Block,
+        content: &str,
+    ) -> anyhow::Result<Option<String>>;
+}
+
+/// Builds the registry of the validators that can fix their violations, in the order the fixes
+/// are applied: generated contents are replaced first, then duplicates are removed before the
+/// remaining lines are sorted.
+pub fn fixers<Fs: FileSystem + 'static>(
+    file_system: &Arc<Fs>,
+) -> Vec<(&'static str, Box<dyn Fixer>)> {
+    vec![
+        ("generate-cmd", Box::new(GenerateCmdValidator::from_env())),
+        (
+            "check-lua",
+            Box::new(CheckLuaValidator::new(Arc::clone(file_system))),
+        ),
+        ("keep-unique", Box::new(KeepUniqueValidator::new())),
+        ("keep-sorted", Box::new(KeepSortedValidator::new())),
+    ]
+}
+
+/// Splits a list item `line` into its indentation, its body and its suffix (an optional trailing
+/// comma followed by the trailing whitespace and the line ending).
+///
+/// Fixers move or drop the bodies only, so that indentation and trailing commas stay where they
+/// were.
+fn split_list_item(line: &str) -> (&str, &str, &str) {
+    let body_start = line.len() - line.trim_start().len();
+    let trimmed_end = line.trim_end();
+    let body_end = trimmed_end
+        .strip_suffix(',')
+        .map_or(trimmed_end.len(), str::len)
+        .max(body_start);
+    (
+        &line[..body_start],
+        &line[body_start..body_end],
+        &line[body_end..],
+    )
+}
+
+/// Returns the value of the `line` and its 1-based character range within the line.
+///
+/// Without a `pattern` the value is the trimmed line. With a `pattern` it is the named group
+/// `value` of the match, or the whole match without one. Empty lines and lines not matching the
+/// `pattern` have no value.
+fn line_value<'a>(
+    line: &'a str,
+    pattern: Option<&regex::Regex>,
+) -> Option<(&'a str, RangeInclusive<usize>)> {
+    match pattern {
+        None => {
+            let trimmed_line = line.trim();
+            if trimmed_line.is_empty() {
+                None
+            } else {
+                let start = trimmed_line.as_ptr() as usize - line.as_ptr() as usize + 1;
+                let end = start + trimmed_line.len() - 1;
+                Some((trimmed_line, start..=end))
+            }
+        }
+        Some(regex) => {
+            let captures = regex.captures(line)?;
+            let m = captures.name("value").or_else(|| captures.get(0))?;
+            let range = m.range();
+            Some((m.as_str(), range.start + 1..=range.end))
+        }
+    }
+}
+
+/// Returns the lines of `content` without the blank lines around them.
+fn trimmed_lines(content: &str) -> Vec<&str> {
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(start, |end| end + 1);
+    lines[start..end].to_vec()
+}
+
+/// Whether the `line` opens or closes a Markdown code fence.
+fn is_code_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// Returns the lines of a block's `content` without the blank lines around them and without the
+/// Markdown code fence they are wrapped in (if any).
+fn unfenced_lines(content: &str) -> Vec<&str> {
+    let lines = trimmed_lines(content);
+    match lines.as_slice() {
+        [first, inner @ .., last] if is_code_fence(first) && is_code_fence(last) => inner.to_vec(),
+        _ => lines,
+    }
+}
+
+/// Returns the `content` of a block with its lines replaced by the `lines`, keeping the blank lines
+/// around them and the Markdown code fence they are wrapped in (if any).
+fn replace_unfenced_lines(content: &str, lines: &[String]) -> String {
+    let new_lines: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    let content_lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let Some(first) = content_lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+    else {
+        // Keep the end tag on its own line.
+        let (before, after) = match content.find('\n') {
+            Some(end) => content.split_at(end + 1),
+            None => ("\n", content),
+        };
+        return format!("{before}{new_lines}{after}");
+    };
+    let last = content_lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .expect("there is a non-blank line");
+    let (first, last) = if first < last
+        && is_code_fence(content_lines[first])
+        && is_code_fence(content_lines[last])
+    {
+        (first + 1, last - 1)
+    } else {
+        (first, last)
+    };
+    let mut result = content_lines[..first].concat();
+    result.push_str(&new_lines);
+    result.push_str(&content_lines[last + 1..].concat());
+    result
+}
+
 pub fn detect_validators<Fs: FileSystem + 'static>(
     context: &ValidationContext,
     detectors: &[(&str, DetectorFactory<Fs>)],
@@ -306,6 +768,40 @@ pub fn detect_validators<Fs: FileSystem + 'static>(
         .collect();
     let mut sync_validators = Vec::new();
     let mut async_validators = Vec::new();
+    if !context.deleted_blocks.is_empty() {
+        let mut undetected = Vec::new();
+        for detector in validator_detectors {
+            match detector.detect_deleted(&context.deleted_blocks)? {
+                Some(ValidatorType::Sync(validator)) => {
+                    sync_validators.push(validator);
+                }
+                Some(ValidatorType::Async(validator)) => {
+                    async_validators.push(validator);
+                }
+                None => {
+                    undetected.push(detector);
+                }
+            }
+        }
+        validator_detectors = undetected;
+    }
+    if !context.changed_files.is_empty() {
+        let mut undetected = Vec::new();
+        for detector in validator_detectors {
+            match detector.detect_changed_files(&context.changed_files, file_system)? {
+                Some(ValidatorType::Sync(validator)) => {
+                    sync_validators.push(validator);
+                }
+                Some(ValidatorType::Async(validator)) => {
+                    async_validators.push(validator);
+                }
+                None => {
+                    undetected.push(detector);
+                }
+            }
+        }
+        validator_detectors = undetected;
+    }
     'outer: for file_blocks in context.blocks.values() {
         for block in &file_blocks.blocks_with_context {
             let mut undetected = Vec::new();
@@ -332,7 +828,7 @@ pub fn detect_validators<Fs: FileSystem + 'static>(
     Ok((sync_validators, async_validators))
 }
 
-pub(in crate::validators) fn parse_affects_attribute(
+pub(crate) fn parse_affects_attribute(
     value: &str,
 ) -> anyhow::Result<Vec<(Option<PathBuf>, String)>> {
     let mut result = Vec::new();
@@ -354,6 +850,51 @@ pub(in crate::validators) fn parse_affects_attribute(
     Ok(result)
 }
 
+/// A block referenced by the `affects` attribute of a validated block, with its content.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct AffectedBlock {
+    pub(crate) file: PathBuf,
+    pub(crate) name: String,
+    pub(crate) content: String,
+}
+
+impl AffectedBlock {
+    /// Returns the reference to the block as written in the `affects` attribute: `file:name`.
+    pub(crate) fn reference(&self) -> String {
+        format!("{}:{}", self.file.display(), self.name)
+    }
+}
+
+/// Resolves the blocks referenced by the `affects` attribute of `block` to their `(file, name,
+/// content)` so they can be checked together with the block.
+///
+/// The blocks which are not in the validation context (e.g. because they are not modified) are
+/// looked up in the whole tree. References to blocks that don't exist are skipped (the `affects`
+/// validator is responsible for reporting those). The content is trimmed to mirror how the
+/// validated block's own content is presented.
+pub(crate) fn resolve_affected_blocks(
+    context: &ValidationContext,
+    current_file_path: &Path,
+    block: &Block,
+) -> anyhow::Result<Vec<AffectedBlock>> {
+    let mut result = Vec::new();
+    let Some(affects) = block.attributes.get("affects") else {
+        return Ok(result);
+    };
+    for (file, name) in parse_affects_attribute(affects)? {
+        let file = file.unwrap_or_else(|| current_file_path.to_path_buf());
+        let Some((affected_block, file_content)) = context.find_block(&file, &name)? else {
+            continue;
+        };
+        result.push(AffectedBlock {
+            file,
+            name,
+            content: affected_block.content(file_content).trim().to_string(),
+        });
+    }
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::blocks::{Block, BlockWithContext, FileSystem};
@@ -713,6 +1254,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn disabled_validators_for_path_are_not_detected_in_that_path() -> anyhow::Result<()> {
+        let context = merge_validation_contexts(vec![
+            validation_context(
+                "vendor/example1.py",
+                r#"# <block fake-sync="condition A" fake-async="condition B">
+    # </block>"#,
+            ),
+            validation_context(
+                "example2.py",
+                r#"# <block fake-sync="condition C">
+    # </block>"#,
+            ),
+        ]);
+        let mut context = Arc::into_inner(context).unwrap();
+
+        context.disable_validators(|path| {
+            if path.starts_with("vendor") {
+                HashSet::from(["fake-async"])
+            } else {
+                HashSet::new()
+            }
+        });
+        let (sync_validators, async_validators) = detect_validators(
+            &context,
+            &detector_factories(),
+            &HashSet::new(),
+            &HashSet::new(),
+            &Arc::new(FakeFileSystem::new(HashMap::new())),
+        )?;
+
+        assert_eq!(sync_validators.len(), 1);
+        assert!(async_validators.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn to_serializable_report_returns_correct_listings() -> anyhow::Result<()> {
         let contents = r#"/* <block name="top"> */ let a = "cc"; /* </block> Block on the first line. */
@@ -776,3 +1353,37 @@ fn b() {}
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod replace_unfenced_lines_tests {
+    use crate::validators::replace_unfenced_lines;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn empty_content_keeps_end_tag_on_its_own_line() {
+        assert_eq!(
+            replace_unfenced_lines("\n", &lines(&["a", "b"])),
+            "\na\nb\n"
+        );
+        assert_eq!(replace_unfenced_lines("", &lines(&["a"])), "\na\n");
+    }
+
+    #[test]
+    fn indentation_of_end_tag_is_kept() {
+        assert_eq!(
+            replace_unfenced_lines("\n    old\n    ", &lines(&["new"])),
+            "\nnew\n    "
+        );
+    }
+
+    #[test]
+    fn code_fence_and_blank_lines_are_kept() {
+        assert_eq!(
+            replace_unfenced_lines("\n\n```text\nold\nolder\n```\n\n", &lines(&["new"])),
+            "\n\n```text\nnew\n```\n\n"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::validators;
+use crate::validators::{
+    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub(crate) struct SyncGroupValidator {}
+
+impl SyncGroupValidator {
+    pub(super) fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Serialize)]
+struct SyncGroupViolation<'a> {
+    group: &'a str,
+    missed_members: Vec<SyncGroupMember<'a>>,
+}
+
+#[derive(Serialize)]
+struct SyncGroupMember<'a> {
+    file_path: &'a Path,
+    name: Option<&'a str>,
+    line: usize,
+}
+
+impl ValidatorSync for SyncGroupValidator {
+    fn validate(
+        &self,
+        context: Arc<validators::ValidationContext>,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut modified_members: BTreeMap<&str, Vec<(&PathBuf, &Block)>> = BTreeMap::new();
+        for (file_path, file_blocks) in &context.blocks {
+            for block_with_context in &file_blocks.blocks_with_context {
+                if !block_with_context.is_content_modified {
+                    // Blocks with unmodified content are not considered modified by this validator.
+                    continue;
+                }
+                if let Some(group) = sync_group(file_path, &block_with_context.block)? {
+                    modified_members
+                        .entry(group)
+                        .or_default()
+                        .push((file_path, &block_with_context.block));
+                }
+            }
+        }
+        if modified_members.is_empty() {
+            return Ok(HashMap::new());
+        }
+        // The members which are not modified are only found in the whole tree.
+        let tree = context.tree()?;
+        let mut missed_members: HashMap<&str, Vec<(&PathBuf, &Block)>> = HashMap::new();
+        for (file_path, file_blocks) in tree {
+            for block_with_context in &file_blocks.blocks_with_context {
+                let block = &block_with_context.block;
+                let Some(group) = sync_group(file_path, block)? else {
+                    continue;
+                };
+                let Some(members) = modified_members.get(group) else {
+                    continue;
+                };
+                let is_modified = members.iter().any(|(member_file_path, member)| {
+                    *member_file_path == file_path
+                        && member.start_tag_position_range == block.start_tag_position_range
+                });
+                if !is_modified {
+                    missed_members
+                        .entry(group)
+                        .or_default()
+                        .push((file_path, block));
+                }
+            }
+        }
+        let mut violations = HashMap::new();
+        for (group, members) in &modified_members {
+            let Some(missed) = missed_members.get_mut(group) else {
+                continue;
+            };
+            missed.sort_by(|(a_path, a), (b_path, b)| {
+                (a_path, a.start_tag_position_range.start())
+                    .cmp(&(b_path, b.start_tag_position_range.start()))
+            });
+            for (file_path, block) in members {
+                violations
+                    .entry((*file_path).clone())
+                    .or_insert_with(Vec::new)
+                    .push(create_violation(file_path, block, group, missed)?);
+            }
+        }
+        Ok(violations)
+    }
+}
+
+/// Returns the sync group of the `block` defined in `file_path` (if any).
+fn sync_group<'a>(file_path: &Path, block: &'a Block) -> anyhow::Result<Option<&'a str>> {
+    let Some(group) = block.attributes.get("sync-group") else {
+        return Ok(None);
+    };
+    let group = group.trim();
+    if group.is_empty() {
+        anyhow::bail!(
+            "sync-group expected a group name in {}:{} at line {}",
+            file_path.display(),
+            block.name_display(),
+            block.start_tag_position_range.start().line
+        );
+    }
+    Ok(Some(group))
+}
+
+fn create_violation(
+    modified_block_file_path: &Path,
+    modified_block: &Block,
+    group: &str,
+    missed_members: &[(&PathBuf, &Block)],
+) -> anyhow::Result<Violation> {
+    let message = format!(
+        "Block {}:{} at line {} is modified, but the other members of sync group \"{}\" are not: {}",
+        modified_block_file_path.display(),
+        modified_block.name_display(),
+        modified_block.start_tag_position_range.start().line,
+        group,
+        missed_members
+            .iter()
+            .map(|(file_path, block)| format!(
+                "{}:{} at line {}",
+                file_path.display(),
+                block.name_display(),
+                block.start_tag_position_range.start().line
+            ))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let details = serde_json::to_value(SyncGroupViolation {
+        group,
+        missed_members: missed_members
+            .iter()
+            .map(|(file_path, block)| SyncGroupMember {
+                file_path,
+                name: block.name(),
+                line: block.start_tag_position_range.start().line,
+            })
+            .collect(),
+    })
+    .context("failed to serialize SyncGroupViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            modified_block.start_tag_position_range.start().clone(),
+            modified_block.start_tag_position_range.end().clone(),
+        ),
+        "sync-group".to_string(),
+        message,
+        modified_block.severity()?,
+        Some(details),
+    ))
+}
+
+pub(crate) struct SyncGroupValidatorDetector();
+
+impl SyncGroupValidatorDetector {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl<Fs: FileSystem> ValidatorDetector<Fs> for SyncGroupValidatorDetector {
+    fn detect(
+        &self,
+        block_with_context: &BlockWithContext,
+        _file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        if block_with_context.is_content_modified
+            && block_with_context
+                .block
+                .attributes
+                .contains_key("sync-group")
+        {
+            Ok(Some(ValidatorType::Sync(Box::new(
+                SyncGroupValidator::new(),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{messages, validation_context_from_changes};
+
+    const ENUM: &str = "# <block sync-group=\"colors\">\nRED\n# </block>\n";
+    const DOCS: &str =
+        "<!-- <block name=\"colors\" sync-group=\"colors\"> -->\nRED\n<!-- </block> -->\n";
+    const PROTO: &str = "// <block sync-group=\"colors\">\nRED = 0;\n// </block>\n";
+
+    #[test]
+    fn all_members_modified_returns_ok() -> anyhow::Result<()> {
+        let validator = SyncGroupValidator::new();
+        let context = validation_context_from_changes(
+            &[("a.py", ENUM), ("b.md", DOCS), ("c.proto", PROTO)],
+            &[
+                ("a.py", &ENUM.replace("RED", "BLUE")),
+                ("b.md", &DOCS.replace("RED", "BLUE")),
+                ("c.proto", &PROTO.replace("RED", "BLUE")),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn missed_members_return_violation() -> anyhow::Result<()> {
+        let validator = SyncGroupValidator::new();
+        let context = validation_context_from_changes(
+            &[("a.py", ENUM), ("b.md", DOCS), ("c.proto", PROTO)],
+            &[
+                ("a.py", &ENUM.replace("RED", "BLUE")),
+                ("b.md", DOCS),
+                ("c.proto", PROTO),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:(unnamed) at line 1 is modified, but the other members of sync group \"colors\" are not: b.md:colors at line 1, c.proto:(unnamed) at line 1"
+            ]
+        );
+        let violation = &violations[Path::new("a.py")][0];
+        assert_eq!(violation.code(), "sync-group");
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({
+                "group": "colors",
+                "missed_members": [
+                    {"file_path": "b.md", "name": "colors", "line": 1},
+                    {"file_path": "c.proto", "name": null, "line": 1},
+                ]
+            }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn every_modified_member_returns_violation() -> anyhow::Result<()> {
+        let validator = SyncGroupValidator::new();
+        let context = validation_context_from_changes(
+            &[("a.py", ENUM), ("b.md", DOCS), ("c.proto", PROTO)],
+            &[
+                ("a.py", &ENUM.replace("RED", "BLUE")),
+                ("b.md", &DOCS.replace("RED", "BLUE")),
+                ("c.proto", PROTO),
+            ],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:(unnamed) at line 1 is modified, but the other members of sync group \"colors\" are not: c.proto:(unnamed) at line 1"
+            ]
+        );
+        assert_eq!(
+            messages(&violations, "b.md"),
+            vec![
+                "Block b.md:colors at line 1 is modified, but the other members of sync group \"colors\" are not: c.proto:(unnamed) at line 1"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn members_of_other_groups_are_ignored() -> anyhow::Result<()> {
+        let validator = SyncGroupValidator::new();
+        let sizes = "# <block sync-group=\"sizes\">\nS\n# </block>\n";
+        let context = validation_context_from_changes(
+            &[("a.py", ENUM), ("b.py", sizes)],
+            &[("a.py", &ENUM.replace("RED", "BLUE")), ("b.py", sizes)],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn empty_group_returns_error() {
+        let validator = SyncGroupValidator::new();
+        let context = validation_context_from_changes(
+            &[("a.py", "# <block sync-group=\" \">\nRED\n# </block>\n")],
+            &[("a.py", "# <block sync-group=\" \">\nBLUE\n# </block>\n")],
+        );
+
+        let result = validator.validate(context);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "sync-group expected a group name in a.py:(unnamed) at line 1"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use crate::blocks::{Block, BlockWithContext, FileSystem};
+use crate::validators;
+use crate::validators::{
+    ValidatorDetector, ValidatorSync, ValidatorType, Violation, ViolationRange,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub(crate) struct UniqueNamesValidator {}
+
+impl UniqueNamesValidator {
+    pub(super) fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(Serialize)]
+struct UniqueNamesViolation<'a> {
+    name: &'a str,
+    duplicate_lines: Vec<usize>,
+}
+
+impl ValidatorSync for UniqueNamesValidator {
+    fn validate(
+        &self,
+        context: Arc<validators::ValidationContext>,
+    ) -> anyhow::Result<HashMap<PathBuf, Vec<Violation>>> {
+        let mut violations = HashMap::new();
+        for (file_path, file_blocks) in &context.blocks {
+            if context.is_validator_disabled(file_path, "unique-names")
+                || !file_blocks
+                    .blocks_with_context
+                    .iter()
+                    .any(|block_with_context| block_with_context.block.name().is_some())
+            {
+                continue;
+            }
+            // The blocks which are not modified are not in the context, but their names are taken
+            // all the same.
+            let all_blocks = context.all_file_blocks(file_path)?;
+            let mut blocks_by_name: HashMap<&str, Vec<&Block>> = HashMap::new();
+            for block in &all_blocks {
+                if let Some(name) = block.name() {
+                    blocks_by_name.entry(name).or_default().push(block);
+                }
+            }
+            let mut file_violations = Vec::new();
+            for (name, blocks) in blocks_by_name {
+                if blocks.len() < 2 {
+                    continue;
+                }
+                let is_in_context = |block: &Block| {
+                    file_blocks
+                        .blocks_with_context
+                        .iter()
+                        .any(|block_with_context| {
+                            block_with_context.block.start_tag_position_range
+                                == block.start_tag_position_range
+                        })
+                };
+                if !blocks.iter().any(|block| is_in_context(block)) {
+                    // Existing duplicates are only reported once one of them is changed.
+                    continue;
+                }
+                for block in &blocks {
+                    let duplicate_lines = blocks
+                        .iter()
+                        .filter(|other| {
+                            other.start_tag_position_range != block.start_tag_position_range
+                        })
+                        .map(|other| other.start_tag_position_range.start().line)
+                        .collect();
+                    file_violations.push(create_violation(
+                        file_path,
+                        block,
+                        name,
+                        duplicate_lines,
+                    )?);
+                }
+            }
+            if !file_violations.is_empty() {
+                file_violations.sort_by(|a, b| a.range().start().cmp(b.range().start()));
+                violations.insert(file_path.clone(), file_violations);
+            }
+        }
+        Ok(violations)
+    }
+}
+
+fn create_violation(
+    block_file_path: &Path,
+    block: &Block,
+    name: &str,
+    duplicate_lines: Vec<usize>,
+) -> anyhow::Result<Violation> {
+    let (blocks, lines) = if duplicate_lines.len() == 1 {
+        ("block", "line")
+    } else {
+        ("blocks", "lines")
+    };
+    let message = format!(
+        "Block {}:{} at line {} has the same name as the {blocks} at {lines} {}",
+        block_file_path.display(),
+        name,
+        block.start_tag_position_range.start().line,
+        duplicate_lines
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let details = serde_json::to_value(UniqueNamesViolation {
+        name,
+        duplicate_lines,
+    })
+    .context("failed to serialize UniqueNamesViolation block")?;
+    Ok(Violation::new(
+        ViolationRange::new(
+            block.start_tag_position_range.start().clone(),
+            block.start_tag_position_range.end().clone(),
+        ),
+        "unique-names".to_string(),
+        message,
+        block.severity()?,
+        Some(details),
+    ))
+}
+
+pub(crate) struct UniqueNamesValidatorDetector();
+
+impl UniqueNamesValidatorDetector {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl<Fs: FileSystem> ValidatorDetector<Fs> for UniqueNamesValidatorDetector {
+    fn detect(
+        &self,
+        block_with_context: &BlockWithContext,
+        _file_system: &Arc<Fs>,
+    ) -> anyhow::Result<Option<ValidatorType>> {
+        // Unlike the other validators this one is not enabled by an attribute: every name must be
+        // unique within its file.
+        if block_with_context.block.name().is_some() {
+            Ok(Some(ValidatorType::Sync(Box::new(
+                UniqueNamesValidator::new(),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff_parser::LineChange;
+    use crate::test_utils::{messages, validation_context, validation_context_with_changes};
+    use std::collections::HashSet;
+
+    #[test]
+    fn unique_names_return_ok() -> anyhow::Result<()> {
+        let validator = UniqueNamesValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"# <block name="foo">
+# </block>
+# <block name="bar">
+# </block>
+# <block>
+# </block>
+# <block>
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_names_return_violations_at_both_start_tags() -> anyhow::Result<()> {
+        let validator = UniqueNamesValidator::new();
+        let context = validation_context(
+            "a.py",
+            r#"# <block name="foo">
+# </block>
+# <block name="bar">
+# </block>
+# <block name="foo">
+# </block>
+"#,
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:foo at line 1 has the same name as the block at line 5",
+                "Block a.py:foo at line 5 has the same name as the block at line 1",
+            ]
+        );
+        let violation = &violations[Path::new("a.py")][1];
+        assert_eq!(violation.code(), "unique-names");
+        assert_eq!(violation.range().start(), &crate::Position::new(5, 3));
+        assert_eq!(
+            violation.data(),
+            Some(&serde_json::json!({"name": "foo", "duplicate_lines": [1]}))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_of_unmodified_block_returns_violations() -> anyhow::Result<()> {
+        let validator = UniqueNamesValidator::new();
+        let context = validation_context_with_changes(
+            "a.py",
+            r#"# <block name="foo">
+a
+# </block>
+# <block name="foo">
+b
+# </block>
+# <block name="foo">
+c
+# </block>
+"#,
+            vec![LineChange {
+                line: 8,
+                ranges: None,
+            }],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert_eq!(
+            messages(&violations, "a.py"),
+            vec![
+                "Block a.py:foo at line 1 has the same name as the blocks at lines 4, 7",
+                "Block a.py:foo at line 4 has the same name as the blocks at lines 1, 7",
+                "Block a.py:foo at line 7 has the same name as the blocks at lines 1, 4",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unchanged_duplicates_return_ok() -> anyhow::Result<()> {
+        let validator = UniqueNamesValidator::new();
+        let context = validation_context_with_changes(
+            "a.py",
+            r#"# <block name="foo">
+# </block>
+# <block name="foo">
+# </block>
+# <block name="bar">
+b
+# </block>
+"#,
+            vec![LineChange {
+                line: 6,
+                ranges: None,
+            }],
+        );
+
+        let violations = validator.validate(context)?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn disabled_for_file_returns_ok() -> anyhow::Result<()> {
+        let validator = UniqueNamesValidator::new();
+        let mut context = Arc::into_inner(validation_context(
+            "a.py",
+            "# <block name=\"foo\">\n# </block>\n# <block name=\"foo\">\n# </block>\n",
+        ))
+        .unwrap();
+        context.disable_validators(|_| HashSet::from(["unique-names"]));
+
+        let violations = validator.validate(Arc::new(context))?;
+
+        assert!(violations.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn detector_detects_named_blocks_only() -> anyhow::Result<()> {
+        let detector = UniqueNamesValidatorDetector::new();
+        let file_system = Arc::new(crate::test_utils::FakeFileSystem::new(HashMap::new()));
+        let context = validation_context(
+            "a.py",
+            "# <block>\n# </block>\n# <block name=\"foo\">\n# </block>\n",
+        );
+        let blocks = &context.blocks[Path::new("a.py")].blocks_with_context;
+
+        assert!(detector.detect(&blocks[0], &file_system)?.is_none());
+        assert!(detector.detect(&blocks[1], &file_system)?.is_some());
+        Ok(())
+    }
+}
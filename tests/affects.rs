@@ -3,6 +3,8 @@ use assert_cmd::cargo_bin_cmd;
 use predicates::prelude::predicate;
 use serde_json::json;
 
+mod common;
+
 #[test]
 fn diff_with_unsatisfied_blocks_fails() {
     let diff_content = r#"
@@ -228,3 +230,176 @@ index abc123..def456 100644
             true
         }));
 }
+
+#[test]
+fn diff_deleting_referenced_block_fails() {
+    let dir = common::repository(&[(
+        "a.py",
+        "# <block affects=\"b.py:foo\">\nprint(\"a\")\n# </block>\n",
+    )]);
+    let diff_content = r#"diff --git a/b.py b/b.py
+deleted file mode 100644
+--- a/b.py
++++ /dev/null
+@@ -1,3 +0,0 @@
+-# <block name="foo">
+-print("foo")
+-# </block>
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "a.py": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 3},
+                        "end": {"line": 1, "character": 28}
+                      },
+                      "code": "affects",
+                      "message": "Block a.py:(unnamed) at line 1 affects b.py:foo which is deleted",
+                      "severity": 1,
+                      "data": {
+                        "deleted_block_file_path": "b.py",
+                        "deleted_block_name": "foo",
+                      }
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn diff_deleting_block_with_unmodified_affected_block_fails() {
+    let dir = common::repository(&[
+        ("a.py", "print(\"a\")\n"),
+        (
+            "b.py",
+            "# <block name=\"foo\">\nprint(\"foo\")\n# </block>\n",
+        ),
+    ]);
+    let diff_content = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -1,3 +1,1 @@
+-# <block affects="b.py:foo">
+ print("a")
+-# </block>
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Block a.py:(unnamed) at line 1 is deleted, but b.py:foo is not modified",
+        ));
+}
+
+#[test]
+fn check_refs_with_dangling_reference_fails() {
+    let dir = common::repository(&[
+        (
+            "a.py",
+            "# <block affects=\"docs/api.md:missing-name\">\nprint(\"a\")\n# </block>\n",
+        ),
+        ("b.py", "print(\"b\")\n"),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("check-refs");
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "a.py": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 3},
+                        "end": {"line": 1, "character": 44}
+                      },
+                      "code": "affects",
+                      "message": "Block a.py:(unnamed) at line 1 affects docs/api.md:missing-name, but docs/api.md does not exist",
+                      "severity": 1,
+                      "data": {
+                        "reference": "docs/api.md:missing-name",
+                        "problem": "missing-file",
+                      }
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn check_refs_with_resolved_references_succeeds() {
+    let dir = common::repository(&[
+        (
+            "a.py",
+            "# <block affects=\"b.py:foo\">\nprint(\"a\")\n# </block>\n",
+        ),
+        (
+            "b.py",
+            "# <block name=\"foo\">\nprint(\"foo\")\n# </block>\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("check-refs");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
+
+#[test]
+fn check_refs_resolves_references_to_ignored_files() {
+    let dir = common::repository(&[
+        (
+            "a.py",
+            "# <block affects=\"b.py:foo\">\nprint(\"a\")\n# </block>\n",
+        ),
+        (
+            "b.py",
+            "# <block name=\"foo\">\nprint(\"foo\")\n# </block>\n# <block affects=\":missing\">\n# </block>\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .args(["check-refs", "--ignore", "b.py"]);
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
@@ -1,15 +1,47 @@
 use assert_cmd::assert::OutputAssertExt;
 use assert_cmd::cargo_bin_cmd;
+use axum::http::{HeaderMap, StatusCode};
 use axum::{Json, Router, routing::post};
+use predicates::prelude::{PredicateBooleanExt, predicate};
 use serde_json::{Value, json};
+use std::fs;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::net::TcpListener;
 
+mod common;
+
 // <block name="check-ai-env-vars">
 const API_KEY_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_API_KEY";
 const API_URL_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_API_URL";
+const PROVIDER_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_PROVIDER";
+const RESPONSE_PATH_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_RESPONSE_PATH";
+const AI_MODE_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_MODE";
+const AI_CONCURRENCY_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_CONCURRENCY";
+const AI_MAX_RETRIES_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_MAX_RETRIES";
+const AI_TIMEOUT_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_TIMEOUT";
+const AI_ON_ERROR_ENV_VAR_NAME: &str = "BLOCKWATCH_AI_ON_ERROR";
 // </block>
 
+async fn serve(app: Router) -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (addr, handle)
+}
+
+/// Returns the reply of the fake AI servers to a block: OK if it mentions a banana.
+fn fake_ai_reply(block: &str) -> &'static str {
+    if block.to_lowercase().contains("banana") {
+        "OK"
+    } else {
+        "The block does not mention 'banana'. Add it."
+    }
+}
+
 async fn start_fake_openai() -> (SocketAddr, tokio::task::JoinHandle<()>) {
     async fn chat_completions(Json(payload): Json<Value>) -> Json<Value> {
         let mut user_content = String::new();
@@ -24,7 +56,7 @@ async fn start_fake_openai() -> (SocketAddr, tokio::task::JoinHandle<()>) {
             }
         }
         // Extract only the BLOCK content portion from the user message to avoid matching the CONDITION text.
-        let marker = "BLOCK (formatting preserved):\n";
+        let marker = "BLOCK (formatting preserved, lines prefixed with their numbers):\n";
         let block_only = if let Some(pos) = user_content.find(marker) {
             &user_content[pos + marker.len()..]
         } else {
@@ -71,6 +103,7 @@ async fn check_ai_ok_succeeds() {
 
     // Configure client to use fake server for this command only
     let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
     cmd.env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"));
     cmd.env(API_KEY_ENV_VAR_NAME, "test-key");
 
@@ -97,6 +130,7 @@ async fn check_ai_violation_fails_and_reports_message() {
     let (addr, _handle) = start_fake_openai().await;
 
     let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
     cmd.env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"));
     cmd.env(API_KEY_ENV_VAR_NAME, "test-key");
 
@@ -157,6 +191,7 @@ async fn when_api_key_is_empty_error_is_printed() {
 
     // Configure client to use fake server for this command only
     let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
     cmd.env_clear()
         .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"));
 
@@ -183,3 +218,613 @@ index 54d1d99..a95a452 100644
             output.contains("API key is empty.")
         }));
 }
+
+const MANGO_DIFF: &str = r#"
+diff --git a/tests/testdata/check_ai.py b/tests/testdata/check_ai.py
+index 1111111..2222222 100644
+--- a/tests/testdata/check_ai.py
++++ b/tests/testdata/check_ai.py
+@@ -5,5 +5,5 @@
+ # </block>
+
+ # <block check-ai="must mention mango">
+-another_text = "I like apple"
++another_text = "I like pear"
+ # </block>
+"#;
+
+const BANANA_DIFF: &str = r#"
+diff --git a/tests/testdata/check_ai.py b/tests/testdata/check_ai.py
+index 54d1d99..a95a452 100644
+--- a/tests/testdata/check_ai.py
++++ b/tests/testdata/check_ai.py
+@@ -1,5 +1,5 @@
+ # AI check integration
+
+ # <block check-ai="must mention banana">
+-s = "I like mangoes"
++s = "I like bananas"
+ # </block>
+"#;
+
+async fn start_fake_anthropic() -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    async fn messages(
+        headers: HeaderMap,
+        Json(payload): Json<Value>,
+    ) -> Result<Json<Value>, (StatusCode, String)> {
+        if headers.get("x-api-key").and_then(|v| v.to_str().ok()) != Some("test-key")
+            || headers.get("anthropic-version").is_none()
+        {
+            return Err((StatusCode::UNAUTHORIZED, "invalid x-api-key".to_string()));
+        }
+        assert!(payload["system"].as_str().is_some());
+        assert!(payload["max_tokens"].as_u64().is_some());
+        let user_content = payload["messages"][0]["content"].as_str().unwrap();
+        let block = user_content
+            .split("BLOCK (formatting preserved, lines prefixed with their numbers):\n")
+            .nth(1)
+            .unwrap();
+        Ok(Json(json!({
+            "id": "msg_test",
+            "type": "message",
+            "role": "assistant",
+            "model": payload["model"],
+            "content": [{"type": "text", "text": fake_ai_reply(block)}],
+            "stop_reason": "end_turn"
+        })))
+    }
+
+    serve(Router::new().route("/v1/messages", post(messages))).await
+}
+
+async fn start_fake_http_ai() -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    async fn check(Json(payload): Json<Value>) -> Json<Value> {
+        assert!(payload["prompt"].as_str().unwrap().contains("CONDITION:"));
+        assert!(payload["condition"].as_str().is_some());
+        Json(json!({"result": {"text": fake_ai_reply(payload["block"].as_str().unwrap())}}))
+    }
+
+    serve(Router::new().route("/check", post(check))).await
+}
+
+/// Replies with a finding for every numbered block line without a banana.
+async fn start_fake_structured_ai() -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    async fn check(Json(payload): Json<Value>) -> Json<Value> {
+        assert!(payload["response_schema"].is_object());
+        let prompt = payload["prompt"].as_str().unwrap();
+        let block = prompt
+            .split("BLOCK (formatting preserved, lines prefixed with their numbers):\n")
+            .nth(1)
+            .unwrap();
+        let findings: Vec<Value> = block
+            .lines()
+            .filter_map(|line| line.split_once("| "))
+            .filter(|(_, line)| !line.contains("banana"))
+            .map(|(number, line)| {
+                json!({
+                    "line": number.parse::<usize>().unwrap(),
+                    "message": "The line does not mention 'banana'.",
+                    "suggestion": format!("{line} # banana")
+                })
+            })
+            .collect();
+        Json(json!({"response": json!({"findings": findings}).to_string()}))
+    }
+
+    serve(Router::new().route("/check", post(check))).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn structured_findings_are_reported_at_their_lines() {
+    let (addr, _handle) = start_fake_structured_ai().await;
+    let dir = common::repository(&[(
+        "example.py",
+        "# <block check-ai=\"every line must mention banana\">\nfirst = \"banana\"\nsecond = \"pear\"\n# </block>\n",
+    )]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .arg("--no-ai-cache")
+        .env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/check"))
+        .arg("example.py");
+    let output = cmd.output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let output_json: Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(
+        output_json,
+        json!({
+          "example.py": [
+            {
+              "range": {
+                "start": {"line": 3, "character": 1},
+                "end": {"line": 3, "character": 15}
+              },
+              "code": "check-ai",
+              "message": "Block example.py:(unnamed) defined at line 1 failed AI check: The line does not mention 'banana'.",
+              "severity": 1,
+              "data": {
+                "condition": "every line must mention banana",
+                "ai_message": "The line does not mention 'banana'.",
+                "suggestion": "second = \"pear\" # banana"
+              }
+            }
+          ]
+        })
+    );
+}
+
+/// Replies with a finding for every affected block which doesn't mention the block content.
+async fn start_fake_drift_ai() -> (SocketAddr, tokio::task::JoinHandle<()>) {
+    async fn check(Json(payload): Json<Value>) -> Json<Value> {
+        assert!(
+            payload["prompt"]
+                .as_str()
+                .unwrap()
+                .contains("AFFECTED BLOCK ")
+        );
+        let block = payload["block"].as_str().unwrap();
+        let findings: Vec<Value> = payload["affected_blocks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|affected_block| !affected_block["content"].as_str().unwrap().contains(block))
+            .map(|affected_block| {
+                json!({
+                    "line": null,
+                    "message": format!("{block} is not documented."),
+                    "suggestion": null,
+                    "affected_block": format!(
+                        "{}:{}",
+                        affected_block["file"].as_str().unwrap(),
+                        affected_block["name"].as_str().unwrap()
+                    )
+                })
+            })
+            .collect();
+        Json(json!({"response": json!({"findings": findings}).to_string()}))
+    }
+
+    serve(Router::new().route("/check", post(check))).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn affected_blocks_are_checked_with_the_block() {
+    let (addr, _handle) = start_fake_drift_ai().await;
+    let dir = common::repository(&[
+        (
+            "fruit.py",
+            "# <block check-ai=\"the docs must describe the value\" affects=\"README.md:fruits, README.md:colors\">\nBANANA\n# </block>\n",
+        ),
+        (
+            "README.md",
+            "<!-- <block name=\"fruits\"> -->\n- APPLE\n<!-- </block> -->\n<!-- <block name=\"colors\"> -->\n- BANANA is yellow\n<!-- </block> -->\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .arg("--no-ai-cache")
+        .env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/check"))
+        .arg("fruit.py");
+    cmd.output()
+        .unwrap()
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check of the affected block README.md:fruits: BANANA is not documented.",
+        ))
+        .stderr(predicate::str::contains("README.md:colors").not());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn anthropic_provider_reports_violation() {
+    let (addr, _handle) = start_fake_anthropic().await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "anthropic")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"))
+        .env(API_KEY_ENV_VAR_NAME, "test-key");
+
+    cmd.write_stdin(MANGO_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: The block does not mention 'banana'. Add it.",
+        ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn anthropic_provider_ok_succeeds() {
+    let (addr, _handle) = start_fake_anthropic().await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "anthropic")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"))
+        .env(API_KEY_ENV_VAR_NAME, "test-key");
+
+    cmd.write_stdin(BANANA_DIFF).assert().success();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn anthropic_provider_error_status_is_printed() {
+    let (addr, _handle) = start_fake_anthropic().await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "anthropic")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"))
+        .env(API_KEY_ENV_VAR_NAME, "wrong-key");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "AI API request failed with 401 Unauthorized: invalid x-api-key",
+        ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ollama_provider_does_not_require_api_key() {
+    let (addr, _handle) = start_fake_openai().await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env_remove(API_KEY_ENV_VAR_NAME)
+        .env(PROVIDER_ENV_VAR_NAME, "ollama")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"));
+
+    cmd.write_stdin(MANGO_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: The block does not mention 'banana'. Add it.",
+        ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn http_provider_reads_reply_at_response_path() {
+    let (addr, _handle) = start_fake_http_ai().await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/check"))
+        .env(RESPONSE_PATH_ENV_VAR_NAME, "/result/text");
+
+    cmd.write_stdin(MANGO_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: The block does not mention 'banana'. Add it.",
+        ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn http_provider_with_wrong_response_path_returns_error() {
+    let (addr, _handle) = start_fake_http_ai().await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/check"))
+        .env_remove(RESPONSE_PATH_ENV_VAR_NAME);
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "AI API response has no string at \"/response\"",
+        ));
+}
+
+#[test]
+fn http_provider_without_url_returns_error() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "http")
+        .env_remove(API_URL_ENV_VAR_NAME);
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "BLOCKWATCH_AI_API_URL must be set when BLOCKWATCH_AI_PROVIDER is \"http\"",
+        ));
+}
+
+#[test]
+fn unsupported_provider_returns_error() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache");
+    cmd.env(PROVIDER_ENV_VAR_NAME, "skynet");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "unsupported BLOCKWATCH_AI_PROVIDER \"skynet\", expected one of: openai, anthropic, ollama, http",
+        ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn responses_are_cached_between_runs() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let app = Router::new().route(
+        "/v1/chat/completions",
+        post({
+            let requests = Arc::clone(&requests);
+            move |Json(payload): Json<Value>| async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                Json(json!({
+                    "id": "chatcmpl-test",
+                    "object": "chat.completion",
+                    "created": 1_700_000_000u64,
+                    "model": payload["model"],
+                    "choices": [{
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "Mention a banana."},
+                        "finish_reason": "stop"
+                    }]
+                }))
+            }
+        }),
+    );
+    let (addr, _handle) = serve(app).await;
+    let dir = common::repository(&[(
+        "example.py",
+        "# <block check-ai=\"must mention banana\">\ns = \"I like pears\"\n# </block>\n",
+    )]);
+    let run = |args: &[&str]| {
+        let mut cmd = cargo_bin_cmd!();
+        cmd.current_dir(dir.path())
+            .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"))
+            .env(API_KEY_ENV_VAR_NAME, "test-key")
+            .args(args)
+            .arg("example.py");
+        cmd.output().unwrap()
+    };
+
+    run(&["-v"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: Mention a banana.",
+        ))
+        .stderr(predicate::str::contains("cache hit").not());
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        fs::read_dir(dir.path().join(".blockwatch/cache/check-ai"))
+            .unwrap()
+            .count(),
+        1
+    );
+
+    run(&["-v"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: Mention a banana.",
+        ))
+        .stderr(predicate::str::contains(
+            "check-ai cache hit for example.py:(unnamed) at line 1",
+        ));
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    run(&["--no-ai-cache"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: Mention a banana.",
+        ));
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recorded_responses_are_replayed_without_api_key() {
+    let (addr, _handle) = start_fake_openai().await;
+    let dir = common::repository(&[(
+        "example.py",
+        "# <block check-ai=\"must mention banana\">\ns = \"I like pears\"\n# </block>\n",
+    )]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env(AI_MODE_ENV_VAR_NAME, "record")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/v1"))
+        .env(API_KEY_ENV_VAR_NAME, "test-key")
+        .arg("example.py");
+    cmd.output()
+        .unwrap()
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: The block does not mention 'banana'. Add it.",
+        ));
+    let fixtures: Value = serde_json::from_str(
+        &fs::read_to_string(dir.path().join(".blockwatch/ai-fixtures.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        fixtures,
+        json!([{
+            "condition": "must mention banana",
+            "block": "s = \"I like pears\"",
+            "findings": [{
+                "line": null,
+                "message": "The block does not mention 'banana'. Add it.",
+                "suggestion": null
+            }]
+        }])
+    );
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env(AI_MODE_ENV_VAR_NAME, "replay")
+        .env(API_URL_ENV_VAR_NAME, "http://127.0.0.1:1/v1")
+        .env_remove(API_KEY_ENV_VAR_NAME)
+        .arg("example.py");
+    cmd.output()
+        .unwrap()
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "failed AI check: The block does not mention 'banana'. Add it.",
+        ));
+
+    fs::write(
+        dir.path().join("example.py"),
+        "# <block check-ai=\"must mention banana\">\ns = \"I like bananas\"\n# </block>\n",
+    )
+    .unwrap();
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env(AI_MODE_ENV_VAR_NAME, "replay")
+        .env_remove(API_KEY_ENV_VAR_NAME)
+        .arg("example.py");
+    cmd.output()
+        .unwrap()
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "no response to the condition \"must mention banana\" for the current block content is recorded in",
+        ))
+        .stderr(predicate::str::contains(
+            "record it with BLOCKWATCH_AI_MODE=record",
+        ));
+}
+
+#[test]
+fn unsupported_ai_mode_returns_error() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache")
+        .env(AI_MODE_ENV_VAR_NAME, "offline");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "unsupported BLOCKWATCH_AI_MODE \"offline\", expected one of: live, record, replay",
+        ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limited_requests_are_retried() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let app = Router::new().route(
+        "/check",
+        post({
+            let requests = Arc::clone(&requests);
+            move |Json(payload): Json<Value>| async move {
+                if requests.fetch_add(1, Ordering::SeqCst) < 2 {
+                    return Err((
+                        StatusCode::TOO_MANY_REQUESTS,
+                        [("retry-after", "0")],
+                        "slow down",
+                    ));
+                }
+                Ok(Json(
+                    json!({"response": fake_ai_reply(payload["block"].as_str().unwrap())}),
+                ))
+            }
+        }),
+    );
+    let (addr, _handle) = serve(app).await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache")
+        .arg("-v")
+        .env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/check"))
+        .env(AI_CONCURRENCY_ENV_VAR_NAME, "1")
+        .env(AI_TIMEOUT_ENV_VAR_NAME, "10");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "check-ai request failed, retrying in 0.0s: AI API request failed with 429 Too Many Requests: slow down",
+        ));
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exhausted_retries_fail_the_run() {
+    let app = Router::new().route(
+        "/check",
+        post(|| async {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [("retry-after", "0")],
+                "down",
+            )
+        }),
+    );
+    let (addr, _handle) = serve(app).await;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache")
+        .env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, format!("http://{addr}/check"))
+        .env(AI_MAX_RETRIES_ENV_VAR_NAME, "1");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("AI API request failed 2 times"))
+        .stderr(predicate::str::contains(
+            "AI API request failed with 503 Service Unavailable: down",
+        ));
+}
+
+#[test]
+fn provider_failures_are_warnings_in_warn_mode() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache")
+        .env(PROVIDER_ENV_VAR_NAME, "http")
+        .env(API_URL_ENV_VAR_NAME, "http://127.0.0.1:1/check")
+        .env(AI_MAX_RETRIES_ENV_VAR_NAME, "0")
+        .env(AI_ON_ERROR_ENV_VAR_NAME, "warn");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "\"message\": \"Block tests/testdata/check_ai.py:(unnamed) defined at line 3 could not be checked by AI: AI API request failed",
+        ))
+        .stderr(predicate::str::contains("\"severity\": 2"));
+}
+
+#[test]
+fn unsupported_on_error_value_returns_error() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.arg("--no-ai-cache")
+        .env(AI_ON_ERROR_ENV_VAR_NAME, "ignore");
+
+    cmd.write_stdin(BANANA_DIFF)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "unsupported BLOCKWATCH_AI_ON_ERROR \"ignore\", expected one of: fail, warn",
+        ));
+}
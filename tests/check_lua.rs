@@ -1,7 +1,10 @@
 use assert_cmd::assert::OutputAssertExt;
 use assert_cmd::cargo_bin_cmd;
-use predicates::prelude::predicate;
+use predicates::prelude::{PredicateBooleanExt, predicate};
 use serde_json::json;
+use std::fs;
+
+mod common;
 
 const LUA_STDLIB_ENV_VAR: &str = "BLOCKWATCH_LUA_MODE";
 
@@ -264,3 +267,173 @@ index 1111111..2222222 100644
 
     output.assert().success();
 }
+
+#[test]
+fn fix_subcommand_rewrites_block_generated_by_lua_script() {
+    let dir = common::repository(&[
+        (
+            "colors.rs",
+            "enum Color {\n    // <block name=\"colors\">\n    Red,\n    Blue,\n    // </block>\n}\n",
+        ),
+        (
+            "table.lua",
+            r#"
+function generate(ctx)
+    local rows = {"| Color |", "|-------|"}
+    for variant in ctx.affects[1].content:gmatch("(%w+),") do
+        table.insert(rows, "| " .. variant .. " |")
+    end
+    return table.concat(rows, "\n")
+end
+"#,
+        ),
+        (
+            "README.md",
+            "<!-- <block check-lua=\"table.lua\" affects=\"colors.rs:colors\"> -->\n| Color |\n|-------|\n| Red |\n<!-- </block> -->\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("README.md");
+    cmd.output()
+        .unwrap()
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "differs from the content generated by table.lua",
+        ));
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).args(["fix", "README.md"]);
+    cmd.output()
+        .unwrap()
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Fixed README.md"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        "<!-- <block check-lua=\"table.lua\" affects=\"colors.rs:colors\"> -->\n| Color |\n|-------|\n| Red |\n| Blue |\n<!-- </block> -->\n"
+    );
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("README.md");
+    cmd.output().unwrap().assert().success();
+}
+
+#[test]
+fn blockwatch_module_is_available_in_sandboxed_mode() {
+    let dir = common::repository(&[
+        (
+            "versions.lua",
+            r#"
+function validate(ctx, content)
+    local config = blockwatch.json.decode(content)
+    if not blockwatch.regex.is_match([[^\d+\.\d+\.\d+$]], config.version) then
+        return "invalid version " .. config.version
+    end
+    return nil
+end
+"#,
+        ),
+        (
+            "config.md",
+            "<!-- <block check-lua=\"versions.lua\" check-lua-pattern=\"\\{.*\\}\"> -->\n```json\n{\"version\": \"1.2\"}\n```\n<!-- </block> -->\n",
+        ),
+        (
+            "versions.md",
+            "<!-- <block check-lua=\"versions.lua\" check-lua-pattern=\"\\{.*\\}\"> -->\n```json\n{\"version\": \"1.2.3\"}\n```\n<!-- </block> -->\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("versions.md");
+    cmd.output().unwrap().assert().success();
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("config.md");
+    cmd.output()
+        .unwrap()
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("invalid version 1.2"));
+}
+
+#[test]
+fn violation_tables_are_reported_at_their_positions() {
+    let dir = common::repository(&[
+        (
+            "numbers.lua",
+            r#"
+function validate(ctx, content)
+    local violations = {}
+    local line = 0
+    for text in (content .. "\n"):gmatch("(.-)\n") do
+        line = line + 1
+        if not tonumber(text) then
+            local severity = text == "two" and "warning" or nil
+            table.insert(violations, { message = text .. " is not a number", line = line, severity = severity })
+        end
+    end
+    return violations
+end
+"#,
+        ),
+        (
+            "numbers.py",
+            "# <block check-lua=\"numbers.lua\">\n1\ntwo\n3\nfour\n# </block>\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("numbers.py");
+    let output = cmd.output().unwrap();
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value =
+                serde_json::from_str(output).expect("invalid json");
+            let violation = |line: usize, end: usize, text: &str, severity: u8| {
+                json!({
+                  "range": {
+                    "start": {"line": line, "character": 1},
+                    "end": {"line": line, "character": end}
+                  },
+                  "code": "check-lua",
+                  "message": format!("Block numbers.py:(unnamed) defined at line 1 failed Lua check: {text} is not a number"),
+                  "severity": severity,
+                  "data": {
+                    "script": "numbers.lua",
+                    "lua_error": format!("{text} is not a number")
+                  }
+                })
+            };
+            output_json == json!({"numbers.py": [violation(3, 3, "two", 2), violation(5, 4, "four", 1)]})
+        }));
+}
+
+#[test]
+fn script_exceeding_the_timeout_fails_instead_of_hanging() {
+    let dir = common::repository(&[
+        (
+            "loop.lua",
+            "function validate(ctx, content) while true do end end",
+        ),
+        (
+            "example.py",
+            "# <block name=\"looping\" check-lua=\"loop.lua\" check-lua-timeout=\"1\">\ntext\n# </block>\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("example.py");
+    cmd.output().unwrap().assert().failure().stderr(
+        predicate::str::contains("check-lua script error in example.py:looping at line 1").and(
+            predicate::str::contains("the script exceeded the timeout of 1s"),
+        ),
+    );
+}
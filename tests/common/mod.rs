@@ -1,4 +1,20 @@
 //! Shared helpers for integration tests.
+#![allow(dead_code)]
+
+use std::fs;
+
+/// Creates a repository root (with a `.git` folder) containing `files`, given as pairs of paths
+/// relative to the root and contents.
+pub fn repository(files: &[(&str, &str)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    for (path, contents) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    dir
+}
 
 /// Runs the `blockwatch` binary with `args`, giving the child process a real
 /// pseudo-terminal on stdin so that `stdin().is_terminal()` returns true (i.e. the
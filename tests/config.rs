@@ -0,0 +1,171 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::{PredicateBooleanExt, predicate};
+use std::path::Path;
+
+mod common;
+
+const UNSORTED_BLOCK: &str = r#"# <block keep-sorted>
+"b",
+"a",
+# </block>
+"#;
+
+fn run(dir: &Path, args: &[&str]) -> std::process::Output {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir).args(args);
+    cmd.output().expect("Failed to get command output")
+}
+
+#[test]
+fn configured_globs_are_checked_without_cli_globs() {
+    let dir = common::repository(&[
+        ("blockwatch.toml", r#"globs = ["**/*.py"]"#),
+        ("a.py", UNSORTED_BLOCK),
+    ]);
+
+    let output = run(dir.path(), &[]);
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("keep-sorted"));
+}
+
+#[test]
+fn configured_disabled_validator_is_not_run() {
+    let dir = common::repository(&[
+        (
+            "blockwatch.toml",
+            r#"
+globs = ["**/*.py"]
+disable = ["keep-sorted"]
+"#,
+        ),
+        ("a.py", UNSORTED_BLOCK),
+    ]);
+
+    let output = run(dir.path(), &[]);
+
+    output.assert().success();
+}
+
+#[test]
+fn cli_enabled_validators_win_over_configured_disabled_validators() {
+    let dir = common::repository(&[
+        (
+            "blockwatch.toml",
+            r#"
+globs = ["**/*.py"]
+disable = ["keep-sorted"]
+"#,
+        ),
+        ("a.py", UNSORTED_BLOCK),
+    ]);
+
+    let output = run(dir.path(), &["-e", "keep-sorted"]);
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("keep-sorted"));
+}
+
+#[test]
+fn configured_ignore_globs_are_combined_with_cli_ignore_globs() {
+    let dir = common::repository(&[
+        (
+            "blockwatch.toml",
+            r#"
+globs = ["**/*.py"]
+ignore = ["a.py"]
+"#,
+        ),
+        ("a.py", UNSORTED_BLOCK),
+        ("b.py", UNSORTED_BLOCK),
+    ]);
+
+    let output = run(dir.path(), &["--ignore", "b.py"]);
+
+    output.assert().success();
+}
+
+#[test]
+fn override_disables_validator_for_matching_paths_only() {
+    let dir = common::repository(&[
+        (
+            "blockwatch.toml",
+            r#"
+globs = ["**/*.py"]
+
+[[overrides]]
+paths = ["vendor/**"]
+disable = ["keep-sorted"]
+"#,
+        ),
+        ("vendor/a.py", UNSORTED_BLOCK),
+        ("src/b.py", UNSORTED_BLOCK),
+    ]);
+
+    let output = run(dir.path(), &[]);
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("src/b.py"))
+        .stderr(predicate::str::contains("vendor/a.py").not());
+}
+
+#[test]
+fn override_remaps_extensions_for_matching_paths_only() {
+    let dir = common::repository(&[
+        (
+            "blockwatch.toml",
+            r#"
+globs = ["**/*.pyx"]
+
+[[overrides]]
+paths = ["third_party/**"]
+extensions = { pyx = "py" }
+"#,
+        ),
+        ("third_party/a.pyx", UNSORTED_BLOCK),
+        ("src/b.pyx", UNSORTED_BLOCK),
+    ]);
+
+    let output = run(dir.path(), &[]);
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("third_party/a.pyx"))
+        .stderr(predicate::str::contains("src/b.pyx").not());
+}
+
+#[test]
+fn unknown_config_key_fails_with_precise_error() {
+    let dir = common::repository(&[("blockwatch.toml", "globs = []\nignored = [\"a.py\"]\n")]);
+
+    let output = run(dir.path(), &[]);
+
+    output
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid blockwatch.toml"))
+        .stderr(predicate::str::contains("unknown field `ignored`"));
+}
+
+#[test]
+fn unsupported_configured_extension_mapping_fails() {
+    let dir = common::repository(&[("blockwatch.toml", "extensions = { pyx = \"nope\" }")]);
+
+    let output = run(dir.path(), &[]);
+
+    output.assert().failure().stderr(predicate::str::contains(
+        "Unsupported extension mapping in blockwatch.toml: pyx=nope",
+    ));
+}
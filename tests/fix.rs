@@ -0,0 +1,129 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use std::fs;
+
+mod common;
+
+const UNSORTED: &str = r#"items = [
+    # <block keep-sorted keep-unique>
+    "b",
+    "a",
+    "b",
+    "c"
+    # </block>
+]
+"#;
+
+const FIXED: &str = r#"items = [
+    # <block keep-sorted keep-unique>
+    "a",
+    "b",
+    "c"
+    # </block>
+]
+"#;
+
+/// The files of the repository the tests run in: `a.py` and `b.py` with `UNSORTED`.
+const FILES: &[(&str, &str)] = &[("a.py", UNSORTED), ("b.py", UNSORTED)];
+
+#[test]
+fn fix_subcommand_rewrites_files_in_place() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("fix").arg("a.py");
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Fixed a.py"));
+    assert_eq!(fs::read_to_string(dir.path().join("a.py")).unwrap(), FIXED);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.py")).unwrap(),
+        UNSORTED
+    );
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("a.py");
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
+
+#[test]
+fn fix_subcommand_with_dry_run_prints_unified_diff_without_writing() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .args(["fix", "--dry-run", "a.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.assert().success().stdout(
+        r#"--- a/a.py
++++ b/a.py
+@@ -1,6 +1,5 @@
+ items = [
+     # <block keep-sorted keep-unique>
+-    "b",
+     "a",
+     "b",
+     "c"
+"#,
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        UNSORTED
+    );
+}
+
+#[test]
+fn fix_subcommand_with_diff_fixes_only_modified_blocks() {
+    let dir = common::repository(FILES);
+    let diff = r#"diff --git a/b.py b/b.py
+--- a/b.py
++++ b/b.py
+@@ -3,1 +3,1 @@
+-    "x",
++    "b",
+"#;
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .args(["fix", "--diff"])
+        .write_stdin(diff);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.assert().success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        UNSORTED
+    );
+    assert_eq!(fs::read_to_string(dir.path().join("b.py")).unwrap(), FIXED);
+}
+
+#[test]
+fn fix_subcommand_does_not_fix_disabled_validators() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .args(["fix", "-d", "keep-sorted", "a.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.assert().success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.py")).unwrap(),
+        r#"items = [
+    # <block keep-sorted keep-unique>
+    "b",
+    "a",
+    "c"
+    # </block>
+]
+"#
+    );
+}
@@ -0,0 +1,95 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use serde_json::{Value, json};
+
+#[test]
+fn format_sarif_writes_sarif_log_with_violations_as_results() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args(["--format", "sarif", "tests/testdata/paths/invalid.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.clone().assert().failure().code(1);
+    let log: Value = serde_json::from_slice(&output.stderr).expect("Failed to parse SARIF log");
+    assert_eq!(log["version"], "2.1.0");
+    let run = &log["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "blockwatch");
+    let results = run["results"]
+        .as_array()
+        .expect("results should be an array");
+    assert_eq!(results.len(), 1);
+    let result = &results[0];
+    assert_eq!(result["ruleId"], "keep-sorted");
+    assert_eq!(result["level"], "error");
+    assert_eq!(
+        run["tool"]["driver"]["rules"][result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+        "keep-sorted"
+    );
+    assert_eq!(
+        result["locations"][0]["physicalLocation"],
+        json!({
+            "artifactLocation": {
+                "uri": "tests/testdata/paths/invalid.py",
+                "uriBaseId": "%SRCROOT%"
+            },
+            "region": {"startLine": 3, "startColumn": 1, "endLine": 3, "endColumn": 6}
+        })
+    );
+}
+
+#[test]
+fn format_sarif_writes_sarif_log_without_violations() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args(["--format", "sarif", "tests/testdata/paths/valid.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.clone().assert().success();
+    let log: Value = serde_json::from_slice(&output.stderr).expect("Failed to parse SARIF log");
+    assert_eq!(log["version"], "2.1.0");
+    assert_eq!(log["runs"][0]["results"], json!([]));
+}
+
+#[test]
+fn format_json_writes_diagnostics_keyed_by_file_path() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args(["--format", "json", "tests/testdata/paths/invalid.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.clone().assert().failure().code(1);
+    let report: Value = serde_json::from_slice(&output.stderr).expect("Failed to parse JSON");
+    assert_eq!(
+        report["tests/testdata/paths/invalid.py"][0]["code"],
+        "keep-sorted"
+    );
+}
+
+#[test]
+fn unknown_format_fails() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args(["--format", "xml", "tests/testdata/paths/invalid.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.assert().failure().code(2);
+}
+
+#[test]
+fn format_text_writes_source_snippet_with_carets() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args(["--format", "text", "tests/testdata/paths/invalid.py"]);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output.assert().failure().code(1).stderr(
+        "error[keep-sorted]: Block tests/testdata/paths/invalid.py:(unnamed) defined at line 1 has an out-of-order line 3 (asc)
+ --> tests/testdata/paths/invalid.py:3:1
+  |
+3 | a = 1
+  | ^^^^^
+
+blockwatch: 1 error
+",
+    );
+}
@@ -0,0 +1,130 @@
+#![cfg(unix)]
+
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use std::fs;
+
+mod common;
+
+const GENERATE_CMD_ENV_VAR: &str = "BLOCKWATCH_GENERATE_CMD_MODE";
+
+const OUTDATED: &str = r#"# Files
+
+<!-- <block generate-cmd="ls files"> -->
+```
+a.txt
+```
+<!-- </block> -->
+"#;
+
+const GENERATED: &str = r#"# Files
+
+<!-- <block generate-cmd="ls files"> -->
+```
+a.txt
+b.txt
+```
+<!-- </block> -->
+"#;
+
+/// The files of the repository the tests run in: `README.md` with `OUTDATED` and the files it
+/// lists.
+const FILES: &[(&str, &str)] = &[
+    ("files/a.txt", ""),
+    ("files/b.txt", ""),
+    ("README.md", OUTDATED),
+];
+
+#[test]
+fn outdated_block_fails() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env(GENERATE_CMD_ENV_VAR, "enabled")
+        .arg("README.md");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Block README.md:(unnamed) at line 3 differs from the output of \\\"ls files\\\"",
+        ));
+}
+
+#[test]
+fn commands_run_in_repository_root() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path().join("files"))
+        .env(GENERATE_CMD_ENV_VAR, "enabled")
+        .arg("fix");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Fixed README.md"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        GENERATED
+    );
+}
+
+#[test]
+fn fix_subcommand_regenerates_block() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env(GENERATE_CMD_ENV_VAR, "enabled")
+        .args(["fix", "README.md"]);
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Fixed README.md"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        GENERATED
+    );
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env(GENERATE_CMD_ENV_VAR, "enabled")
+        .arg("README.md");
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
+
+#[test]
+fn commands_are_not_run_unless_enabled() {
+    let dir = common::repository(FILES);
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env_remove(GENERATE_CMD_ENV_VAR)
+        .args(["fix", "README.md"]);
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        OUTDATED
+    );
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .env_remove(GENERATE_CMD_ENV_VAR)
+        .arg("README.md");
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("running commands is disabled"));
+}
@@ -0,0 +1,143 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+const SORTED: &str = "# <block keep-sorted>\na\nb\n# </block>\n";
+const UNSORTED: &str = "# <block keep-sorted>\na\nc\nb\n# </block>\n";
+const SORTED_WITH_C: &str = "# <block keep-sorted>\na\nb\nc\n# </block>\n";
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// Creates a git repository with `a.py` committed as `SORTED`.
+fn repository() -> tempfile::TempDir {
+    let dir = common::repository(&[("a.py", SORTED)]);
+    git(dir.path(), &["init", "-q"]);
+    git(dir.path(), &["add", "-A"]);
+    git(dir.path(), &["commit", "-q", "-m", "first"]);
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> std::process::Output {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir).args(args);
+    cmd.output().expect("Failed to get command output")
+}
+
+#[test]
+fn staged_validates_files_as_staged() {
+    let dir = repository();
+    fs::write(dir.path().join("a.py"), UNSORTED).unwrap();
+    git(dir.path(), &["add", "a.py"]);
+    // The working tree is fixed but the index is not.
+    fs::write(dir.path().join("a.py"), SORTED_WITH_C).unwrap();
+
+    let output = run(dir.path(), &["--staged"]);
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("keep-sorted"));
+}
+
+#[test]
+fn staged_ignores_unstaged_changes() {
+    let dir = repository();
+    fs::write(dir.path().join("a.py"), SORTED_WITH_C).unwrap();
+    git(dir.path(), &["add", "a.py"]);
+    fs::write(dir.path().join("a.py"), UNSORTED).unwrap();
+
+    let output = run(dir.path(), &["--staged"]);
+
+    output.assert().success();
+}
+
+#[test]
+fn since_validates_working_tree_changes() {
+    let dir = repository();
+    fs::write(dir.path().join("a.py"), UNSORTED).unwrap();
+
+    let output = run(dir.path(), &["--since", "HEAD"]);
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("keep-sorted"));
+}
+
+#[test]
+fn range_validates_committed_changes() {
+    let dir = repository();
+    fs::write(dir.path().join("a.py"), UNSORTED).unwrap();
+    git(dir.path(), &["commit", "-q", "-a", "-m", "second"]);
+    fs::write(dir.path().join("a.py"), SORTED_WITH_C).unwrap();
+
+    run(dir.path(), &["--range", "HEAD~1..HEAD"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("keep-sorted"));
+    run(dir.path(), &["--range", "HEAD~1..HEAD~1"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn range_without_two_revisions_fails() {
+    let dir = repository();
+
+    let output = run(dir.path(), &["--range", "HEAD"]);
+
+    output.assert().failure().stderr(predicate::str::contains(
+        "Invalid range: HEAD. Expected A..B or A...B, use --since HEAD for the working tree",
+    ));
+}
+
+#[test]
+fn git_flags_are_mutually_exclusive() {
+    let dir = repository();
+
+    let output = run(dir.path(), &["--staged", "--since", "HEAD"]);
+
+    output
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn git_flags_with_subcommand_fail() {
+    let dir = repository();
+
+    let output = run(dir.path(), &["--staged", "list"]);
+
+    output.assert().failure().stderr(predicate::str::contains(
+        "--staged, --since and --range flags can't be used with a subcommand",
+    ));
+}
+
+#[test]
+fn unknown_revision_fails() {
+    let dir = repository();
+
+    let output = run(dir.path(), &["--since", "nope"]);
+
+    output
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("\"git diff"));
+}
@@ -0,0 +1,84 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use serde_json::json;
+
+mod common;
+
+#[test]
+fn diff_with_out_of_sync_block_fails() {
+    let dir = common::repository(&[
+        (
+            "colors.py",
+            "# <block keep-in-sync=\"colors.md:colors\" keep-in-sync-normalize=\"trim\">\n    'blue',\n    'red',\n# </block>\n",
+        ),
+        (
+            "colors.md",
+            "<!-- <block name=\"colors\"> -->\n'blue',\n'green',\n<!-- </block> -->\n",
+        ),
+    ]);
+    let diff_content = r#"diff --git a/colors.py b/colors.py
+--- a/colors.py
++++ b/colors.py
+@@ -3 +3 @@
+-    'green',
++    'red',
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "colors.py": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 3},
+                        "end": {"line": 1, "character": 71}
+                      },
+                      "code": "keep-in-sync",
+                      "message": "Block colors.py:(unnamed) at line 1 is out of sync with colors.md:colors",
+                      "severity": 1,
+                      "data": {
+                        "in_sync_with_file_path": "colors.md",
+                        "in_sync_with_block_name": "colors",
+                        "diff": "--- colors.md:colors\n+++ colors.py:(unnamed)\n@@ -1,2 +1,2 @@\n 'blue',\n-'green',\n+'red',\n"
+                      }
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn in_sync_blocks_succeed() {
+    let dir = common::repository(&[
+        (
+            "colors.py",
+            "# <block keep-in-sync=\"colors.md:colors\">\n'blue',\n# </block>\n",
+        ),
+        (
+            "colors.md",
+            "<!-- <block name=\"colors\"> -->\n'blue',\n<!-- </block> -->\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("**");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
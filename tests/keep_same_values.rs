@@ -0,0 +1,82 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use serde_json::json;
+
+mod common;
+
+const COLORS_MD: &str = "<!-- <block name=\"colors\" keep-same-values-pattern=\"^- (?P<value>\\w+)\"> -->\n- Blue\n- Green\n<!-- </block> -->\n";
+
+#[test]
+fn diff_with_missing_values_fails() {
+    let dir = common::repository(&[
+        (
+            "colors.py",
+            "# <block keep-same-values=\"colors.md:colors\" keep-same-values-pattern=\"^(?P<value>\\w+) =\">\nBlue = 1\nRed = 2\n# </block>\n",
+        ),
+        ("colors.md", COLORS_MD),
+    ]);
+    let diff_content = r#"diff --git a/colors.py b/colors.py
+--- a/colors.py
++++ b/colors.py
+@@ -3 +3 @@
+-Green = 2
++Red = 2
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "colors.py": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 3},
+                        "end": {"line": 1, "character": 90}
+                      },
+                      "code": "keep-same-values",
+                      "message": "Block colors.py:(unnamed) at line 1 has different values than colors.md:colors (missing here: Green; missing in colors.md:colors: Red)",
+                      "severity": 1,
+                      "data": {
+                        "other_block_file_path": "colors.md",
+                        "other_block_name": "colors",
+                        "missing_in_block": ["Green"],
+                        "missing_in_other_block": ["Red"],
+                        "is_order_different": false
+                      }
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn same_values_succeed() {
+    let dir = common::repository(&[
+        (
+            "colors.py",
+            "# <block keep-same-values=\"colors.md:colors\" keep-same-values-pattern=\"^(?P<value>\\w+) =\">\nGreen = 1\nBlue = 2\n# </block>\n",
+        ),
+        ("colors.md", COLORS_MD),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("**");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
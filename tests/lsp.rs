@@ -0,0 +1,86 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use serde_json::{Value, json};
+
+mod common;
+
+/// Frames the JSON-RPC `messages` with the `Content-Length` headers of the LSP base protocol.
+fn frame(messages: &[Value]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let body = message.to_string();
+            format!("Content-Length: {}\r\n\r\n{body}", body.len())
+        })
+        .collect()
+}
+
+/// Parses the JSON-RPC messages written by the server.
+fn unframe(output: &str) -> Vec<Value> {
+    output
+        .split("Content-Length: ")
+        .filter(|chunk| !chunk.is_empty())
+        .map(|chunk| {
+            let (_, body) = chunk.split_once("\r\n\r\n").unwrap();
+            serde_json::from_str(body).unwrap()
+        })
+        .collect()
+}
+
+/// Runs the language server in `dir` with `messages` sent between the handshake and the shutdown.
+fn run_session(dir: &std::path::Path, messages: Vec<Value>) -> Vec<Value> {
+    let root_uri = url::Url::from_directory_path(dir).unwrap().to_string();
+    let mut session = vec![
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}, "rootUri": root_uri}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+    ];
+    session.extend(messages);
+    session.push(json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}));
+    session.push(json!({"jsonrpc": "2.0", "method": "exit"}));
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir).arg("lsp").write_stdin(frame(&session));
+    let output = cmd.output().expect("Failed to get command output");
+    output.clone().assert().success();
+    unframe(&String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn lsp_subcommand_publishes_diagnostics_for_opened_documents() {
+    let text = "# <block keep-sorted>\nb\na\n# </block>\n";
+    let dir = common::repository(&[("a.py", text)]);
+    let uri = url::Url::from_file_path(dir.path().canonicalize().unwrap().join("a.py"))
+        .unwrap()
+        .to_string();
+
+    let messages = run_session(
+        dir.path(),
+        vec![json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": uri, "languageId": "python", "version": 1, "text": text}}
+        })],
+    );
+
+    let diagnostics = messages
+        .iter()
+        .find(|message| message["method"] == "textDocument/publishDiagnostics")
+        .expect("No diagnostics were published");
+    assert_eq!(diagnostics["params"]["uri"], uri);
+    assert_eq!(
+        diagnostics["params"]["diagnostics"],
+        json!([{
+            "range": {"start": {"line": 2, "character": 0}, "end": {"line": 2, "character": 1}},
+            "severity": 1,
+            "code": "keep-sorted",
+            "source": "blockwatch",
+            "message": "Block a.py:(unnamed) defined at line 1 has an out-of-order line 3 (asc)",
+            "data": {"order_by": "asc"}
+        }])
+    );
+    assert!(
+        messages
+            .iter()
+            .any(|message| message["id"] == 2 && message["result"].is_null())
+    );
+}
@@ -0,0 +1,131 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use serde_json::json;
+use std::fs;
+
+mod common;
+
+#[test]
+fn mirrored_file_succeeds() {
+    let dir = common::repository(&[
+        ("config.toml", "[server]\nport = 8080\n"),
+        (
+            "README.md",
+            "<!-- <block mirrors-file=\"config.toml\"> -->\n```toml\n[server]\nport = 8080\n```\n<!-- </block> -->\n",
+        ),
+    ]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("**");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
+
+#[test]
+fn diff_with_outdated_mirror_fails() {
+    let dir = common::repository(&[
+        ("config.toml", "[server]\nport = 8080\n"),
+        (
+            "README.md",
+            "<!-- <block mirrors-file=\"config.toml\"> -->\n[server]\nport = 80\n<!-- </block> -->\n",
+        ),
+    ]);
+    let diff_content = r#"diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -3 +3 @@
+-port = 8080
++port = 80
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "README.md": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 6},
+                        "end": {"line": 1, "character": 39}
+                      },
+                      "code": "mirrors-file",
+                      "message": "Block README.md:(unnamed) at line 1 differs from config.toml",
+                      "severity": 1,
+                      "data": {
+                        "mirrored_file_path": "config.toml",
+                        "diff": "--- config.toml\n+++ README.md:(unnamed)\n@@ -1,2 +1,2 @@\n [server]\n-port = 8080\n+port = 80\n"
+                      }
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn diff_changing_only_mirrored_file_fails() {
+    let dir = common::repository(&[
+        ("config.toml", "[server]\nport = 80\n"),
+        (
+            "README.md",
+            "<!-- <block mirrors-file=\"config.toml\"> -->\n[server]\nport = 8080\n<!-- </block> -->\n",
+        ),
+    ]);
+    let diff_content = r#"diff --git a/config.toml b/config.toml
+--- a/config.toml
++++ b/config.toml
+@@ -2 +2 @@
+-port = 8080
++port = 80
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Block README.md:(unnamed) at line 1 differs from config.toml",
+        ));
+}
+
+#[test]
+fn mirrored_file_outside_repository_fails() {
+    let outside = tempfile::tempdir().unwrap();
+    let secret = outside.path().join("secret.txt");
+    fs::write(&secret, "secret\n").unwrap();
+    let dir = common::repository(&[(
+        "README.md",
+        &format!(
+            "<!-- <block mirrors-file=\"{}\"> -->\nsecret\n<!-- </block> -->\n",
+            secret.display()
+        ),
+    )]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("**");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("outside the repository root"));
+}
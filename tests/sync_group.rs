@@ -0,0 +1,96 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use serde_json::json;
+
+mod common;
+
+#[test]
+fn diff_with_missed_group_members_fails() {
+    let dir = common::repository(&[
+        (
+            "colors.py",
+            "# <block sync-group=\"colors\">\nBLUE = 1\n# </block>\n",
+        ),
+        (
+            "colors.md",
+            "<!-- <block name=\"colors\" sync-group=\"colors\"> -->\n- RED\n<!-- </block> -->\n",
+        ),
+    ]);
+    let diff_content = r#"diff --git a/colors.py b/colors.py
+--- a/colors.py
++++ b/colors.py
+@@ -2 +2 @@
+-RED = 1
++BLUE = 1
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "colors.py": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 3},
+                        "end": {"line": 1, "character": 29}
+                      },
+                      "code": "sync-group",
+                      "message": "Block colors.py:(unnamed) at line 1 is modified, but the other members of sync group \"colors\" are not: colors.md:colors at line 1",
+                      "severity": 1,
+                      "data": {
+                        "group": "colors",
+                        "missed_members": [{"file_path": "colors.md", "name": "colors", "line": 1}]
+                      }
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn diff_with_all_group_members_modified_succeeds() {
+    let dir = common::repository(&[
+        (
+            "colors.py",
+            "# <block sync-group=\"colors\">\nBLUE = 1\n# </block>\n",
+        ),
+        (
+            "colors.md",
+            "<!-- <block sync-group=\"colors\"> -->\n- BLUE\n<!-- </block> -->\n",
+        ),
+    ]);
+    let diff_content = r#"diff --git a/colors.py b/colors.py
+--- a/colors.py
++++ b/colors.py
+@@ -2 +2 @@
+-RED = 1
++BLUE = 1
+diff --git a/colors.md b/colors.md
+--- a/colors.md
++++ b/colors.md
+@@ -2 +2 @@
+-- RED
++- BLUE
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
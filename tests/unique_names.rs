@@ -0,0 +1,96 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo_bin_cmd;
+use predicates::prelude::predicate;
+use serde_json::json;
+
+mod common;
+
+#[test]
+fn diff_adding_duplicate_name_fails() {
+    let dir = common::repository(&[(
+        "a.py",
+        "# <block name=\"foo\">\nprint(\"a\")\n# </block>\n# <block name=\"foo\">\nprint(\"b\")\n# </block>\n",
+    )]);
+    let diff_content = r#"diff --git a/a.py b/a.py
+--- a/a.py
++++ b/a.py
+@@ -3,0 +4,3 @@
++# <block name="foo">
++print("b")
++# </block>
+"#;
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).write_stdin(diff_content);
+
+    let output = cmd.output().expect("Failed to get command output");
+
+    output
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::function(|output: &str| {
+            let output_json: serde_json::Value = serde_json::from_str(output).unwrap();
+            assert_eq!(
+                output_json,
+                json!({
+                  "a.py": [
+                    {
+                      "range": {
+                        "start": {"line": 1, "character": 3},
+                        "end": {"line": 1, "character": 20}
+                      },
+                      "code": "unique-names",
+                      "message": "Block a.py:foo at line 1 has the same name as the block at line 4",
+                      "severity": 1,
+                      "data": {"name": "foo", "duplicate_lines": [4]}
+                    },
+                    {
+                      "range": {
+                        "start": {"line": 4, "character": 3},
+                        "end": {"line": 4, "character": 20}
+                      },
+                      "code": "unique-names",
+                      "message": "Block a.py:foo at line 4 has the same name as the block at line 1",
+                      "severity": 1,
+                      "data": {"name": "foo", "duplicate_lines": [1]}
+                    }
+                  ]
+                })
+            );
+            true
+        }));
+}
+
+#[test]
+fn unique_names_succeed() {
+    let dir = common::repository(&[(
+        "a.py",
+        "# <block name=\"foo\">\nprint(\"a\")\n# </block>\n# <block name=\"bar\">\nprint(\"b\")\n# </block>\n",
+    )]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path()).arg("a.py");
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}
+
+#[test]
+fn disabled_unique_names_succeeds() {
+    let dir = common::repository(&[(
+        "a.py",
+        "# <block name=\"foo\">\n# </block>\n# <block name=\"foo\">\n# </block>\n",
+    )]);
+
+    let mut cmd = cargo_bin_cmd!();
+    cmd.current_dir(dir.path())
+        .args(["a.py", "-d", "unique-names"]);
+
+    cmd.output()
+        .expect("Failed to get command output")
+        .assert()
+        .success();
+}